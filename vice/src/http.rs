@@ -1,7 +1,6 @@
 //! http protocol
-use bytes::Bytes;
-use http_body_util::Full;
-
+pub mod body;
+pub mod cookie;
 pub mod from_request;
 pub mod into_response;
 
@@ -12,8 +11,8 @@ pub use http::response;
 pub use http::header;
 pub use http::status;
 
-pub use hyper::body::Incoming as ReqBody;
-
+#[doc(inline)]
+pub use body::Body;
 #[doc(inline)]
 pub use from_request::{FromRequest, FromRequestParts};
 #[doc(inline)]
@@ -23,5 +22,7 @@ pub use into_response::{IntoResponse, IntoResponseParts};
 pub type Request<T = ReqBody> = hyper::http::Request<T>;
 /// Represents an HTTP response
 pub type Response<T = ResBody> = hyper::http::Response<T>;
+/// Represents a request body
+pub type ReqBody = Body;
 /// Represents a response body
-pub type ResBody = Full<Bytes>;
+pub type ResBody = Body;
@@ -0,0 +1,203 @@
+//! request and response [`Body`]
+use super::{IntoResponse, Response};
+use bytes::Bytes;
+use http::StatusCode;
+use http_body_util::{combinators::UnsyncBoxBody, BodyExt, Full};
+use hyper::body::{Body as HttpBody, Frame, Incoming, SizeHint};
+use std::{
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn from_full() {
+        let body = Body::from(Full::new(Bytes::from_static(b"vice")));
+        assert_eq!(body.as_bytes(), Some(&b"vice"[..]));
+        assert_eq!(Body::from(Full::new(Bytes::new())).as_bytes(), Some(&b""[..]));
+    }
+}
+
+/// boxed error of streaming body
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// error when reading request body
+///
+/// respond with `413 Payload Too Large` if caused by [`LengthLimitError`],
+/// otherwise `400 Bad Request`
+#[derive(thiserror::Error, Debug)]
+#[error(transparent)]
+pub struct BodyError(BoxError);
+
+impl BodyError {
+    /// create new `BodyError`
+    pub fn new(err: impl Into<BoxError>) -> Self {
+        Self(err.into())
+    }
+
+    /// returns `true` if body exceeds its size limit
+    pub fn is_length_limit(&self) -> bool {
+        self.0.is::<LengthLimitError>()
+    }
+}
+
+impl IntoResponse for BodyError {
+    fn into_response(self) -> Response {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(error = %self.0, "failed to read body");
+        let status = match self.is_length_limit() {
+            true => StatusCode::PAYLOAD_TOO_LARGE,
+            false => StatusCode::BAD_REQUEST,
+        };
+        (status, self.0.to_string()).into_response()
+    }
+}
+
+/// error returned when body exceeds its size limit
+#[derive(thiserror::Error, Debug)]
+#[error("body exceeds the limit of {limit} bytes")]
+pub struct LengthLimitError {
+    limit: u64,
+}
+
+impl LengthLimitError {
+    /// create new `LengthLimitError`
+    pub fn new(limit: u64) -> Self {
+        Self { limit }
+    }
+
+    /// returns the exceeded limit
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+}
+
+/// request and response body
+///
+/// the body is either fully buffered bytes, or a streaming body created by [`Body::new`]
+pub struct Body {
+    kind: Kind,
+}
+
+enum Kind {
+    Full(Option<Bytes>),
+    Incoming(Incoming),
+    Stream(UnsyncBoxBody<Bytes, BoxError>),
+}
+
+impl Body {
+    /// create empty body
+    pub fn empty() -> Self {
+        Self { kind: Kind::Full(None) }
+    }
+
+    /// create streaming body from another body implementation
+    pub fn new<B>(body: B) -> Self
+    where
+        B: HttpBody<Data = Bytes> + Send + 'static,
+        B::Error: Into<BoxError>,
+    {
+        Self { kind: Kind::Stream(body.map_err(Into::into).boxed_unsync()) }
+    }
+
+    /// returns the buffered bytes, or `None` if body is streaming
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match &self.kind {
+            Kind::Full(bytes) => Some(bytes.as_deref().unwrap_or_default()),
+            Kind::Incoming(_) | Kind::Stream(_) => None,
+        }
+    }
+}
+
+impl Default for Body {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl fmt::Debug for Body {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            Kind::Full(bytes) => f.debug_tuple("Full").field(bytes).finish(),
+            Kind::Incoming(_) => f.write_str("Incoming"),
+            Kind::Stream(_) => f.write_str("Stream"),
+        }
+    }
+}
+
+impl From<Bytes> for Body {
+    fn from(value: Bytes) -> Self {
+        Self { kind: Kind::Full(Some(value)) }
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(value: Vec<u8>) -> Self {
+        Bytes::from(value).into()
+    }
+}
+
+impl From<String> for Body {
+    fn from(value: String) -> Self {
+        Bytes::from(value).into()
+    }
+}
+
+impl From<&'static str> for Body {
+    fn from(value: &'static str) -> Self {
+        Bytes::from_static(value.as_bytes()).into()
+    }
+}
+
+impl From<Incoming> for Body {
+    fn from(value: Incoming) -> Self {
+        Self { kind: Kind::Incoming(value) }
+    }
+}
+
+impl From<Full<Bytes>> for Body {
+    fn from(mut value: Full<Bytes>) -> Self {
+        // `Full` is always ready and yield at most one data frame
+        let mut cx = Context::from_waker(std::task::Waker::noop());
+        match Pin::new(&mut value).poll_frame(&mut cx) {
+            Poll::Ready(Some(Ok(frame))) => frame.into_data().map_or_else(|_|Self::empty(), Self::from),
+            _ => Self::empty(),
+        }
+    }
+}
+
+impl HttpBody for Body {
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        match &mut self.get_mut().kind {
+            Kind::Full(bytes) => Poll::Ready(bytes.take().filter(|e|!e.is_empty()).map(|e|Ok(Frame::data(e)))),
+            Kind::Incoming(body) => Pin::new(body).poll_frame(cx).map_err(Into::into),
+            Kind::Stream(body) => Pin::new(body).poll_frame(cx),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match &self.kind {
+            Kind::Full(bytes) => bytes.as_ref().is_none_or(Bytes::is_empty),
+            Kind::Incoming(body) => body.is_end_stream(),
+            Kind::Stream(body) => body.is_end_stream(),
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match &self.kind {
+            Kind::Full(bytes) => SizeHint::with_exact(bytes.as_ref().map_or(0, |e|e.len() as u64)),
+            Kind::Incoming(body) => body.size_hint(),
+            Kind::Stream(body) => body.size_hint(),
+        }
+    }
+}
@@ -0,0 +1,479 @@
+//! http cookie
+//!
+//! [`CookieJar`] is an extractor that parse `Cookie` request headers, cookies
+//! added or removed from the jar is sent back as `Set-Cookie` headers when the
+//! jar is returned as response parts
+//!
+//! with `secure-cookie` feature, `SignedCookieJar` and `PrivateCookieJar`
+//! protect cookie value using keys provided by the `Keys` layer
+//!
+//! # Example
+//!
+//! ```no_run
+//! use vice::{
+//!     http::cookie::{Cookie, CookieJar},
+//!     router::{Router, get},
+//! };
+//!
+//! async fn handle(jar: CookieJar) -> (CookieJar, String) {
+//!     let visit = jar.get("visit").and_then(|e|e.value().parse().ok()).unwrap_or(0u64) + 1;
+//!     let jar = jar.insert(Cookie::new("visit", visit.to_string()).http_only(true));
+//!     (jar, format!("visit {visit}"))
+//! }
+//!
+//! fn main() -> std::io::Result<()> {
+//!     let route = Router::new().route("/", get(handle));
+//!     vice::listen("0.0.0.0:3000", route)
+//! }
+//! ```
+use super::{FromRequestParts, IntoResponseParts};
+use crate::util::time::UtcTime;
+use http::{header, request, response, HeaderMap, HeaderValue};
+use std::{
+    convert::Infallible,
+    fmt,
+    future::{ready, Ready},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::{IntoResponse, Response};
+
+    #[test]
+    fn jar() {
+        let mut headers = HeaderMap::new();
+        headers.append(header::COOKIE, HeaderValue::from_static("a=1; b=\"2\"; bad; c=x y"));
+        headers.append(header::COOKIE, HeaderValue::from_static("d=4"));
+        let jar = CookieJar::from_headers(&headers);
+        assert_eq!(jar.iter().map(|e|(e.name(), e.value())).collect::<Vec<_>>(), [("a", "1"), ("b", "2"), ("d", "4")]);
+
+        let jar = jar
+            .insert(Cookie::new("a", "5").path("/").max_age(Duration::from_secs(60)).http_only(true).same_site(SameSite::Lax))
+            .remove("b")
+            .insert(Cookie::new("e", "6").same_site(SameSite::None).partitioned(true));
+        assert_eq!(jar.get("a").unwrap().value(), "5");
+        assert!(jar.get("b").is_none());
+
+        let res: Response = (jar, ()).into_response();
+        let set = res.headers().get_all(header::SET_COOKIE).iter().map(|e|e.to_str().unwrap()).collect::<Vec<_>>();
+        assert_eq!(set, [
+            "a=5; Path=/; Max-Age=60; HttpOnly; SameSite=Lax",
+            "b=; Max-Age=0; Expires=Thu, 01 Jan 1970 00:00:00 GMT",
+            "e=6; Secure; SameSite=None; Partitioned",
+        ]);
+    }
+
+    #[test]
+    fn validate() {
+        assert_eq!(Cookie::new("a", "1").path("/app").domain("example.com").validate(), Ok(()));
+        assert_eq!(Cookie::new("a b", "1").validate(), Err(InvalidCookie::Name));
+        assert_eq!(Cookie::new("", "1").validate(), Err(InvalidCookie::Name));
+        assert_eq!(Cookie::new("a", "1; Domain=evil.com").validate(), Err(InvalidCookie::Value));
+        assert_eq!(Cookie::new("a", "1").path("/; SameSite=None").validate(), Err(InvalidCookie::Path));
+        assert_eq!(Cookie::new("a", "1").domain("a.com\r\nX: y").validate(), Err(InvalidCookie::Domain));
+        assert_eq!(Cookie::new("a", "x y").to_string(), "a=x y");
+
+        // invalid cookie is skipped, the rest is still sent
+        let jar = CookieJar::new().insert(Cookie::new("a", "x y")).insert(Cookie::new("b", "1"));
+        let res: Response = (jar, ()).into_response();
+        let set = res.headers().get_all(header::SET_COOKIE).iter().collect::<Vec<_>>();
+        assert_eq!(set, ["b=1"]);
+    }
+}
+
+#[cfg(feature = "secure-cookie")]
+pub mod key;
+#[cfg(feature = "secure-cookie")]
+pub mod private;
+#[cfg(feature = "secure-cookie")]
+pub mod signed;
+
+#[cfg(feature = "secure-cookie")]
+#[doc(inline)]
+pub use key::{Key, Keys, MissingKeys};
+#[cfg(feature = "secure-cookie")]
+#[doc(inline)]
+pub use private::PrivateCookieJar;
+#[cfg(feature = "secure-cookie")]
+#[doc(inline)]
+pub use signed::SignedCookieJar;
+
+/// `SameSite` cookie attribute
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+impl SameSite {
+    /// attribute value, `Strict`, `Lax` or `None`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Strict => "Strict",
+            Self::Lax => "Lax",
+            Self::None => "None",
+        }
+    }
+}
+
+/// http cookie
+///
+/// the [`Display`] implementation format the cookie as `Set-Cookie` value,
+/// it is written as is, use [`Cookie::validate`] to check it first
+///
+/// [`Display`]: fmt::Display
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    path: Option<String>,
+    domain: Option<String>,
+    max_age: Option<Duration>,
+    expires: Option<SystemTime>,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>,
+    partitioned: bool,
+}
+
+impl Cookie {
+    /// create new cookie without any attribute
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+            path: None,
+            domain: None,
+            max_age: None,
+            expires: None,
+            secure: false,
+            http_only: false,
+            same_site: None,
+            partitioned: false,
+        }
+    }
+
+    /// create cookie that instruct the client to remove cookie with given name
+    ///
+    /// `path` and `domain` must match the original cookie for the removal to work
+    pub fn removal(name: impl Into<String>) -> Self {
+        Self::new(name, "").max_age(Duration::ZERO).expires(UNIX_EPOCH)
+    }
+
+    /// parse single `name=value` pair, surrounding double quote of the value is removed
+    pub fn parse_pair(pair: &str) -> Option<Self> {
+        let (name, value) = pair.trim().split_once('=')?;
+        let name = name.trim();
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|e|e.strip_suffix('"'))
+            .unwrap_or(value);
+        if name.is_empty() || !name.bytes().all(is_token) || !value.bytes().all(is_cookie_octet) {
+            return None;
+        }
+        Some(Self::new(name, value))
+    }
+
+    /// cookie name
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// cookie value, without surrounding double quote
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// replace cookie value
+    pub fn set_value(&mut self, value: impl Into<String>) {
+        self.value = value.into();
+    }
+
+    /// `Path` attribute
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// `Domain` attribute
+    pub fn domain(mut self, domain: impl Into<String>) -> Self {
+        self.domain = Some(domain.into());
+        self
+    }
+
+    /// `Max-Age` attribute, precision is in seconds
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// `Expires` attribute, precision is in seconds
+    pub fn expires(mut self, expires: SystemTime) -> Self {
+        self.expires = Some(expires);
+        self
+    }
+
+    /// `Secure` attribute
+    ///
+    /// this is always sent when [`SameSite::None`] or `Partitioned` is set, as
+    /// required by browsers
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    /// `HttpOnly` attribute
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+
+    /// `SameSite` attribute
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    /// `Partitioned` attribute
+    pub fn partitioned(mut self, partitioned: bool) -> Self {
+        self.partitioned = partitioned;
+        self
+    }
+
+    /// `Path` attribute, if any
+    pub fn get_path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    /// `Domain` attribute, if any
+    pub fn get_domain(&self) -> Option<&str> {
+        self.domain.as_deref()
+    }
+
+    /// `Max-Age` attribute, if any
+    pub fn get_max_age(&self) -> Option<Duration> {
+        self.max_age
+    }
+
+    /// `Expires` attribute, if any
+    pub fn get_expires(&self) -> Option<SystemTime> {
+        self.expires
+    }
+
+    /// returns `true` if `Secure` attribute is sent, see [`Cookie::secure`]
+    pub fn is_secure(&self) -> bool {
+        self.secure || self.partitioned || self.same_site == Some(SameSite::None)
+    }
+
+    /// returns `true` if `HttpOnly` attribute is set
+    pub fn is_http_only(&self) -> bool {
+        self.http_only
+    }
+
+    /// `SameSite` attribute, if any
+    pub fn get_same_site(&self) -> Option<SameSite> {
+        self.same_site
+    }
+
+    /// returns `true` if `Partitioned` attribute is set
+    pub fn is_partitioned(&self) -> bool {
+        self.partitioned
+    }
+
+    /// check that the cookie can be sent as `Set-Cookie` header
+    ///
+    /// name and value follow the same rule as [`Cookie::parse_pair`], path
+    /// and domain must not contain control character or `;`, which would
+    /// inject another attribute
+    pub fn validate(&self) -> Result<(), InvalidCookie> {
+        if self.name.is_empty() || !self.name.bytes().all(is_token) {
+            return Err(InvalidCookie::Name);
+        }
+        if !self.value.bytes().all(is_cookie_octet) {
+            return Err(InvalidCookie::Value);
+        }
+        if self.path.as_ref().is_some_and(|e|!e.bytes().all(is_av_octet)) {
+            return Err(InvalidCookie::Path);
+        }
+        if self.domain.as_ref().is_some_and(|e|!e.bytes().all(is_av_octet)) {
+            return Err(InvalidCookie::Domain);
+        }
+        Ok(())
+    }
+}
+
+/// error when cookie cannot be sent as `Set-Cookie` header
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidCookie {
+    #[error("invalid cookie name")]
+    Name,
+    #[error("invalid cookie value")]
+    Value,
+    #[error("invalid cookie path")]
+    Path,
+    #[error("invalid cookie domain")]
+    Domain,
+}
+
+impl fmt::Display for Cookie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+        if let Some(path) = &self.path {
+            write!(f, "; Path={path}")?;
+        }
+        if let Some(domain) = &self.domain {
+            write!(f, "; Domain={domain}")?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age.as_secs())?;
+        }
+        if let Some(expires) = self.expires {
+            write!(f, "; Expires={}", UtcTime::from(expires).http_date())?;
+        }
+        if self.is_secure() {
+            f.write_str("; Secure")?;
+        }
+        if self.http_only {
+            f.write_str("; HttpOnly")?;
+        }
+        if let Some(same_site) = self.same_site {
+            write!(f, "; SameSite={}", same_site.as_str())?;
+        }
+        if self.partitioned {
+            f.write_str("; Partitioned")?;
+        }
+        Ok(())
+    }
+}
+
+/// rfc 6265 cookie name
+fn is_token(b: u8) -> bool {
+    b.is_ascii_graphic() && !b"()<>@,;:\\\"/[]?={}".contains(&b)
+}
+
+/// rfc 6265 cookie value
+fn is_cookie_octet(b: u8) -> bool {
+    b.is_ascii_graphic() && !b"\",;\\".contains(&b)
+}
+
+/// rfc 6265 attribute value
+fn is_av_octet(b: u8) -> bool {
+    (b.is_ascii_graphic() || b == b' ') && b != b';'
+}
+
+/// a single cookie as response parts, this append `Set-Cookie` header
+///
+/// cookie that does not pass [`Cookie::validate`] is not sent and logged as error
+impl IntoResponseParts for Cookie {
+    fn into_response_parts(self, mut parts: response::Parts) -> response::Parts {
+        if let Err(err) = self.validate() {
+            log::error!("{err} in cookie {:?}, Set-Cookie is not sent", self.name);
+            return parts;
+        }
+        let value = HeaderValue::try_from(self.to_string()).expect("validated cookie is valid header value");
+        parts.headers.append(header::SET_COOKIE, value);
+        parts
+    }
+}
+
+/// collection of request cookies and pending changes
+///
+/// see [module level documentation](self) for more details
+#[derive(Clone, Debug, Default)]
+pub struct CookieJar {
+    cookies: Vec<Cookie>,
+    delta: Vec<Cookie>,
+}
+
+impl CookieJar {
+    /// create empty `CookieJar`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// parse all `Cookie` headers
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let cookies = headers
+            .get_all(header::COOKIE)
+            .into_iter()
+            .filter_map(|e|e.to_str().ok())
+            .flat_map(|e|e.split(';'))
+            .filter_map(Cookie::parse_pair)
+            .collect();
+        Self { cookies, delta: vec![] }
+    }
+
+    /// get cookie by name, including cookie added to the jar
+    pub fn get(&self, name: &str) -> Option<&Cookie> {
+        self.cookies.iter().find(|e|e.name == name)
+    }
+
+    /// iterate over all cookies, including cookie added to the jar
+    pub fn iter(&self) -> impl Iterator<Item = &Cookie> {
+        self.cookies.iter()
+    }
+
+    /// add cookie, replacing existing cookie with the same name
+    pub fn insert(self, cookie: Cookie) -> Self {
+        let value = cookie.value.clone();
+        self.insert_sealed(cookie, value)
+    }
+
+    /// add cookie, but send `value` as `Set-Cookie` value
+    fn insert_sealed(mut self, cookie: Cookie, value: String) -> Self {
+        self.cookies.retain(|e|e.name != cookie.name);
+        self.delta.retain(|e|e.name != cookie.name);
+        let mut sealed = cookie.clone();
+        sealed.value = value;
+        self.cookies.push(cookie);
+        self.delta.push(sealed);
+        self
+    }
+
+    /// keep only cookies that can be opened, replacing their value
+    #[cfg(feature = "secure-cookie")]
+    fn open(mut self, f: impl Fn(&Cookie) -> Option<String>) -> Self {
+        self.cookies.retain_mut(|cookie| match f(cookie) {
+            Some(value) => {
+                cookie.value = value;
+                true
+            }
+            None => false,
+        });
+        self
+    }
+
+    /// remove cookie with given name
+    ///
+    /// to remove cookie with `Path` or `Domain` attribute, use [`CookieJar::insert`]
+    /// with [`Cookie::removal`] instead
+    pub fn remove(self, name: impl Into<String>) -> Self {
+        let mut jar = self.insert(Cookie::removal(name));
+        jar.cookies.pop();
+        jar
+    }
+
+    /// cookies that will be sent as `Set-Cookie` header
+    pub fn delta(&self) -> impl Iterator<Item = &Cookie> {
+        self.delta.iter()
+    }
+}
+
+impl FromRequestParts for CookieJar {
+    type Error = Infallible;
+    type Future = Ready<Result<Self,Self::Error>>;
+
+    fn from_request_parts(parts: &mut request::Parts) -> Self::Future {
+        ready(Ok(Self::from_headers(&parts.headers)))
+    }
+}
+
+impl IntoResponseParts for CookieJar {
+    fn into_response_parts(self, parts: response::Parts) -> response::Parts {
+        self.delta.into_iter().fold(parts, |parts, cookie| cookie.into_response_parts(parts))
+    }
+}
@@ -0,0 +1,134 @@
+//! cookie keys
+use crate::{
+    http::{FromRequestParts, IntoResponse, Request, Response},
+    middleware::Layer,
+    util::random,
+};
+use http::{request, StatusCode};
+use hyper::service::Service;
+use std::{
+    fmt,
+    future::{ready, Ready},
+    sync::Arc,
+};
+
+/// key used to sign and encrypt cookies
+#[derive(Clone)]
+pub struct Key {
+    pub(super) signing: [u8; 32],
+    pub(super) encryption: [u8; 32],
+}
+
+impl Key {
+    /// generate random key
+    ///
+    /// cookies is no longer readable after restart, use [`Key::from_bytes`]
+    /// for persistent key
+    pub fn generate() -> Self {
+        Self::from_bytes(&random::bytes::<64>()).expect("64 bytes key")
+    }
+
+    /// create key from at least 64 bytes of high entropy data
+    ///
+    /// the first 32 bytes is used for signing and the next 32 bytes for encryption
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let signing = bytes.get(..32)?.try_into().ok()?;
+        let encryption = bytes.get(32..64)?.try_into().ok()?;
+        Some(Self { signing, encryption })
+    }
+}
+
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Key(..)")
+    }
+}
+
+/// keys for signed and private cookie jar
+///
+/// new cookies is always sealed with the current key, cookies sealed with
+/// fallback keys is still accepted, which allow key rotation without
+/// invalidating existing cookies
+///
+/// this is also a [`Layer`] that make the keys available to
+/// [`SignedCookieJar`] and [`PrivateCookieJar`] extractor
+///
+/// [`SignedCookieJar`]: super::SignedCookieJar
+/// [`PrivateCookieJar`]: super::PrivateCookieJar
+#[derive(Clone, Debug)]
+pub struct Keys {
+    keys: Arc<[Key]>,
+}
+
+impl Keys {
+    /// create `Keys` with the current key
+    pub fn new(current: Key) -> Self {
+        Self { keys: Arc::from([current]) }
+    }
+
+    /// add older key that is still accepted
+    pub fn fallback(self, key: Key) -> Self {
+        let mut keys = self.keys.to_vec();
+        keys.push(key);
+        Self { keys: keys.into() }
+    }
+
+    /// key used to seal new cookies
+    pub fn current(&self) -> &Key {
+        &self.keys[0]
+    }
+
+    /// all accepted keys, starting with the current key
+    pub fn iter(&self) -> impl Iterator<Item = &Key> {
+        self.keys.iter()
+    }
+}
+
+impl FromRequestParts for Keys {
+    type Error = MissingKeys;
+    type Future = Ready<Result<Self,Self::Error>>;
+
+    fn from_request_parts(parts: &mut request::Parts) -> Self::Future {
+        ready(parts.extensions.get::<Keys>().cloned().ok_or(MissingKeys))
+    }
+}
+
+/// error returned when [`Keys`] layer is not applied
+#[derive(thiserror::Error, Debug)]
+#[error("cookie keys is not available")]
+pub struct MissingKeys;
+
+impl IntoResponse for MissingKeys {
+    fn into_response(self) -> Response {
+        (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()).into_response()
+    }
+}
+
+impl<S> Layer<S> for Keys {
+    type Service = KeysService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        KeysService { inner, keys: self.clone() }
+    }
+}
+
+/// service that insert [`Keys`] into request extensions
+#[derive(Clone)]
+pub struct KeysService<S> {
+    inner: S,
+    keys: Keys,
+}
+
+impl<S,B> Service<Request<B>> for KeysService<S>
+where
+    S: Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn call(&self, mut req: Request<B>) -> Self::Future {
+        req.extensions_mut().insert(self.keys.clone());
+        self.inner.call(req)
+    }
+}
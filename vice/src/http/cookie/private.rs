@@ -0,0 +1,145 @@
+//! private cookie jar
+//!
+//! # Example
+//!
+//! ```no_run
+//! use vice::{
+//!     http::cookie::{Cookie, Key, Keys, PrivateCookieJar},
+//!     router::{Router, get},
+//! };
+//!
+//! async fn login(jar: PrivateCookieJar) -> (PrivateCookieJar, &'static str) {
+//!     (jar.insert(Cookie::new("user", "bob").http_only(true)), "logged in")
+//! }
+//!
+//! async fn whoami(jar: PrivateCookieJar) -> String {
+//!     jar.get("user").map(|e|e.value().to_owned()).unwrap_or_default()
+//! }
+//!
+//! fn main() -> std::io::Result<()> {
+//!     let keys = Keys::new(Key::generate());
+//!     let route = Router::new()
+//!         .route("/login", get(login))
+//!         .route("/whoami", get(whoami))
+//!         .layer(keys);
+//!     vice::listen("0.0.0.0:3000", route)
+//! }
+//! ```
+use super::{Cookie, CookieJar, Keys, MissingKeys};
+use crate::{
+    http::{FromRequestParts, IntoResponseParts},
+    util::{base64, random},
+};
+use aes_gcm::{
+    aead::{Aead, Payload},
+    Aes256Gcm, KeyInit, Nonce,
+};
+use http::{request, response, HeaderMap};
+use std::future::{ready, Ready};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::cookie::Key;
+
+    #[test]
+    fn private() {
+        let old = Key::generate();
+        let keys = Keys::new(Key::generate()).fallback(old.clone());
+        let cookie = Cookie::new("user", "bob");
+        let sealed = encrypt(&keys, &cookie);
+        assert!(!sealed.contains("bob"));
+        assert_ne!(sealed, encrypt(&keys, &cookie));
+
+        assert_eq!(decrypt(&keys, &Cookie::new("user", &sealed)).as_deref(), Some("bob"));
+        assert_eq!(decrypt(&keys, &Cookie::new("user", encrypt(&Keys::new(old), &cookie))).as_deref(), Some("bob"));
+        assert!(decrypt(&keys, &Cookie::new("admin", &sealed)).is_none());
+        assert!(decrypt(&Keys::new(Key::generate()), &Cookie::new("user", &sealed)).is_none());
+    }
+}
+
+const NONCE_LEN: usize = 12;
+
+/// cookie jar that encrypt cookie value using AES-256-GCM
+///
+/// the value is neither readable nor modifiable by the client, cookie that
+/// fail to decrypt is dropped when parsing the request
+///
+/// [`Keys`] layer must be applied when used as extractor
+#[derive(Clone, Debug)]
+pub struct PrivateCookieJar {
+    jar: CookieJar,
+    keys: Keys,
+}
+
+impl PrivateCookieJar {
+    /// parse and decrypt all `Cookie` headers
+    pub fn from_headers(headers: &HeaderMap, keys: Keys) -> Self {
+        let jar = CookieJar::from_headers(headers).open(|cookie|decrypt(&keys, cookie));
+        Self { jar, keys }
+    }
+
+    /// get decrypted cookie by name, including cookie added to the jar
+    pub fn get(&self, name: &str) -> Option<&Cookie> {
+        self.jar.get(name)
+    }
+
+    /// iterate over decrypted cookies, including cookie added to the jar
+    pub fn iter(&self) -> impl Iterator<Item = &Cookie> {
+        self.jar.iter()
+    }
+
+    /// encrypt and add cookie, replacing existing cookie with the same name
+    pub fn insert(mut self, cookie: Cookie) -> Self {
+        let value = encrypt(&self.keys, &cookie);
+        self.jar = self.jar.insert_sealed(cookie, value);
+        self
+    }
+
+    /// remove cookie with given name
+    pub fn remove(mut self, name: impl Into<String>) -> Self {
+        self.jar = self.jar.remove(name);
+        self
+    }
+}
+
+/// the cookie name is used as associated data, so value cannot be moved to another cookie
+fn encrypt(keys: &Keys, cookie: &Cookie) -> String {
+    let nonce = random::bytes::<NONCE_LEN>();
+    let cipher = Aes256Gcm::new(&keys.current().encryption.into());
+    let payload = Payload { msg: cookie.value().as_bytes(), aad: cookie.name().as_bytes() };
+    let sealed = cipher.encrypt(Nonce::from_slice(&nonce), payload).expect("cookie value is too large");
+    base64::encode_url(&[&nonce[..], &sealed].concat())
+}
+
+fn decrypt(keys: &Keys, cookie: &Cookie) -> Option<String> {
+    let data = base64::decode_url(cookie.value())?;
+    if data.len() < NONCE_LEN {
+        return None;
+    }
+    let (nonce, sealed) = data.split_at(NONCE_LEN);
+    keys.iter().find_map(|key| {
+        let cipher = Aes256Gcm::new(&key.encryption.into());
+        let payload = Payload { msg: sealed, aad: cookie.name().as_bytes() };
+        cipher.decrypt(Nonce::from_slice(nonce), payload).ok()
+    }).and_then(|e|String::from_utf8(e).ok())
+}
+
+impl FromRequestParts for PrivateCookieJar {
+    type Error = MissingKeys;
+    type Future = Ready<Result<Self,Self::Error>>;
+
+    fn from_request_parts(parts: &mut request::Parts) -> Self::Future {
+        let result = match parts.extensions.get::<Keys>() {
+            Some(keys) => Ok(Self::from_headers(&parts.headers, keys.clone())),
+            None => Err(MissingKeys),
+        };
+        ready(result)
+    }
+}
+
+impl IntoResponseParts for PrivateCookieJar {
+    fn into_response_parts(self, parts: response::Parts) -> response::Parts {
+        self.jar.into_response_parts(parts)
+    }
+}
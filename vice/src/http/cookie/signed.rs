@@ -0,0 +1,121 @@
+//! signed cookie jar
+use super::{Cookie, CookieJar, Keys, MissingKeys};
+use crate::{
+    http::{FromRequestParts, IntoResponseParts},
+    util::base64,
+};
+use hmac::{Hmac, Mac};
+use http::{request, response, HeaderMap};
+use sha2::Sha256;
+use std::future::{ready, Ready};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::cookie::Key;
+
+    #[test]
+    fn signed() {
+        let old = Key::generate();
+        let keys = Keys::new(Key::generate()).fallback(old.clone());
+        let sealed = |keys: &Keys, name: &str, value: &str| {
+            let jar = SignedCookieJar { jar: CookieJar::new(), keys: keys.clone() }.insert(Cookie::new(name, value));
+            jar.jar.delta().next().unwrap().value().to_owned()
+        };
+
+        let cookie = sealed(&keys, "user", "bob");
+        assert_eq!(verify(&keys, &Cookie::new("user", &cookie)).as_deref(), Some("bob"));
+        assert_eq!(verify(&keys, &Cookie::new("user", sealed(&Keys::new(old), "user", "bob"))).as_deref(), Some("bob"));
+        assert!(verify(&keys, &Cookie::new("admin", &cookie)).is_none());
+        assert!(verify(&keys, &Cookie::new("user", cookie.replace("bob", "eve"))).is_none());
+        assert!(verify(&Keys::new(Key::generate()), &Cookie::new("user", &cookie)).is_none());
+    }
+}
+
+/// length of base64 encoded HMAC-SHA256 tag
+const TAG_LEN: usize = 43;
+
+/// cookie jar that sign cookie value using HMAC-SHA256
+///
+/// the value is readable by the client but cannot be modified, cookie with
+/// invalid signature is dropped when parsing the request
+///
+/// [`Keys`] layer must be applied when used as extractor
+#[derive(Clone, Debug)]
+pub struct SignedCookieJar {
+    jar: CookieJar,
+    keys: Keys,
+}
+
+impl SignedCookieJar {
+    /// parse and verify all `Cookie` headers
+    pub fn from_headers(headers: &HeaderMap, keys: Keys) -> Self {
+        let jar = CookieJar::from_headers(headers).open(|cookie|verify(&keys, cookie));
+        Self { jar, keys }
+    }
+
+    /// get verified cookie by name, including cookie added to the jar
+    pub fn get(&self, name: &str) -> Option<&Cookie> {
+        self.jar.get(name)
+    }
+
+    /// iterate over verified cookies, including cookie added to the jar
+    pub fn iter(&self) -> impl Iterator<Item = &Cookie> {
+        self.jar.iter()
+    }
+
+    /// sign and add cookie, replacing existing cookie with the same name
+    pub fn insert(mut self, cookie: Cookie) -> Self {
+        let value = sign(&self.keys, &cookie);
+        self.jar = self.jar.insert_sealed(cookie, value);
+        self
+    }
+
+    /// remove cookie with given name
+    pub fn remove(mut self, name: impl Into<String>) -> Self {
+        self.jar = self.jar.remove(name);
+        self
+    }
+}
+
+fn mac(key: &[u8], cookie: &Cookie) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac accept any key length");
+    mac.update(cookie.name().as_bytes());
+    mac.update(b"=");
+    mac
+}
+
+pub(crate) fn sign(keys: &Keys, cookie: &Cookie) -> String {
+    let mut mac = mac(&keys.current().signing, cookie);
+    mac.update(cookie.value().as_bytes());
+    base64::encode_url(&mac.finalize().into_bytes()) + cookie.value()
+}
+
+pub(crate) fn verify(keys: &Keys, cookie: &Cookie) -> Option<String> {
+    let tag = base64::decode_url(cookie.value().get(..TAG_LEN)?)?;
+    let value = &cookie.value()[TAG_LEN..];
+    keys.iter().any(|key| {
+        let mut mac = mac(&key.signing, cookie);
+        mac.update(value.as_bytes());
+        mac.verify_slice(&tag).is_ok()
+    }).then(||value.to_owned())
+}
+
+impl FromRequestParts for SignedCookieJar {
+    type Error = MissingKeys;
+    type Future = Ready<Result<Self,Self::Error>>;
+
+    fn from_request_parts(parts: &mut request::Parts) -> Self::Future {
+        let result = match parts.extensions.get::<Keys>() {
+            Some(keys) => Ok(Self::from_headers(&parts.headers, keys.clone())),
+            None => Err(MissingKeys),
+        };
+        ready(result)
+    }
+}
+
+impl IntoResponseParts for SignedCookieJar {
+    fn into_response_parts(self, parts: response::Parts) -> response::Parts {
+        self.jar.into_response_parts(parts)
+    }
+}
@@ -1,6 +1,6 @@
 //! [`FromRequest`] and [`FromRequestParts`] trait
 use std::{convert::Infallible, future::{ready, Ready}};
-use super::{into_response::IntoResponse, ReqBody, Request};
+use super::{body::BodyError, into_response::IntoResponse, ReqBody, Request};
 use crate::util::response::BadRequest;
 use bytes::Bytes;
 use http::request;
@@ -91,7 +91,7 @@ macro_rules! from_request {
 pub use bytes_future::BytesFuture;
 from_request! {
     Bytes,
-    Error = BadRequest<hyper::Error>;
+    Error = BodyError;
     Future = BytesFuture;
     (req) => BytesFuture::new(req.into_body())
 }
@@ -100,7 +100,7 @@ from_request! {
 pub use string_future::{StringFuture, StringFutureError};
 from_request! {
     String,
-    Error = BadRequest<StringFutureError>;
+    Error = StringFutureError;
     Future = StringFuture;
     (req) => StringFuture::new(req.into_body())
 }
@@ -124,13 +124,13 @@ mod bytes_future {
     }
 
     impl Future for BytesFuture {
-        type Output = Result<Bytes, BadRequest<hyper::Error>>;
+        type Output = Result<Bytes, BodyError>;
 
         fn poll(self: std::pin::Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> std::task::Poll<Self::Output> {
             use std::task::Poll::*;
             match self.project().inner.poll(cx) {
                 Ready(Ok(ok)) => Ready(Ok(ok.to_bytes())),
-                Ready(Err(err)) => Ready(Err(err.into())),
+                Ready(Err(err)) => Ready(Err(BodyError::new(err))),
                 Pending => Pending
             }
         }
@@ -157,16 +157,16 @@ mod string_future {
     }
 
     impl Future for StringFuture {
-        type Output = Result<String, BadRequest<StringFutureError>>;
+        type Output = Result<String, StringFutureError>;
 
         fn poll(self: std::pin::Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> std::task::Poll<Self::Output> {
             use std::task::Poll::*;
             match self.project().inner.poll(cx) {
                 Ready(Ok(ok)) => match String::from_utf8(Vec::from(ok.to_bytes())) {
                     Ok(ok) => Ready(Ok(ok)),
-                    Err(err) => Ready(Err(StringFutureError::Utf8(err).into())),
+                    Err(err) => Ready(Err(StringFutureError::Utf8(err))),
                 },
-                Ready(Err(err)) => Ready(Err(StringFutureError::Hyper(err).into())),
+                Ready(Err(err)) => Ready(Err(StringFutureError::Body(BodyError::new(err)))),
                 Pending => Pending
             }
         }
@@ -176,9 +176,18 @@ mod string_future {
     #[derive(thiserror::Error, Debug)]
     pub enum StringFutureError {
         #[error(transparent)]
-        Hyper(hyper::Error),
+        Body(BodyError),
         #[error(transparent)]
         Utf8(FromUtf8Error),
     }
+
+    impl IntoResponse for StringFutureError {
+        fn into_response(self) -> crate::http::Response {
+            match self {
+                StringFutureError::Body(err) => err.into_response(),
+                StringFutureError::Utf8(err) => BadRequest::new(err).into_response(),
+            }
+        }
+    }
 }
 
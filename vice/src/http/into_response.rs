@@ -72,12 +72,10 @@ impl IntoResponseParts for StatusCode {
     }
 }
 
+/// replace existing headers with the same name, multiple values of one name is preserved
 impl IntoResponseParts for HeaderMap {
-    fn into_response_parts(mut self, mut parts: response::Parts) -> response::Parts {
-        const PLACEHOLDER: HeaderValue = HeaderValue::from_static("deez");
-        for (key,val) in (&mut self).into_iter() {
-            parts.headers.insert(key, std::mem::replace(val, PLACEHOLDER));
-        }
+    fn into_response_parts(self, mut parts: response::Parts) -> response::Parts {
+        parts.headers.extend(self);
         parts
     }
 }
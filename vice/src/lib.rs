@@ -1,8 +1,13 @@
 //! http server framework
+// test modules sit at the top of the file, right after the imports
+#![cfg_attr(test, allow(clippy::items_after_test_module))]
 pub mod http;
+pub mod middleware;
 pub mod router;
 pub mod util;
 pub mod runtime;
+#[cfg(feature = "tower")]
+pub mod tower;
 
 #[doc(inline)]
 pub use runtime::listen;
@@ -0,0 +1,143 @@
+//! request and response middleware
+//!
+//! middleware is a [`Service`] that wrap another service, a [`Layer`] create
+//! the middleware from the inner service
+//!
+//! use [`Router::layer`] to wrap the whole router, or [`Router::route_layer`]
+//! to wrap only the last assigned route
+//!
+//! # Example
+//!
+//! ```no_run
+//! use vice::{
+//!     http::{Request, Response},
+//!     middleware::{from_fn, Next},
+//!     router::{Router, get},
+//! };
+//!
+//! async fn log(req: Request, next: Next) -> Response {
+//!     let path = req.uri().path().to_owned();
+//!     let res = next.run(req).await;
+//!     println!("{path} {}", res.status());
+//!     res
+//! }
+//!
+//! fn main() -> std::io::Result<()> {
+//!     let route = Router::new()
+//!         .route("/", get(||async { "Vice Dev" }))
+//!         .layer(from_fn(log));
+//!     vice::listen("0.0.0.0:3000", route)
+//! }
+//! ```
+//!
+//! [`Service`]: hyper::service::Service
+//! [`Router::layer`]: crate::router::Router::layer
+//! [`Router::route_layer`]: crate::router::Router::route_layer
+pub mod access_log;
+pub mod auth;
+pub mod catch_panic;
+#[cfg(feature = "compression")]
+pub mod compression;
+pub mod cors;
+pub mod csrf;
+#[cfg(feature = "compression")]
+pub mod decompression;
+pub mod forwarded;
+pub mod from_fn;
+#[cfg(feature = "jwt")]
+pub mod jwt;
+pub mod metrics;
+pub mod rate_limit;
+pub mod request_id;
+pub mod security_headers;
+#[cfg(feature = "session")]
+pub mod session;
+pub mod timeout;
+#[cfg(feature = "tracing")]
+pub mod trace;
+
+#[doc(inline)]
+pub use from_fn::{from_fn, Next};
+
+/// decorate a service with cross cutting behaviour
+///
+/// see [module level documentation](self) for more details
+pub trait Layer<S> {
+    /// the wrapped service
+    type Service;
+
+    /// wrap the inner service
+    fn layer(&self, inner: S) -> Self::Service;
+}
+
+/// create [`Layer`] from a function that wrap the inner service
+///
+/// # Example
+///
+/// ```
+/// use vice::{middleware::{layer_fn, Layer}, util::service::NotFound};
+///
+/// #[derive(Clone)]
+/// struct MyService<S>(S);
+///
+/// let layer = layer_fn(MyService);
+/// let MyService(NotFound) = layer.layer(NotFound);
+/// ```
+pub fn layer_fn<F>(f: F) -> LayerFn<F> {
+    LayerFn { f }
+}
+
+/// [`Layer`] returned from [`layer_fn`]
+#[derive(Clone, Copy)]
+pub struct LayerFn<F> {
+    f: F,
+}
+
+impl<F,S,Out> Layer<S> for LayerFn<F>
+where
+    F: Fn(S) -> Out,
+{
+    type Service = Out;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        (self.f)(inner)
+    }
+}
+
+/// layer that does nothing
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Identity;
+
+impl<S> Layer<S> for Identity {
+    type Service = S;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        inner
+    }
+}
+
+/// two layers applied in order, the first layer is the outermost
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Stack<Outer,Inner> {
+    outer: Outer,
+    inner: Inner,
+}
+
+impl<Outer,Inner> Stack<Outer,Inner> {
+    /// create new `Stack`
+    pub fn new(outer: Outer, inner: Inner) -> Self {
+        Self { outer, inner }
+    }
+}
+
+impl<S,Outer,Inner> Layer<S> for Stack<Outer,Inner>
+where
+    Inner: Layer<S>,
+    Outer: Layer<Inner::Service>,
+{
+    type Service = Outer::Service;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        self.outer.layer(self.inner.layer(inner))
+    }
+}
@@ -0,0 +1,391 @@
+//! access logging
+//!
+//! every request is logged through the [`log`] facade with `vice::access` target
+//!
+//! # Example
+//!
+//! ```no_run
+//! use vice::{
+//!     middleware::access_log::{AccessLog, LogFormat},
+//!     router::{Router, get},
+//! };
+//!
+//! fn main() -> std::io::Result<()> {
+//!     let route = Router::new()
+//!         .route("/", get(||async { "Vice Dev" }))
+//!         .layer(AccessLog::new(LogFormat::Json).redact_query("token"));
+//!     vice::listen("0.0.0.0:3000", route)
+//! }
+//! ```
+use super::{forwarded::ClientIp, request_id::RequestId, Layer};
+use crate::{
+    http::{Request, Response},
+    runtime::Connection,
+    util::time::UtcTime,
+};
+use http::{header, HeaderMap, HeaderName, Method, Uri, Version};
+use hyper::{body::Body, service::Service};
+use log::Level;
+use std::{
+    fmt::Write,
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    sync::Arc,
+    task::{ready, Context, Poll},
+    time::{Duration, Instant},
+};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn format() {
+        let log = AccessLog::new(LogFormat::Combined).redact_query("token").redact_path("/reset/");
+        let mut req = Request::new(());
+        *req.uri_mut() = "/reset/abc?token=secret&page=2".parse().unwrap();
+        req.headers_mut().insert(header::USER_AGENT, "curl/8.0".parse().unwrap());
+        let mut entry = log.entry(&req);
+        entry.time = UtcTime::from_unix(971186136);
+        entry.request_id = Some(RequestId::new("abc"));
+
+        let line = log.format(&entry, 200, Some(5), Duration::from_millis(3));
+        assert_eq!(line, r#"- - - [10/Oct/2000:13:55:36 +0000] "GET /reset/[redacted]?token=[redacted]&page=2 HTTP/1.1" 200 5 "-" "curl/8.0""#);
+
+        let log = AccessLog { format: LogFormat::Json, ..log };
+        let line = log.format(&entry, 404, None, Duration::from_micros(1500));
+        assert_eq!(line, concat!(
+            r#"{"time":"2000-10-10T13:55:36Z","remote_addr":null,"request_id":"abc","method":"GET","#,
+            r#""path":"/reset/[redacted]?token=[redacted]&page=2","version":"HTTP/1.1","status":404,"#,
+            r#""size":null,"latency_ms":1.500,"referer":null,"user_agent":"curl/8.0"}"#,
+        ));
+
+        // quote in user agent cannot forge fields
+        let log = AccessLog { format: LogFormat::Combined, ..log };
+        let mut req = Request::new(());
+        *req.uri_mut() = "/a%22b".parse().unwrap();
+        req.headers_mut().insert(header::USER_AGENT, http::HeaderValue::from_bytes(b"a\" 500 0 \"x\\\xff").unwrap());
+        let mut entry = log.entry(&req);
+        entry.time = UtcTime::from_unix(971186136);
+        let line = log.format(&entry, 200, Some(5), Duration::ZERO);
+        assert_eq!(line, r#"- - - [10/Oct/2000:13:55:36 +0000] "GET /a%22b HTTP/1.1" 200 5 "-" "a\" 500 0 \"x\\\xef\xbf\xbd""#);
+    }
+}
+
+const REDACTED: &str = "[redacted]";
+
+/// access log line format
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    /// [Common Log Format](https://httpd.apache.org/docs/current/logs.html#common)
+    Common,
+    /// [Combined Log Format](https://httpd.apache.org/docs/current/logs.html#combined)
+    Combined,
+    /// structured json line
+    Json,
+}
+
+/// access logging configuration
+///
+/// see [module level documentation](self) for more details
+#[derive(Clone, Debug)]
+pub struct AccessLog {
+    format: LogFormat,
+    level: Level,
+    redact_query: Arc<[String]>,
+    redact_paths: Arc<[String]>,
+    redact_headers: Arc<[HeaderName]>,
+    headers: Arc<[HeaderName]>,
+}
+
+impl AccessLog {
+    /// create new `AccessLog` with given format
+    pub fn new(format: LogFormat) -> Self {
+        Self {
+            format,
+            level: Level::Info,
+            redact_query: Arc::new([]),
+            redact_paths: Arc::new([]),
+            redact_headers: Arc::new([]),
+            headers: Arc::new([]),
+        }
+    }
+
+    /// log level of access log, default to `Info`
+    pub fn level(mut self, level: Level) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// replace value of query parameter with given name
+    pub fn redact_query(mut self, name: impl Into<String>) -> Self {
+        self.redact_query = push(&self.redact_query, name.into());
+        self
+    }
+
+    /// replace the rest of the path that start with given prefix
+    pub fn redact_path(mut self, prefix: impl Into<String>) -> Self {
+        self.redact_paths = push(&self.redact_paths, prefix.into());
+        self
+    }
+
+    /// replace value of logged header with given name
+    pub fn redact_header(mut self, name: HeaderName) -> Self {
+        self.redact_headers = push(&self.redact_headers, name);
+        self
+    }
+
+    /// additionally log request header with given name
+    ///
+    /// only used by [`LogFormat::Json`]
+    pub fn header(mut self, name: HeaderName) -> Self {
+        self.headers = push(&self.headers, name);
+        self
+    }
+
+    fn entry<B>(&self, req: &Request<B>) -> Entry {
+        let ext = req.extensions();
+        let remote_addr = ext
+            .get::<ClientIp>()
+            .map(|e|e.0)
+            .or_else(||ext.get::<Connection>().map(|e|e.peer_addr().ip()))
+            .or_else(||ext.get::<SocketAddr>().map(|e|e.ip()));
+
+        Entry {
+            time: UtcTime::now(),
+            start: Instant::now(),
+            remote_addr,
+            request_id: ext.get::<RequestId>().cloned(),
+            method: req.method().clone(),
+            path: self.redacted_path(req.uri()),
+            version: req.version(),
+            headers: self.logged_headers(req.headers()),
+        }
+    }
+
+    fn redacted_path(&self, uri: &Uri) -> String {
+        let mut path = uri.path().to_owned();
+        if let Some(prefix) = self.redact_paths.iter().find(|e|path.starts_with(e.as_str()))
+            && path.len() > prefix.len()
+        {
+            path.truncate(prefix.len());
+            path.push_str(REDACTED);
+        }
+
+        if let Some(query) = uri.query() {
+            path.push('?');
+            for (i,pair) in query.split('&').enumerate() {
+                if i != 0 {
+                    path.push('&');
+                }
+                match pair.split_once('=') {
+                    Some((key,_)) if self.redact_query.iter().any(|e|e == key) => {
+                        let _ = write!(path, "{key}={REDACTED}");
+                    }
+                    _ => path.push_str(pair),
+                }
+            }
+        }
+
+        path
+    }
+
+    fn logged_headers(&self, headers: &HeaderMap) -> Vec<(HeaderName, String)> {
+        let names = [header::REFERER, header::USER_AGENT];
+        names
+            .iter()
+            .chain(self.headers.iter())
+            .filter_map(|name| {
+                let value = headers.get(name)?;
+                let value = match self.redact_headers.contains(name) {
+                    true => REDACTED.to_owned(),
+                    false => String::from_utf8_lossy(value.as_bytes()).into_owned(),
+                };
+                Some((name.clone(), value))
+            })
+            .collect()
+    }
+
+    fn format(&self, entry: &Entry, status: u16, size: Option<u64>, latency: Duration) -> String {
+        let mut line = String::with_capacity(128);
+        let header = |name: &HeaderName| entry.headers.iter().find(|e|e.0 == name).map(|e|e.1.as_str());
+
+        match self.format {
+            LogFormat::Common | LogFormat::Combined => {
+                let _ = write!(line, "{} - - [{}] \"{} ", Or(entry.remote_addr), entry.time.clf(), entry.method);
+                clf_str(&mut line, Some(&entry.path));
+                let _ = write!(line, " {:?}\" {status} ", entry.version);
+                match size {
+                    Some(size) => { let _ = write!(line, "{size}"); },
+                    None => line.push('-'),
+                }
+                if self.format == LogFormat::Combined {
+                    line.push_str(" \"");
+                    clf_str(&mut line, header(&header::REFERER));
+                    line.push_str("\" \"");
+                    clf_str(&mut line, header(&header::USER_AGENT));
+                    line.push('"');
+                }
+            }
+            LogFormat::Json => {
+                let _ = write!(line, "{{\"time\":\"{}\",\"remote_addr\":", entry.time.rfc3339());
+                json_str(&mut line, entry.remote_addr.map(|e|e.to_string()).as_deref());
+                line.push_str(",\"request_id\":");
+                json_str(&mut line, entry.request_id.as_ref().map(RequestId::as_str));
+                line.push_str(",\"method\":");
+                json_str(&mut line, Some(entry.method.as_str()));
+                line.push_str(",\"path\":");
+                json_str(&mut line, Some(&entry.path));
+                let _ = write!(line, ",\"version\":\"{:?}\",\"status\":{status},\"size\":", entry.version);
+                match size {
+                    Some(size) => { let _ = write!(line, "{size}"); },
+                    None => line.push_str("null"),
+                }
+                let _ = write!(line, ",\"latency_ms\":{:.3}", latency.as_secs_f64() * 1000.0);
+                line.push_str(",\"referer\":");
+                json_str(&mut line, header(&header::REFERER));
+                line.push_str(",\"user_agent\":");
+                json_str(&mut line, header(&header::USER_AGENT));
+                for name in self.headers.iter() {
+                    line.push(',');
+                    json_str(&mut line, Some(name.as_str()));
+                    line.push(':');
+                    json_str(&mut line, header(name));
+                }
+                line.push('}');
+            }
+        }
+
+        line
+    }
+}
+
+impl<S> Layer<S> for AccessLog {
+    type Service = AccessLogService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AccessLogService { inner, log: self.clone() }
+    }
+}
+
+fn push<T: Clone>(slice: &[T], value: T) -> Arc<[T]> {
+    slice.iter().cloned().chain(Some(value)).collect()
+}
+
+/// write `Some` value or `-`
+struct Or<T>(Option<T>);
+
+impl<T: std::fmt::Display> std::fmt::Display for Or<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.0 {
+            Some(value) => value.fmt(f),
+            None => f.write_str("-"),
+        }
+    }
+}
+
+/// write value escaped the way apache escape log items, or `-`
+///
+/// `"` and `\` is escaped with backslash, non printable and non ascii bytes as `\xhh`
+fn clf_str(buf: &mut String, value: Option<&str>) {
+    let Some(value) = value else {
+        buf.push('-');
+        return;
+    };
+    for b in value.bytes() {
+        match b {
+            b'"' => buf.push_str("\\\""),
+            b'\\' => buf.push_str("\\\\"),
+            b'\n' => buf.push_str("\\n"),
+            b'\r' => buf.push_str("\\r"),
+            b'\t' => buf.push_str("\\t"),
+            b' '..=b'~' => buf.push(b as char),
+            b => { let _ = write!(buf, "\\x{b:02x}"); },
+        }
+    }
+}
+
+/// write escaped json string or `null`
+fn json_str(buf: &mut String, value: Option<&str>) {
+    let Some(value) = value else {
+        buf.push_str("null");
+        return;
+    };
+    buf.push('"');
+    for ch in value.chars() {
+        match ch {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            '\n' => buf.push_str("\\n"),
+            '\r' => buf.push_str("\\r"),
+            '\t' => buf.push_str("\\t"),
+            ch if ch.is_control() => { let _ = write!(buf, "\\u{:04x}", ch as u32); },
+            ch => buf.push(ch),
+        }
+    }
+    buf.push('"');
+}
+
+/// request information captured before calling the inner service
+struct Entry {
+    time: UtcTime,
+    start: Instant,
+    remote_addr: Option<IpAddr>,
+    request_id: Option<RequestId>,
+    method: Method,
+    path: String,
+    version: Version,
+    headers: Vec<(HeaderName, String)>,
+}
+
+/// service that log every request
+#[derive(Clone)]
+pub struct AccessLogService<S> {
+    inner: S,
+    log: AccessLog,
+}
+
+impl<S,B> Service<Request<B>> for AccessLogService<S>
+where
+    S: Service<Request<B>, Response = Response>,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = AccessLogFuture<S::Future>;
+
+    fn call(&self, req: Request<B>) -> Self::Future {
+        let log = log::log_enabled!(target: "vice::access", self.log.level)
+            .then(||(self.log.clone(), self.log.entry(&req)));
+        AccessLogFuture { inner: self.inner.call(req), log }
+    }
+}
+
+pin_project_lite::pin_project! {
+    /// future returned from [`AccessLogService`]
+    pub struct AccessLogFuture<F> {
+        #[pin]
+        inner: F,
+        log: Option<(AccessLog, Entry)>,
+    }
+}
+
+impl<F,E> Future for AccessLogFuture<F>
+where
+    F: Future<Output = Result<Response,E>>,
+{
+    type Output = Result<Response,E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let me = self.project();
+        let res = ready!(me.inner.poll(cx));
+        if let (Ok(res), Some((log, mut entry))) = (&res, me.log.take()) {
+            if entry.request_id.is_none() {
+                entry.request_id = res.extensions().get::<RequestId>().cloned();
+            }
+            let size = res.body().size_hint().exact();
+            let line = log.format(&entry, res.status().as_u16(), size, entry.start.elapsed());
+            log::log!(target: "vice::access", log.level, "{line}");
+        }
+        Poll::Ready(res)
+    }
+}
@@ -0,0 +1,363 @@
+//! HTTP authentication
+//!
+//! [`Auth`] parse `Authorization` header using [`Basic`] or [`Bearer`] scheme,
+//! then call the async validator with the credentials
+//!
+//! when the validator returns `Some`, the principal is inserted into request
+//! extensions and can be extracted with [`Principal`], otherwise the request
+//! is rejected with `401 Unauthorized` and `WWW-Authenticate` challenge
+//!
+//! # Example
+//!
+//! ```no_run
+//! use vice::{
+//!     middleware::auth::{Auth, BasicCredentials, Principal},
+//!     router::{Router, get},
+//! };
+//!
+//! #[derive(Clone)]
+//! struct User {
+//!     name: String,
+//! }
+//!
+//! async fn validate(cred: BasicCredentials) -> Option<User> {
+//!     (cred.password == "secret").then(|| User { name: cred.username })
+//! }
+//!
+//! async fn handle(user: Principal<User>) -> String {
+//!     format!("hello {}", user.name)
+//! }
+//!
+//! fn main() -> std::io::Result<()> {
+//!     let route = Router::new()
+//!         .route("/", get(handle))
+//!         .layer(Auth::basic("admin", validate));
+//!     vice::listen("0.0.0.0:3000", route)
+//! }
+//! ```
+use super::Layer;
+use crate::{
+    http::{FromRequestParts, IntoResponse, Request, Response},
+    util::base64,
+};
+use http::{header, request, HeaderMap, HeaderValue, StatusCode};
+use hyper::service::Service;
+use std::{
+    fmt,
+    future::{ready, Ready},
+    marker::PhantomData,
+    ops::Deref,
+    pin::Pin,
+    sync::Arc,
+    task::{ready, Context, Poll},
+};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse<S: Scheme>(value: &'static str) -> Option<S::Credentials> {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_static(value));
+        credentials::<S>(&headers)
+    }
+
+    #[test]
+    fn scheme() {
+        let cred = parse::<Basic>("Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ==").unwrap();
+        assert_eq!(cred.username, "Aladdin");
+        assert_eq!(cred.password, "open sesame");
+        assert_eq!(parse::<Basic>("basic   dTpwOmM=").unwrap().password, "p:c");
+        assert!(parse::<Basic>("Basic bm9jb2xvbg==").is_none());
+        assert!(parse::<Basic>("Bearer abc").is_none());
+
+        assert_eq!(parse::<Bearer>("Bearer abc.def-_~+/=").unwrap().0, "abc.def-_~+/=");
+        assert!(parse::<Bearer>("Bearer a b").is_none());
+        assert!(parse::<Bearer>("Basic abc").is_none());
+
+        assert_eq!(Basic::challenge("a\"b", false), "Basic realm=\"a\\\"b\", charset=\"UTF-8\"");
+        assert_eq!(Bearer::challenge("api", true), "Bearer realm=\"api\", error=\"invalid_token\"");
+    }
+}
+
+/// authentication scheme
+pub trait Scheme: Send + Sync + 'static {
+    /// scheme name, compared case insensitively
+    const NAME: &'static str;
+
+    /// credentials parsed from `Authorization` header
+    type Credentials: Send + 'static;
+
+    /// parse the value after the scheme name
+    fn parse(params: &str) -> Option<Self::Credentials>;
+
+    /// value of `WWW-Authenticate` header
+    ///
+    /// `invalid` is `true` if credentials is present but rejected by the validator
+    fn challenge(realm: &str, invalid: bool) -> String;
+}
+
+/// `Basic` authentication scheme, [RFC7617](https://www.rfc-editor.org/rfc/rfc7617)
+#[derive(Debug)]
+pub struct Basic;
+
+/// credentials of [`Basic`] scheme
+#[derive(Clone, Debug)]
+pub struct BasicCredentials {
+    pub username: String,
+    pub password: String,
+}
+
+impl Scheme for Basic {
+    const NAME: &'static str = "Basic";
+    type Credentials = BasicCredentials;
+
+    fn parse(params: &str) -> Option<Self::Credentials> {
+        let decoded = String::from_utf8(base64::decode(params)?).ok()?;
+        let (username, password) = decoded.split_once(':')?;
+        Some(BasicCredentials { username: username.to_owned(), password: password.to_owned() })
+    }
+
+    fn challenge(realm: &str, _: bool) -> String {
+        format!("Basic realm=\"{}\", charset=\"UTF-8\"", Quoted(realm))
+    }
+}
+
+/// `Bearer` authentication scheme, [RFC6750](https://www.rfc-editor.org/rfc/rfc6750)
+#[derive(Debug)]
+pub struct Bearer;
+
+/// credentials of [`Bearer`] scheme
+#[derive(Clone, Debug)]
+pub struct BearerToken(pub String);
+
+impl Scheme for Bearer {
+    const NAME: &'static str = "Bearer";
+    type Credentials = BearerToken;
+
+    fn parse(params: &str) -> Option<Self::Credentials> {
+        let valid = !params.is_empty() && params
+            .trim_end_matches('=')
+            .bytes()
+            .all(|e|e.is_ascii_alphanumeric() || b"-._~+/".contains(&e));
+        valid.then(||BearerToken(params.to_owned()))
+    }
+
+    fn challenge(realm: &str, invalid: bool) -> String {
+        match invalid {
+            true => format!("Bearer realm=\"{}\", error=\"invalid_token\"", Quoted(realm)),
+            false => format!("Bearer realm=\"{}\"", Quoted(realm)),
+        }
+    }
+}
+
+/// write escaped quoted string content
+struct Quoted<'a>(&'a str);
+
+impl fmt::Display for Quoted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for ch in self.0.chars() {
+            if matches!(ch, '"' | '\\') {
+                f.write_str("\\")?;
+            }
+            fmt::Write::write_char(f, ch)?;
+        }
+        Ok(())
+    }
+}
+
+/// parse credentials of given scheme from `Authorization` header
+pub(super) fn credentials<S: Scheme>(headers: &HeaderMap) -> Option<S::Credentials> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, params) = value.split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case(S::NAME) {
+        return None;
+    }
+    S::parse(params.trim())
+}
+
+/// authenticated principal
+///
+/// when used as extractor, [`Auth`] must be applied
+#[derive(Clone, Debug)]
+pub struct Principal<T>(pub T);
+
+impl<T> Deref for Principal<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> FromRequestParts for Principal<T>
+where
+    T: Clone + Send + Sync + 'static,
+{
+    type Error = MissingPrincipal;
+    type Future = Ready<Result<Self,Self::Error>>;
+
+    fn from_request_parts(parts: &mut request::Parts) -> Self::Future {
+        ready(parts.extensions.get::<Principal<T>>().cloned().ok_or(MissingPrincipal))
+    }
+}
+
+/// error returned when [`Auth`] is not applied
+#[derive(thiserror::Error, Debug)]
+#[error("authenticated principal is not available")]
+pub struct MissingPrincipal;
+
+impl IntoResponse for MissingPrincipal {
+    fn into_response(self) -> Response {
+        (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()).into_response()
+    }
+}
+
+/// authentication layer
+///
+/// see [module level documentation](self) for more details
+pub struct Auth<A, F> {
+    realm: Arc<str>,
+    validator: F,
+    _scheme: PhantomData<fn() -> A>,
+}
+
+/// authentication layer using [`Basic`] scheme
+pub type BasicAuth<F> = Auth<Basic, F>;
+
+/// authentication layer using [`Bearer`] scheme
+pub type BearerAuth<F> = Auth<Bearer, F>;
+
+impl<F> Auth<Basic, F> {
+    /// create new `Basic` authentication layer
+    pub fn basic(realm: impl Into<Arc<str>>, validator: F) -> Self {
+        Self::new(realm, validator)
+    }
+}
+
+impl<F> Auth<Bearer, F> {
+    /// create new `Bearer` authentication layer
+    pub fn bearer(realm: impl Into<Arc<str>>, validator: F) -> Self {
+        Self::new(realm, validator)
+    }
+}
+
+impl<A, F> Auth<A, F> {
+    /// create new `Auth` with given scheme
+    pub fn new(realm: impl Into<Arc<str>>, validator: F) -> Self {
+        Self { realm: realm.into(), validator, _scheme: PhantomData }
+    }
+}
+
+impl<A, F: Clone> Clone for Auth<A, F> {
+    fn clone(&self) -> Self {
+        Self { realm: self.realm.clone(), validator: self.validator.clone(), _scheme: PhantomData }
+    }
+}
+
+impl<A, F, S> Layer<S> for Auth<A, F>
+where
+    F: Clone,
+{
+    type Service = AuthService<A, F, S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AuthService { auth: self.clone(), inner: Arc::new(inner) }
+    }
+}
+
+/// service that authenticate request
+pub struct AuthService<A, F, S> {
+    auth: Auth<A, F>,
+    inner: Arc<S>,
+}
+
+impl<A, F: Clone, S> Clone for AuthService<A, F, S> {
+    fn clone(&self) -> Self {
+        Self { auth: self.auth.clone(), inner: self.inner.clone() }
+    }
+}
+
+impl<A, F, Fut, T, S, B> Service<Request<B>> for AuthService<A, F, S>
+where
+    A: Scheme,
+    F: Fn(A::Credentials) -> Fut,
+    Fut: Future<Output = Option<T>>,
+    T: Clone + Send + Sync + 'static,
+    S: Service<Request<B>, Response = Response>,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = AuthFuture<A, Fut, S, B>;
+
+    fn call(&self, req: Request<B>) -> Self::Future {
+        let state = match credentials::<A>(req.headers()) {
+            Some(cred) => State::Validate { f: (self.auth.validator)(cred), req: Some(req) },
+            None => State::Done { res: Some(unauthorized::<A>(&self.auth.realm, false)) },
+        };
+        AuthFuture { state, inner: self.inner.clone(), realm: self.auth.realm.clone(), _scheme: PhantomData }
+    }
+}
+
+pub(super) fn unauthorized<A: Scheme>(realm: &str, invalid: bool) -> Response {
+    let mut res = (StatusCode::UNAUTHORIZED, "unauthorized").into_response();
+    if let Ok(value) = HeaderValue::from_str(&A::challenge(realm, invalid)) {
+        res.headers_mut().insert(header::WWW_AUTHENTICATE, value);
+    }
+    res
+}
+
+pin_project_lite::pin_project! {
+    #[project = StateProj]
+    enum State<Fut, F, B> {
+        Validate { #[pin] f: Fut, req: Option<Request<B>> },
+        Inner { #[pin] f: F },
+        Done { res: Option<Response> },
+    }
+}
+
+pin_project_lite::pin_project! {
+    /// future returned from [`AuthService`]
+    pub struct AuthFuture<A, Fut, S, B>
+    where
+        S: Service<Request<B>>,
+    {
+        #[pin]
+        state: State<Fut, S::Future, B>,
+        inner: Arc<S>,
+        realm: Arc<str>,
+        _scheme: PhantomData<fn() -> A>,
+    }
+}
+
+impl<A, Fut, T, S, B> Future for AuthFuture<A, Fut, S, B>
+where
+    A: Scheme,
+    Fut: Future<Output = Option<T>>,
+    T: Clone + Send + Sync + 'static,
+    S: Service<Request<B>, Response = Response>,
+{
+    type Output = Result<Response, S::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut me = self.project();
+        loop {
+            match me.state.as_mut().project() {
+                StateProj::Validate { f, req } => {
+                    let principal = ready!(f.poll(cx));
+                    let mut req = req.take().expect("poll after complete");
+                    let next = match principal {
+                        Some(principal) => {
+                            req.extensions_mut().insert(Principal(principal));
+                            State::Inner { f: me.inner.call(req) }
+                        }
+                        None => State::Done { res: Some(unauthorized::<A>(me.realm, true)) },
+                    };
+                    me.state.set(next);
+                }
+                StateProj::Inner { f } => return f.poll(cx),
+                StateProj::Done { res } => return Poll::Ready(Ok(res.take().expect("poll after complete"))),
+            }
+        }
+    }
+}
@@ -0,0 +1,231 @@
+//! panic recovery
+//!
+//! [`CatchPanic`] catch panic of the inner service and respond with
+//! `500 Internal Server Error`, so the connection stay usable instead of
+//! being reset
+//!
+//! the panic message, location and backtrace is logged through the [`log`]
+//! facade, the location and backtrace is captured by a panic hook installed
+//! when [`CatchPanic`] is created, the hook chain to the previous one so the
+//! default panic output is kept
+//!
+//! the backtrace is only captured when enabled with `RUST_BACKTRACE` or
+//! `RUST_LIB_BACKTRACE`, see [`Backtrace::capture`]
+//!
+//! # Example
+//!
+//! ```no_run
+//! use vice::{middleware::catch_panic::CatchPanic, router::{Router, get}};
+//!
+//! async fn handle() -> &'static str {
+//!     panic!("oops")
+//! }
+//!
+//! fn main() -> std::io::Result<()> {
+//!     let route = Router::new()
+//!         .route("/", get(handle))
+//!         .layer(CatchPanic::new());
+//!     vice::listen("0.0.0.0:3000", route)
+//! }
+//! ```
+use super::Layer;
+use crate::http::{IntoResponse, Request, Response};
+use bytes::Bytes;
+use http::StatusCode;
+use hyper::service::Service;
+use std::{
+    any::Any,
+    backtrace::{Backtrace, BacktraceStatus},
+    cell::{Cell, RefCell},
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    sync::Once,
+    task::{Context, Poll},
+};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::convert::Infallible;
+
+    #[test]
+    fn catch() {
+        let service = CatchPanic::new().body("oops").layer(hyper::service::service_fn(|req: Request| async move {
+            if req.uri() == "/panic" {
+                panic!("handler panicked");
+            }
+            Ok::<Response,Infallible>(Response::default())
+        }));
+
+        let mut cx = Context::from_waker(std::task::Waker::noop());
+        let mut poll = |uri: &'static str| {
+            let mut req = Request::new(Default::default());
+            *req.uri_mut() = uri.parse().unwrap();
+            let mut f = std::pin::pin!(service.call(req));
+            match f.as_mut().poll(&mut cx) {
+                Poll::Ready(Ok(res)) => res,
+                _ => unreachable!(),
+            }
+        };
+
+        let res = poll("/panic");
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(res.body().as_bytes(), Some(&b"oops"[..]));
+        assert_eq!(poll("/").status(), StatusCode::OK);
+
+        // stale capture is not attached to a later panic
+        CAUGHT.set(Some((String::from("stale"), Backtrace::disabled())));
+        assert!(super::catch(||()).is_ok());
+        assert!(CAUGHT.take().is_none());
+        let res = poll("/panic");
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(CAUGHT.take().is_none());
+    }
+}
+
+thread_local! {
+    /// number of [`CatchPanic`] being polled on current thread
+    static CATCHING: Cell<usize> = const { Cell::new(0) };
+    /// panic location and backtrace captured by the panic hook
+    static CAUGHT: RefCell<Option<(String, Backtrace)>> = const { RefCell::new(None) };
+}
+
+static HOOK: Once = Once::new();
+
+/// install panic hook that capture backtrace of caught panic
+fn install_hook() {
+    HOOK.call_once(|| {
+        let prev = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if CATCHING.get() != 0 {
+                let location = info.location().map(ToString::to_string).unwrap_or_default();
+                CAUGHT.set(Some((location, Backtrace::capture())));
+            }
+            prev(info);
+        }));
+    });
+}
+
+/// run `f` while marking current thread as catching panic
+fn catch<T>(f: impl FnOnce() -> T) -> Result<T, Box<dyn Any + Send>> {
+    // discard capture of a previous panic that was not consumed
+    CAUGHT.take();
+    CATCHING.set(CATCHING.get() + 1);
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    CATCHING.set(CATCHING.get() - 1);
+    result
+}
+
+/// panic recovery layer
+///
+/// see [module level documentation](self) for more details
+#[derive(Clone, Debug)]
+pub struct CatchPanic {
+    body: Bytes,
+}
+
+impl CatchPanic {
+    /// create new `CatchPanic`, this also install the panic hook
+    pub fn new() -> Self {
+        install_hook();
+        Self { body: Bytes::from_static(b"internal server error") }
+    }
+
+    /// body of panicked response
+    pub fn body(mut self, body: impl Into<Bytes>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    fn response(&self, payload: Box<dyn Any + Send>) -> Response {
+        let message = payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(||payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("Box<dyn Any>");
+
+        match CAUGHT.take() {
+            Some((location, backtrace)) if backtrace.status() == BacktraceStatus::Captured => {
+                log::error!("service panicked at {location}: {message}\n{backtrace}");
+            }
+            Some((location, _)) => log::error!("service panicked at {location}: {message}"),
+            None => log::error!("service panicked: {message}"),
+        }
+
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ("Content-Type", "text/plain"),
+            self.body.clone(),
+        ).into_response()
+    }
+}
+
+impl Default for CatchPanic {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Layer<S> for CatchPanic {
+    type Service = CatchPanicService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CatchPanicService { inner, catch: self.clone() }
+    }
+}
+
+/// service that catch panic of the inner service
+#[derive(Clone)]
+pub struct CatchPanicService<S> {
+    inner: S,
+    catch: CatchPanic,
+}
+
+impl<S,B> Service<Request<B>> for CatchPanicService<S>
+where
+    S: Service<Request<B>, Response = Response>,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = CatchPanicFuture<S::Future>;
+
+    fn call(&self, req: Request<B>) -> Self::Future {
+        match catch(||self.inner.call(req)) {
+            Ok(f) => CatchPanicFuture::Inner { f, catch: Some(self.catch.clone()) },
+            Err(payload) => CatchPanicFuture::Panicked { res: Some(self.catch.response(payload)) },
+        }
+    }
+}
+
+pin_project_lite::pin_project! {
+    /// future returned from [`CatchPanicService`]
+    #[project = CatchPanicProj]
+    pub enum CatchPanicFuture<F> {
+        Inner { #[pin] f: F, catch: Option<CatchPanic> },
+        Panicked { res: Option<Response> },
+    }
+}
+
+impl<F,E> Future for CatchPanicFuture<F>
+where
+    F: Future<Output = Result<Response,E>>,
+{
+    type Output = Result<Response,E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project() {
+            CatchPanicProj::Inner { f, catch } => match catch_poll(f, cx) {
+                Ok(poll) => poll,
+                Err(payload) => {
+                    let catch = catch.take().expect("poll after panic");
+                    Poll::Ready(Ok(catch.response(payload)))
+                }
+            },
+            CatchPanicProj::Panicked { res } => Poll::Ready(Ok(res.take().expect("poll after complete"))),
+        }
+    }
+}
+
+fn catch_poll<F: Future>(f: Pin<&mut F>, cx: &mut Context<'_>) -> Result<Poll<F::Output>, Box<dyn Any + Send>> {
+    catch(||f.poll(cx))
+}
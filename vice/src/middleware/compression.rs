@@ -0,0 +1,524 @@
+//! response compression
+//!
+//! [`Compression`] negotiate `Accept-Encoding` and compress the response body
+//! using gzip, deflate, brotli or zstd
+//!
+//! response is not compressed when:
+//!
+//! - it already have `Content-Encoding`
+//! - the content type is already compressed, like images, audio, video or archives
+//! - the body size is known and smaller than the threshold
+//! - status is `1xx`, `204 No Content`, `206 Partial Content` or `304 Not Modified`
+//! - `Cache-Control` contains `no-transform`
+//!
+//! streaming body is compressed per chunk, every chunk is flushed so the client
+//! receive data as soon as the inner body produce it
+//!
+//! # Example
+//!
+//! ```no_run
+//! use vice::{middleware::compression::Compression, router::{Router, get}};
+//!
+//! fn main() -> std::io::Result<()> {
+//!     let route = Router::new()
+//!         .route("/", get(||async { "Vice Dev" }))
+//!         .layer(Compression::new().min_size(256));
+//!     vice::listen("0.0.0.0:3000", route)
+//! }
+//! ```
+use super::Layer;
+use crate::http::{
+    body::{BoxError, Body},
+    Request, Response,
+};
+use bytes::Bytes;
+use http::{header, HeaderMap, HeaderValue, Method, StatusCode};
+use hyper::{
+    body::{Body as HttpBody, Frame},
+    service::Service,
+};
+use std::{
+    io::{self, Write},
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Read;
+
+    fn negotiate(accept: &'static str) -> Option<Encoding> {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT_ENCODING, HeaderValue::from_static(accept));
+        Compression::new().negotiate(&headers)
+    }
+
+    #[test]
+    fn negotiation() {
+        assert_eq!(negotiate("gzip, deflate, br, zstd"), Some(Encoding::Zstd));
+        assert_eq!(negotiate("gzip;q=1.0, br;q=0.8"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("br;q=0, *;q=0.5"), Some(Encoding::Zstd));
+        assert_eq!(negotiate("GZIP;Q=0.1, identity"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("identity, gzip;q=0"), None);
+        assert_eq!(negotiate("compress"), None);
+    }
+
+    fn collect(mut body: Body) -> Vec<u8> {
+        let mut cx = Context::from_waker(std::task::Waker::noop());
+        let mut out = Vec::new();
+        while let Poll::Ready(Some(frame)) = Pin::new(&mut body).poll_frame(&mut cx) {
+            out.extend_from_slice(&frame.unwrap().into_data().unwrap());
+        }
+        out
+    }
+
+    #[test]
+    fn compress() {
+        let text = "vice ".repeat(1024);
+        let mut res = Response::new(Body::from(text.clone()));
+        res.headers_mut().insert(header::CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+        res.headers_mut().insert(header::CONTENT_LENGTH, HeaderValue::from(text.len()));
+        res.headers_mut().insert(header::ETAG, HeaderValue::from_static("\"v1\""));
+
+        let res = Compression::new().compress(res, Some(Encoding::Gzip), false);
+        assert_eq!(res.headers()[header::CONTENT_ENCODING], "gzip");
+        assert_eq!(res.headers()[header::VARY], "accept-encoding");
+        assert_eq!(res.headers()[header::ETAG], "W/\"v1\"");
+        assert!(!res.headers().contains_key(header::CONTENT_LENGTH));
+
+        let body = collect(res.into_body());
+        let mut out = String::new();
+        flate2::read::GzDecoder::new(&body[..]).read_to_string(&mut out).unwrap();
+        assert_eq!(out, text);
+
+        let mut res = Response::new(Body::from("small"));
+        res.headers_mut().insert(header::CONTENT_TYPE, HeaderValue::from_static("image/png"));
+        let res = Compression::new().min_size(0).compress(res, Some(Encoding::Gzip), false);
+        assert!(!res.headers().contains_key(header::CONTENT_ENCODING));
+        assert!(!res.headers().contains_key(header::VARY));
+
+        let mut res = Response::new(Body::from(text.clone()));
+        res.headers_mut().insert(header::VARY, HeaderValue::from_static("Origin, Accept-Encoding"));
+        let res = Compression::new().compress(res, Some(Encoding::Gzip), false);
+        assert_eq!(res.headers().get_all(header::VARY).iter().count(), 1);
+    }
+}
+
+/// content coding supported by [`Compression`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    /// `gzip`
+    Gzip,
+    /// `deflate`, zlib format as specified by HTTP
+    Deflate,
+    /// `br`
+    Brotli,
+    /// `zstd`
+    Zstd,
+}
+
+impl Encoding {
+    /// server preference when client q-values are equal
+    const PREFERENCE: [Encoding; 4] = [Encoding::Zstd, Encoding::Brotli, Encoding::Gzip, Encoding::Deflate];
+
+    /// returns the content coding token
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+            Encoding::Brotli => "br",
+            Encoding::Zstd => "zstd",
+        }
+    }
+
+    /// parse content coding token, case insensitive
+    pub fn from_token(token: &str) -> Option<Self> {
+        Self::PREFERENCE.into_iter().find(|e|e.as_str().eq_ignore_ascii_case(token))
+    }
+}
+
+/// compression level
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Level {
+    /// fastest compression
+    Fastest,
+    /// balance between speed and size
+    #[default]
+    Default,
+    /// smallest size
+    Best,
+}
+
+/// response compression layer
+///
+/// see [module level documentation](self) for more details
+#[derive(Clone, Debug)]
+pub struct Compression {
+    enabled: [bool; 4],
+    min_size: u64,
+    level: Level,
+}
+
+impl Compression {
+    /// create new `Compression` with every encoding enabled and 1024 bytes threshold
+    pub fn new() -> Self {
+        Self { enabled: [true; 4], min_size: 1024, level: Level::Default }
+    }
+
+    /// enable or disable gzip
+    pub fn gzip(self, enable: bool) -> Self {
+        self.set(Encoding::Gzip, enable)
+    }
+
+    /// enable or disable deflate
+    pub fn deflate(self, enable: bool) -> Self {
+        self.set(Encoding::Deflate, enable)
+    }
+
+    /// enable or disable brotli
+    pub fn br(self, enable: bool) -> Self {
+        self.set(Encoding::Brotli, enable)
+    }
+
+    /// enable or disable zstd
+    pub fn zstd(self, enable: bool) -> Self {
+        self.set(Encoding::Zstd, enable)
+    }
+
+    /// minimum body size in bytes to be compressed, default to 1024
+    ///
+    /// streaming body with unknown size is always compressed
+    pub fn min_size(mut self, min_size: u64) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    /// compression level, default to [`Level::Default`]
+    pub fn level(mut self, level: Level) -> Self {
+        self.level = level;
+        self
+    }
+
+    fn set(mut self, encoding: Encoding, enable: bool) -> Self {
+        self.enabled[encoding as usize] = enable;
+        self
+    }
+
+    fn is_enabled(&self, encoding: Encoding) -> bool {
+        self.enabled[encoding as usize]
+    }
+
+    /// select encoding with highest q-value from `Accept-Encoding`
+    fn negotiate(&self, headers: &HeaderMap) -> Option<Encoding> {
+        let mut q_values = [None::<u16>; 4];
+        let mut wildcard = None;
+
+        for value in headers.get_all(header::ACCEPT_ENCODING) {
+            let Ok(value) = value.to_str() else { continue };
+            for item in value.split(',') {
+                let mut params = item.split(';');
+                let token = params.next().unwrap_or_default().trim();
+                let q = params
+                    .filter_map(|e|e.trim().split_once('='))
+                    .find(|(k,_)|k.eq_ignore_ascii_case("q"))
+                    .map_or(Some(1000), |(_,v)|parse_q(v.trim()));
+                let Some(q) = q else { continue };
+
+                if token == "*" {
+                    wildcard = Some(q);
+                } else if let Some(encoding) = Encoding::from_token(token) {
+                    q_values[encoding as usize] = Some(q);
+                }
+            }
+        }
+
+        Encoding::PREFERENCE
+            .into_iter()
+            .filter(|e|self.is_enabled(*e))
+            .filter_map(|e|Some((e, q_values[e as usize].or(wildcard)?)))
+            .filter(|(_,q)|*q > 0)
+            .fold(None, |best: Option<(Encoding, u16)>, (e,q)| match best {
+                Some((_,best_q)) if best_q >= q => best,
+                _ => Some((e,q)),
+            })
+            .map(|(e,_)|e)
+    }
+
+    /// compress response if applicable
+    fn compress(&self, mut res: Response, encoding: Option<Encoding>, is_head: bool) -> Response {
+        if !is_compressible(&res) {
+            return res;
+        }
+
+        add_vary(res.headers_mut());
+
+        let Some(encoding) = encoding else {
+            return res;
+        };
+        if is_head || res.body().size_hint().exact().is_some_and(|e|e < self.min_size) {
+            return res;
+        }
+
+        let headers = res.headers_mut();
+        headers.insert(header::CONTENT_ENCODING, HeaderValue::from_static(encoding.as_str()));
+        headers.remove(header::CONTENT_LENGTH);
+        headers.remove(header::ACCEPT_RANGES);
+        if let Some(etag) = headers.get(header::ETAG)
+            && !etag.as_bytes().starts_with(b"W/")
+        {
+            let mut weak = b"W/".to_vec();
+            weak.extend_from_slice(etag.as_bytes());
+            if let Ok(weak) = HeaderValue::from_bytes(&weak) {
+                headers.insert(header::ETAG, weak);
+            }
+        }
+
+        res.map(|body| Body::new(CompressBody {
+            inner: body,
+            encoder: Some(Encoder::new(encoding, self.level)),
+            trailers: None,
+        }))
+    }
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Layer<S> for Compression {
+    type Service = CompressionService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CompressionService { inner, compression: self.clone() }
+    }
+}
+
+/// add `accept-encoding` to `Vary` unless it is already covered
+fn add_vary(headers: &mut HeaderMap) {
+    let covered = headers
+        .get_all(header::VARY)
+        .iter()
+        .filter_map(|e|e.to_str().ok())
+        .flat_map(|e|e.split(','))
+        .map(str::trim)
+        .any(|e|e == "*" || e.eq_ignore_ascii_case("accept-encoding"));
+    if !covered {
+        headers.append(header::VARY, HeaderValue::from_static("accept-encoding"));
+    }
+}
+
+/// parse q-value into thousandths
+fn parse_q(value: &str) -> Option<u16> {
+    let (int, frac) = value.split_once('.').unwrap_or((value, ""));
+    if frac.len() > 3 || !frac.bytes().all(|e|e.is_ascii_digit()) {
+        return None;
+    }
+    let frac = format!("{frac:0<3}").parse::<u16>().ok()?;
+    match int {
+        "0" => Some(frac),
+        "1" if frac == 0 => Some(1000),
+        _ => None,
+    }
+}
+
+fn is_compressible(res: &Response) -> bool {
+    let status = res.status();
+    if status.is_informational()
+        || matches!(status, StatusCode::NO_CONTENT | StatusCode::PARTIAL_CONTENT | StatusCode::NOT_MODIFIED)
+    {
+        return false;
+    }
+
+    let headers = res.headers();
+    if headers.contains_key(header::CONTENT_ENCODING) || headers.contains_key(header::CONTENT_RANGE) {
+        return false;
+    }
+
+    let no_transform = headers
+        .get_all(header::CACHE_CONTROL)
+        .iter()
+        .filter_map(|e|e.to_str().ok())
+        .flat_map(|e|e.split(','))
+        .any(|e|e.trim().eq_ignore_ascii_case("no-transform"));
+    if no_transform {
+        return false;
+    }
+
+    let Some(content_type) = headers.get(header::CONTENT_TYPE).and_then(|e|e.to_str().ok()) else {
+        return true;
+    };
+    let mime = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    let already_compressed = match mime.split_once('/') {
+        Some(("image", sub)) => sub != "svg+xml",
+        Some(("audio" | "video", _)) => true,
+        Some(("font", sub)) => matches!(sub, "woff" | "woff2"),
+        Some(("application", sub)) => matches!(
+            sub,
+            "zip" | "gzip" | "x-gzip" | "zstd" | "x-bzip2" | "x-xz" | "x-7z-compressed"
+                | "x-rar-compressed" | "vnd.rar" | "pdf" | "wasm"
+        ),
+        _ => false,
+    };
+    !already_compressed
+}
+
+/// streaming encoder writing into in memory buffer
+enum Encoder {
+    Gzip(flate2::write::GzEncoder<Vec<u8>>),
+    Deflate(flate2::write::ZlibEncoder<Vec<u8>>),
+    Brotli(Box<brotli::CompressorWriter<Vec<u8>>>),
+    Zstd(zstd::stream::write::Encoder<'static, Vec<u8>>),
+}
+
+impl Encoder {
+    fn new(encoding: Encoding, level: Level) -> Self {
+        let flate = match level {
+            Level::Fastest => flate2::Compression::fast(),
+            Level::Default => flate2::Compression::default(),
+            Level::Best => flate2::Compression::best(),
+        };
+        match encoding {
+            Encoding::Gzip => Self::Gzip(flate2::write::GzEncoder::new(Vec::new(), flate)),
+            Encoding::Deflate => Self::Deflate(flate2::write::ZlibEncoder::new(Vec::new(), flate)),
+            Encoding::Brotli => {
+                let quality = match level {
+                    Level::Fastest => 1,
+                    Level::Default => 4,
+                    Level::Best => 11,
+                };
+                Self::Brotli(Box::new(brotli::CompressorWriter::new(Vec::new(), 4096, quality, 22)))
+            }
+            Encoding::Zstd => {
+                let level = match level {
+                    Level::Fastest => 1,
+                    Level::Default => zstd::DEFAULT_COMPRESSION_LEVEL,
+                    Level::Best => 19,
+                };
+                let encoder = zstd::stream::write::Encoder::new(Vec::new(), level)
+                    .expect("zstd encoder with valid level");
+                Self::Zstd(encoder)
+            }
+        }
+    }
+
+    /// compress and flush data, returns the produced output
+    fn write(&mut self, data: &[u8]) -> io::Result<Bytes> {
+        let buf = match self {
+            Encoder::Gzip(e) => { e.write_all(data)?; e.flush()?; e.get_mut() }
+            Encoder::Deflate(e) => { e.write_all(data)?; e.flush()?; e.get_mut() }
+            Encoder::Brotli(e) => { e.write_all(data)?; e.flush()?; e.get_mut() }
+            Encoder::Zstd(e) => { e.write_all(data)?; e.flush()?; e.get_mut() }
+        };
+        Ok(std::mem::take(buf).into())
+    }
+
+    /// finish the stream, returns the remaining output
+    fn finish(self) -> io::Result<Bytes> {
+        let buf = match self {
+            Encoder::Gzip(e) => e.finish()?,
+            Encoder::Deflate(e) => e.finish()?,
+            Encoder::Brotli(e) => e.into_inner(),
+            Encoder::Zstd(e) => e.finish()?,
+        };
+        Ok(buf.into())
+    }
+}
+
+/// body that compress the inner body
+struct CompressBody {
+    inner: Body,
+    encoder: Option<Encoder>,
+    trailers: Option<HeaderMap>,
+}
+
+impl HttpBody for CompressBody {
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let me = self.get_mut();
+        loop {
+            let Some(encoder) = &mut me.encoder else {
+                return Poll::Ready(me.trailers.take().map(|e|Ok(Frame::trailers(e))));
+            };
+
+            let data = match ready!(Pin::new(&mut me.inner).poll_frame(cx)) {
+                Some(Ok(frame)) => match frame.into_data() {
+                    Ok(data) => encoder.write(&data)?,
+                    Err(frame) => {
+                        me.trailers = frame.into_trailers().ok();
+                        me.encoder.take().expect("checked above").finish()?
+                    }
+                },
+                Some(Err(err)) => return Poll::Ready(Some(Err(err))),
+                None => me.encoder.take().expect("checked above").finish()?,
+            };
+
+            if !data.is_empty() {
+                return Poll::Ready(Some(Ok(Frame::data(data))));
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.encoder.is_none() && self.trailers.is_none()
+    }
+}
+
+/// service that compress response
+#[derive(Clone)]
+pub struct CompressionService<S> {
+    inner: S,
+    compression: Compression,
+}
+
+impl<S,B> Service<Request<B>> for CompressionService<S>
+where
+    S: Service<Request<B>, Response = Response>,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = CompressionFuture<S::Future>;
+
+    fn call(&self, req: Request<B>) -> Self::Future {
+        let encoding = self.compression.negotiate(req.headers());
+        let is_head = req.method() == Method::HEAD;
+        CompressionFuture {
+            inner: self.inner.call(req),
+            compression: Some(self.compression.clone()),
+            encoding,
+            is_head,
+        }
+    }
+}
+
+pin_project_lite::pin_project! {
+    /// future returned from [`CompressionService`]
+    pub struct CompressionFuture<F> {
+        #[pin]
+        inner: F,
+        compression: Option<Compression>,
+        encoding: Option<Encoding>,
+        is_head: bool,
+    }
+}
+
+impl<F,E> Future for CompressionFuture<F>
+where
+    F: Future<Output = Result<Response,E>>,
+{
+    type Output = Result<Response,E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let me = self.project();
+        let res = ready!(me.inner.poll(cx));
+        let compression = me.compression.take().expect("poll after complete");
+        Poll::Ready(res.map(|res|compression.compress(res, *me.encoding, *me.is_head)))
+    }
+}
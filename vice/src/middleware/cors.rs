@@ -0,0 +1,416 @@
+//! cross origin resource sharing
+//!
+//! [`Cors`] add CORS headers to responses of allowed origin, preflight `OPTIONS`
+//! request is answered directly without reaching the inner service
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use vice::{
+//!     http::Method,
+//!     middleware::cors::{AllowOrigin, Cors},
+//!     router::{Router, get},
+//! };
+//!
+//! fn main() -> std::io::Result<()> {
+//!     let cors = Cors::new()
+//!         .allow_origin(AllowOrigin::list(["https://app.example.com", "https://admin.example.com"]))
+//!         .allow_methods([Method::GET, Method::POST])
+//!         .allow_credentials(true)
+//!         .max_age(Duration::from_secs(600));
+//!
+//!     let route = Router::new()
+//!         .route("/", get(||async { "Vice Dev" }))
+//!         .layer(cors);
+//!     vice::listen("0.0.0.0:3000", route)
+//! }
+//! ```
+use super::Layer;
+use crate::http::{IntoResponse, Request, Response};
+use http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode};
+use hyper::service::Service;
+use std::{
+    fmt,
+    pin::Pin,
+    sync::Arc,
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn headers(pairs: &[(HeaderName, &'static str)]) -> HeaderMap {
+        pairs.iter().map(|(k,v)|(k.clone(), HeaderValue::from_static(v))).collect()
+    }
+
+    #[test]
+    fn preflight() {
+        let cors = Cors::new()
+            .allow_origin(AllowOrigin::exact("https://a.com"))
+            .allow_headers([header::CONTENT_TYPE])
+            .allow_credentials(true)
+            .max_age(Duration::from_secs(60));
+
+        let res = cors.preflight(&headers(&[
+            (header::ORIGIN, "https://a.com"),
+            (header::ACCESS_CONTROL_REQUEST_METHOD, "POST"),
+        ]));
+        let h = res.headers();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert_eq!(h[header::ACCESS_CONTROL_ALLOW_ORIGIN], "https://a.com");
+        assert_eq!(h[header::ACCESS_CONTROL_ALLOW_METHODS], "GET,HEAD,POST");
+        assert_eq!(h[header::ACCESS_CONTROL_ALLOW_HEADERS], "content-type");
+        assert_eq!(h[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        assert_eq!(h[header::ACCESS_CONTROL_MAX_AGE], "60");
+
+        let res = cors.preflight(&headers(&[
+            (header::ORIGIN, "https://b.com"),
+            (header::ACCESS_CONTROL_REQUEST_METHOD, "POST"),
+        ]));
+        assert!(!res.headers().contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+        let vary = res.headers().get_all(header::VARY).iter().collect::<Vec<_>>();
+        assert_eq!(vary, ["origin", "access-control-request-method, access-control-request-headers"]);
+    }
+
+    #[test]
+    fn permissive() {
+        let cors = Cors::permissive();
+        let res = cors.preflight(&headers(&[
+            (header::ORIGIN, "https://a.com"),
+            (header::ACCESS_CONTROL_REQUEST_METHOD, "PATCH"),
+            (header::ACCESS_CONTROL_REQUEST_HEADERS, "x-token"),
+        ]));
+        let h = res.headers();
+        assert_eq!(h[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+        assert_eq!(h[header::ACCESS_CONTROL_ALLOW_METHODS], "PATCH");
+        assert_eq!(h[header::ACCESS_CONTROL_ALLOW_HEADERS], "x-token");
+
+        let mut h = HeaderMap::new();
+        let origin = HeaderValue::from_static("https://a.com");
+        cors.expose_headers([header::ETAG]).apply(Some(&origin), &mut h);
+        assert_eq!(h[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+        assert_eq!(h[header::ACCESS_CONTROL_EXPOSE_HEADERS], "etag");
+        assert!(!h.contains_key(header::VARY));
+
+        // mirroring every origin with credentials must be explicit
+        let cors = Cors::new().allow_credentials(true).allow_origin(AllowOrigin::predicate(|_|true));
+        let mut h = HeaderMap::new();
+        cors.apply(Some(&origin), &mut h);
+        assert_eq!(h[header::ACCESS_CONTROL_ALLOW_ORIGIN], "https://a.com");
+        assert_eq!(h[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        assert_eq!(h[header::VARY], "origin");
+
+        assert!(std::panic::catch_unwind(||Cors::permissive().allow_credentials(true)).is_err());
+        assert!(std::panic::catch_unwind(||Cors::new().allow_credentials(true).allow_origin(AllowOrigin::Any)).is_err());
+    }
+}
+
+/// allowed origin of [`Cors`]
+#[derive(Clone)]
+pub enum AllowOrigin {
+    /// allow any origin
+    Any,
+    /// allow origin that exactly match one of the list
+    List(Arc<[HeaderValue]>),
+    /// allow origin that the predicate returns `true`
+    Predicate(Arc<dyn Fn(&HeaderValue) -> bool + Send + Sync>),
+}
+
+impl AllowOrigin {
+    /// allow any origin
+    pub fn any() -> Self {
+        Self::Any
+    }
+
+    /// allow single origin
+    ///
+    /// # Panics
+    ///
+    /// panics if origin is not a valid header value
+    pub fn exact(origin: &str) -> Self {
+        Self::list([origin])
+    }
+
+    /// allow list of origin
+    ///
+    /// # Panics
+    ///
+    /// panics if any origin is not a valid header value
+    pub fn list<'a>(origins: impl IntoIterator<Item = &'a str>) -> Self {
+        Self::List(origins.into_iter().map(|e|HeaderValue::from_str(e).expect("invalid origin")).collect())
+    }
+
+    /// allow origin that the predicate returns `true`
+    pub fn predicate<F>(f: F) -> Self
+    where
+        F: Fn(&HeaderValue) -> bool + Send + Sync + 'static,
+    {
+        Self::Predicate(Arc::new(f))
+    }
+
+    fn is_allowed(&self, origin: &HeaderValue) -> bool {
+        match self {
+            AllowOrigin::Any => true,
+            AllowOrigin::List(list) => list.contains(origin),
+            AllowOrigin::Predicate(f) => f(origin),
+        }
+    }
+}
+
+impl fmt::Debug for AllowOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Any => f.write_str("Any"),
+            Self::List(list) => f.debug_tuple("List").field(list).finish(),
+            Self::Predicate(_) => f.write_str("Predicate"),
+        }
+    }
+}
+
+/// CORS configuration
+///
+/// see [module level documentation](self) for more details
+#[derive(Clone, Debug)]
+pub struct Cors {
+    origin: AllowOrigin,
+    /// `None` mirror the requested value
+    methods: Option<HeaderValue>,
+    /// `None` mirror the requested value
+    headers: Option<HeaderValue>,
+    expose: Option<HeaderValue>,
+    credentials: bool,
+    max_age: Option<HeaderValue>,
+}
+
+impl Cors {
+    /// create new `Cors` that does not allow any origin
+    ///
+    /// allowed methods default to `GET`, `HEAD` and `POST`, no request header is allowed
+    pub fn new() -> Self {
+        Self {
+            origin: AllowOrigin::List(Arc::new([])),
+            methods: Some(HeaderValue::from_static("GET,HEAD,POST")),
+            headers: Some(HeaderValue::from_static("")),
+            expose: None,
+            credentials: false,
+            max_age: None,
+        }
+    }
+
+    /// create new `Cors` that allow any origin, method and request header
+    pub fn permissive() -> Self {
+        Self::new().allow_origin(AllowOrigin::Any).allow_any_method().allow_any_header()
+    }
+
+    /// set allowed origin
+    ///
+    /// # Panics
+    ///
+    /// panics if [`AllowOrigin::Any`] is set while credentials is allowed,
+    /// see [`Cors::allow_credentials`]
+    pub fn allow_origin(mut self, origin: AllowOrigin) -> Self {
+        self.origin = origin;
+        self.check_credentials();
+        self
+    }
+
+    /// set allowed methods
+    pub fn allow_methods(mut self, methods: impl IntoIterator<Item = Method>) -> Self {
+        self.methods = Some(join(methods.into_iter().map(|e|e.to_string())));
+        self
+    }
+
+    /// allow any method by mirroring the requested method
+    pub fn allow_any_method(mut self) -> Self {
+        self.methods = None;
+        self
+    }
+
+    /// set allowed request headers
+    pub fn allow_headers(mut self, headers: impl IntoIterator<Item = HeaderName>) -> Self {
+        self.headers = Some(join(headers));
+        self
+    }
+
+    /// allow any request header by mirroring the requested headers
+    pub fn allow_any_header(mut self) -> Self {
+        self.headers = None;
+        self
+    }
+
+    /// set response headers exposed to the client
+    pub fn expose_headers(mut self, headers: impl IntoIterator<Item = HeaderName>) -> Self {
+        self.expose = Some(join(headers)).filter(|e|!e.is_empty());
+        self
+    }
+
+    /// whether to allow credentials, default to `false`
+    ///
+    /// # Panics
+    ///
+    /// panics if enabled with [`AllowOrigin::Any`], as that would let any site
+    /// make credentialed request, to really allow it, pass a predicate that
+    /// accept every origin with [`AllowOrigin::predicate`]
+    pub fn allow_credentials(mut self, credentials: bool) -> Self {
+        self.credentials = credentials;
+        self.check_credentials();
+        self
+    }
+
+    fn check_credentials(&self) {
+        assert!(
+            !(self.credentials && matches!(self.origin, AllowOrigin::Any)),
+            "credentials cannot be allowed with `AllowOrigin::Any`",
+        );
+    }
+
+    /// how long preflight response can be cached
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age.as_secs().into());
+        self
+    }
+
+    fn is_wildcard(&self) -> bool {
+        matches!(self.origin, AllowOrigin::Any)
+    }
+
+    /// write the allow origin and credentials headers, returns `false` if origin is not allowed
+    fn allow(&self, origin: Option<&HeaderValue>, headers: &mut HeaderMap) -> bool {
+        if !self.is_wildcard() {
+            headers.append(header::VARY, HeaderValue::from_static("origin"));
+        }
+
+        let Some(origin) = origin.filter(|e|self.origin.is_allowed(e)) else {
+            return false;
+        };
+
+        let value = match self.is_wildcard() {
+            true => HeaderValue::from_static("*"),
+            false => origin.clone(),
+        };
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, value);
+        if self.credentials {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_CREDENTIALS, HeaderValue::from_static("true"));
+        }
+        true
+    }
+
+    /// write CORS headers of actual request
+    fn apply(&self, origin: Option<&HeaderValue>, headers: &mut HeaderMap) {
+        if self.allow(origin, headers)
+            && let Some(expose) = &self.expose
+        {
+            headers.insert(header::ACCESS_CONTROL_EXPOSE_HEADERS, expose.clone());
+        }
+    }
+
+    /// response of preflight request
+    fn preflight(&self, req: &HeaderMap) -> Response {
+        let mut res = StatusCode::NO_CONTENT.into_response();
+        let headers = res.headers_mut();
+        let allowed = self.allow(req.get(header::ORIGIN), headers);
+        headers.append(header::VARY, HeaderValue::from_static("access-control-request-method, access-control-request-headers"));
+        if !allowed {
+            return res;
+        }
+
+        let methods = self.methods.as_ref().or_else(||req.get(header::ACCESS_CONTROL_REQUEST_METHOD));
+        if let Some(methods) = methods {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, methods.clone());
+        }
+        let allowed = self.headers.as_ref().or_else(||req.get(header::ACCESS_CONTROL_REQUEST_HEADERS));
+        if let Some(allowed) = allowed.filter(|e|!e.is_empty()) {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, allowed.clone());
+        }
+        if let Some(max_age) = &self.max_age {
+            headers.insert(header::ACCESS_CONTROL_MAX_AGE, max_age.clone());
+        }
+        res
+    }
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Layer<S> for Cors {
+    type Service = CorsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CorsService { inner, cors: Arc::new(self.clone()) }
+    }
+}
+
+fn join<T: AsRef<str>>(items: impl IntoIterator<Item = T>) -> HeaderValue {
+    let value = items.into_iter().fold(String::new(), |mut acc, e| {
+        if !acc.is_empty() {
+            acc.push(',');
+        }
+        acc.push_str(e.as_ref());
+        acc
+    });
+    HeaderValue::from_str(&value).expect("method and header name is valid header value")
+}
+
+/// service that handle CORS
+#[derive(Clone)]
+pub struct CorsService<S> {
+    inner: S,
+    cors: Arc<Cors>,
+}
+
+impl<S,B> Service<Request<B>> for CorsService<S>
+where
+    S: Service<Request<B>, Response = Response>,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = CorsFuture<S::Future>;
+
+    fn call(&self, req: Request<B>) -> Self::Future {
+        let headers = req.headers();
+        if req.method() == Method::OPTIONS
+            && headers.contains_key(header::ORIGIN)
+            && headers.contains_key(header::ACCESS_CONTROL_REQUEST_METHOD)
+        {
+            return CorsFuture::Preflight { res: Some(self.cors.preflight(headers)) };
+        }
+
+        let origin = headers.get(header::ORIGIN).cloned();
+        CorsFuture::Inner { f: self.inner.call(req), cors: self.cors.clone(), origin }
+    }
+}
+
+pin_project_lite::pin_project! {
+    /// future returned from [`CorsService`]
+    #[project = CorsProj]
+    pub enum CorsFuture<F> {
+        Inner { #[pin] f: F, cors: Arc<Cors>, origin: Option<HeaderValue> },
+        Preflight { res: Option<Response> },
+    }
+}
+
+impl<F,E> Future for CorsFuture<F>
+where
+    F: Future<Output = Result<Response,E>>,
+{
+    type Output = Result<Response,E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project() {
+            CorsProj::Inner { f, cors, origin } => {
+                let mut res = ready!(f.poll(cx));
+                if let Ok(res) = &mut res {
+                    cors.apply(origin.as_ref(), res.headers_mut());
+                }
+                Poll::Ready(res)
+            }
+            CorsProj::Preflight { res } => Poll::Ready(Ok(res.take().expect("poll after complete"))),
+        }
+    }
+}
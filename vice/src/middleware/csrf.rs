@@ -0,0 +1,532 @@
+//! cross site request forgery protection
+//!
+//! [`Csrf`] verify every request with unsafe method, that is any method
+//! other than `GET`, `HEAD`, `OPTIONS` and `TRACE`
+//!
+//! as the first line of defence, `Sec-Fetch-Site` and `Origin` header must
+//! indicate same origin request, or the origin is trusted
+//!
+//! then the submitted token must match the token of the client, the token is
+//! submitted in `X-CSRF-Token` header, or `csrf_token` field of url encoded
+//! form body, the token can be embedded in templates using [`CsrfToken`]
+//! extractor
+//!
+//! the client token is stored either in a cookie, known as double submit
+//! cookie pattern, or in server side session with `session` feature, known as
+//! synchronizer token pattern, in which case [`SessionLayer`] must be applied
+//! after [`Csrf`]
+//!
+//! a client token that is not in the issued format is replaced by a new one,
+//! with `secure-cookie` feature the double submit cookie can be signed using
+//! [`Csrf::signed`], so a cookie planted by a sibling subdomain is rejected
+//!
+//! # Example
+//!
+//! ```no_run
+//! use vice::{
+//!     middleware::csrf::{Csrf, CsrfToken},
+//!     router::{Router, get},
+//! };
+//!
+//! async fn form(token: CsrfToken) -> String {
+//!     format!(r#"<form method="post"><input type="hidden" name="csrf_token" value="{token}"></form>"#)
+//! }
+//!
+//! fn main() -> std::io::Result<()> {
+//!     let route = Router::new()
+//!         .route("/", get(form))
+//!         .layer(Csrf::double_submit());
+//!     vice::listen("0.0.0.0:3000", route)
+//! }
+//! ```
+//!
+//! [`SessionLayer`]: super::session::SessionLayer
+use super::Layer;
+use crate::{
+    http::{
+        body::{BodyError, LengthLimitError},
+        cookie::{Cookie, CookieJar, SameSite},
+        Body, FromRequestParts, IntoResponse, IntoResponseParts, Request, Response,
+    },
+    util::{base64, random},
+};
+use http::{header, request, HeaderMap, HeaderName, Method, StatusCode};
+use http_body_util::{BodyExt, Limited};
+use hyper::service::Service;
+use std::{
+    fmt,
+    future::{ready, Ready},
+    pin::Pin,
+    sync::Arc,
+};
+#[cfg(feature = "secure-cookie")]
+use crate::http::cookie::{signed, Keys};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{convert::Infallible, task::{Context, Poll}};
+
+    #[test]
+    fn csrf() {
+        let service = Csrf::double_submit().trust_origin("https://trusted.com").layer(hyper::service::service_fn(|req: Request| async move {
+            let token = req.extensions().get::<CsrfToken>().unwrap().clone();
+            Ok::<Response,Infallible>(token.to_string().into_response())
+        }));
+
+        let mut cx = Context::from_waker(std::task::Waker::noop());
+        let mut call = |method: Method, headers: &[(&'static str, &str)], body: &'static str| {
+            let mut req = Request::new(Body::from(body));
+            *req.method_mut() = method;
+            req.headers_mut().insert(header::HOST, "example.com".parse().unwrap());
+            for (name, value) in headers {
+                req.headers_mut().insert(*name, value.parse().unwrap());
+            }
+            match service.call(req).as_mut().poll(&mut cx) {
+                Poll::Ready(Ok(res)) => res,
+                _ => unreachable!(),
+            }
+        };
+
+        let res = call(Method::GET, &[], "");
+        let cookie = res.headers()[header::SET_COOKIE].to_str().unwrap().split(';').next().unwrap().to_owned();
+        let token = cookie.strip_prefix("csrf=").unwrap().to_owned();
+        assert_eq!(res.body().as_bytes(), Some(token.as_bytes()));
+
+        let form = format!("a=1&csrf_token={token}").leak();
+        let mut status = |method, headers: &[(&'static str, &str)], body| call(method, headers, body).status();
+        assert_eq!(status(Method::POST, &[("cookie", &cookie), ("x-csrf-token", &token)], ""), StatusCode::OK);
+        assert_eq!(status(Method::POST, &[("cookie", &cookie), ("content-type", "application/x-www-form-urlencoded")], form), StatusCode::OK);
+        assert_eq!(status(Method::POST, &[("cookie", &cookie), ("x-csrf-token", "wrong")], ""), StatusCode::FORBIDDEN);
+        assert_eq!(status(Method::POST, &[("x-csrf-token", &token)], ""), StatusCode::FORBIDDEN);
+
+        let valid = [("cookie", cookie.as_str()), ("x-csrf-token", token.as_str())];
+        let with = |header| [valid[0], valid[1], header];
+        assert_eq!(status(Method::DELETE, &with(("sec-fetch-site", "same-origin")), ""), StatusCode::OK);
+        assert_eq!(status(Method::DELETE, &with(("sec-fetch-site", "cross-site")), ""), StatusCode::FORBIDDEN);
+        assert_eq!(status(Method::PUT, &with(("origin", "https://example.com")), ""), StatusCode::OK);
+        assert_eq!(status(Method::PUT, &with(("origin", "https://evil.com")), ""), StatusCode::FORBIDDEN);
+        assert_eq!(status(Method::PUT, &with(("origin", "https://trusted.com")), ""), StatusCode::OK);
+
+        // token not in the issued format is replaced
+        assert_eq!(call(Method::POST, &[("cookie", "csrf=a"), ("x-csrf-token", "a")], "").status(), StatusCode::FORBIDDEN);
+        let res = call(Method::GET, &[("cookie", "csrf=a")], "");
+        assert_ne!(res.body().as_bytes(), Some(&b"a"[..]));
+        assert!(res.headers().contains_key(header::SET_COOKIE));
+    }
+
+    #[cfg(feature = "secure-cookie")]
+    #[test]
+    fn signed() {
+        use crate::http::cookie::Key;
+
+        let service = Csrf::double_submit().signed(Keys::new(Key::generate())).layer(hyper::service::service_fn(|req: Request| async move {
+            let token = req.extensions().get::<CsrfToken>().unwrap().clone();
+            Ok::<Response,Infallible>(token.to_string().into_response())
+        }));
+        let mut cx = Context::from_waker(std::task::Waker::noop());
+        let mut call = |method: Method, cookie: &str, token: &str| {
+            let mut req = Request::new(Body::default());
+            *req.method_mut() = method;
+            req.headers_mut().insert(header::COOKIE, cookie.parse().unwrap());
+            req.headers_mut().insert("x-csrf-token", token.parse().unwrap());
+            match service.call(req).as_mut().poll(&mut cx) {
+                Poll::Ready(Ok(res)) => res,
+                _ => unreachable!(),
+            }
+        };
+
+        let res = call(Method::GET, "a=1", "");
+        let cookie = res.headers()[header::SET_COOKIE].to_str().unwrap().split(';').next().unwrap().to_owned();
+        let token = std::str::from_utf8(res.body().as_bytes().unwrap()).unwrap().to_owned();
+        assert_eq!(cookie.len(), "csrf=".len() + 86);
+        assert!(cookie.ends_with(&token));
+
+        assert_eq!(call(Method::POST, &cookie, &token).status(), StatusCode::OK);
+        // unsigned cookie planted by another site is rejected
+        assert_eq!(call(Method::POST, &format!("csrf={token}"), &token).status(), StatusCode::FORBIDDEN);
+    }
+}
+
+/// session key of the token in synchronizer token pattern
+#[cfg(feature = "session")]
+const SESSION_KEY: &str = "csrf_token";
+
+/// csrf token of the client
+///
+/// when used as extractor, [`Csrf`] must be applied
+#[derive(Clone, Debug)]
+pub struct CsrfToken(Arc<str>);
+
+impl CsrfToken {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for CsrfToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromRequestParts for CsrfToken {
+    type Error = MissingCsrfToken;
+    type Future = Ready<Result<Self,Self::Error>>;
+
+    fn from_request_parts(parts: &mut request::Parts) -> Self::Future {
+        ready(parts.extensions.get::<CsrfToken>().cloned().ok_or(MissingCsrfToken))
+    }
+}
+
+/// error returned when [`Csrf`] is not applied
+#[derive(thiserror::Error, Debug)]
+#[error("csrf token is not available")]
+pub struct MissingCsrfToken;
+
+impl IntoResponse for MissingCsrfToken {
+    fn into_response(self) -> Response {
+        (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()).into_response()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Storage {
+    Cookie,
+    #[cfg(feature = "session")]
+    Session,
+}
+
+/// csrf protection layer
+///
+/// see [module level documentation](self) for more details
+#[derive(Clone, Debug)]
+pub struct Csrf {
+    storage: Storage,
+    cookie: Cookie,
+    header: HeaderName,
+    field: Arc<str>,
+    trusted_origins: Arc<[String]>,
+    body_limit: usize,
+    #[cfg(feature = "secure-cookie")]
+    keys: Option<Keys>,
+}
+
+impl Csrf {
+    fn new(storage: Storage) -> Self {
+        let cookie = Cookie::new("csrf", "")
+            .path("/")
+            .http_only(true)
+            .secure(true)
+            .same_site(SameSite::Lax);
+        Self {
+            storage,
+            cookie,
+            header: HeaderName::from_static("x-csrf-token"),
+            field: Arc::from("csrf_token"),
+            trusted_origins: Arc::new([]),
+            body_limit: 2 * 1024 * 1024,
+            #[cfg(feature = "secure-cookie")]
+            keys: None,
+        }
+    }
+
+    /// store the client token in a cookie
+    pub fn double_submit() -> Self {
+        Self::new(Storage::Cookie)
+    }
+
+    /// store the client token in server side session
+    #[cfg(feature = "session")]
+    pub fn synchronizer() -> Self {
+        Self::new(Storage::Session)
+    }
+
+    /// token cookie name and attributes in double submit cookie pattern, the value is ignored
+    ///
+    /// default cookie is `csrf` with `Path=/`, `HttpOnly`, `Secure` and `SameSite=Lax`
+    pub fn cookie(mut self, cookie: Cookie) -> Self {
+        self.cookie = cookie;
+        self
+    }
+
+    /// sign the token cookie in double submit cookie pattern with HMAC-SHA256
+    ///
+    /// cookie with invalid signature is ignored and a new token is issued
+    #[cfg(feature = "secure-cookie")]
+    pub fn signed(mut self, keys: Keys) -> Self {
+        self.keys = Some(keys);
+        self
+    }
+
+    /// header containing submitted token, default to `X-CSRF-Token`
+    ///
+    /// # Panics
+    ///
+    /// panics if the header name is invalid
+    pub fn header(mut self, name: &str) -> Self {
+        self.header = HeaderName::try_from(name).expect("invalid header name");
+        self
+    }
+
+    /// url encoded form field containing submitted token, default to `csrf_token`
+    pub fn field(mut self, name: impl Into<Arc<str>>) -> Self {
+        self.field = name.into();
+        self
+    }
+
+    /// allow cross origin request from given origin, e.g. `https://example.com`
+    pub fn trust_origin(mut self, origin: impl Into<String>) -> Self {
+        let mut origins = self.trusted_origins.to_vec();
+        origins.push(origin.into());
+        self.trusted_origins = origins.into();
+        self
+    }
+
+    /// maximum form body read when looking for submitted token, default to 2MiB
+    pub fn body_limit(mut self, limit: usize) -> Self {
+        self.body_limit = limit;
+        self
+    }
+
+    /// check `Sec-Fetch-Site` and `Origin` header
+    fn check_origin(&self, headers: &HeaderMap) -> Result<(), &'static str> {
+        let origin = headers.get(header::ORIGIN).and_then(|e|e.to_str().ok());
+        if origin.is_some_and(|e|self.trusted_origins.iter().any(|t|t.eq_ignore_ascii_case(e))) {
+            return Ok(());
+        }
+
+        match headers.get("sec-fetch-site").map(|e|e.as_bytes()) {
+            Some(b"same-origin" | b"none") => return Ok(()),
+            Some(_) => return Err("cross site request"),
+            None => {}
+        }
+
+        let Some(origin) = origin else {
+            return Ok(());
+        };
+        let host = headers.get(header::HOST).and_then(|e|e.to_str().ok());
+        match (origin.split_once("://"), host) {
+            (Some((_, authority)), Some(host)) if authority.eq_ignore_ascii_case(host) => Ok(()),
+            _ => Err("cross origin request"),
+        }
+    }
+}
+
+impl<S> Layer<S> for Csrf {
+    type Service = CsrfService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CsrfService { inner: Arc::new(inner), csrf: Arc::new(self.clone()) }
+    }
+}
+
+/// service that verify csrf token
+pub struct CsrfService<S> {
+    inner: Arc<S>,
+    csrf: Arc<Csrf>,
+}
+
+impl<S> Clone for CsrfService<S> {
+    fn clone(&self) -> Self {
+        Self { inner: self.inner.clone(), csrf: self.csrf.clone() }
+    }
+}
+
+/// future returned from [`CsrfService`]
+pub type CsrfFuture<E> = Pin<Box<dyn Future<Output = Result<Response,E>> + Send + 'static>>;
+
+impl<S> Service<Request> for CsrfService<S>
+where
+    S: Service<Request, Response = Response> + Send + Sync + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = CsrfFuture<S::Error>;
+
+    fn call(&self, req: Request) -> Self::Future {
+        let inner = self.inner.clone();
+        let csrf = self.csrf.clone();
+        Box::pin(async move {
+            let (token, issued) = match csrf.client_token(&req) {
+                Ok(Some(token)) => (token, false),
+                Ok(None) => (new_token(), true),
+                Err(err) => {
+                    log::error!("csrf: {err}");
+                    return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+                }
+            };
+
+            let mut req = if is_safe(req.method()) {
+                req
+            } else {
+                match csrf.verify(req, &token, issued).await {
+                    Ok(req) => req,
+                    Err(res) => return Ok(res),
+                }
+            };
+
+            #[cfg(feature = "session")]
+            if issued
+                && matches!(csrf.storage, Storage::Session)
+                && let Some(session) = req.extensions().get::<super::session::Session>()
+                && let Err(err) = session.insert(SESSION_KEY, &token)
+            {
+                log::error!("csrf: failed to store token in session: {err}");
+                return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+            }
+            req.extensions_mut().insert(CsrfToken(Arc::from(token.as_str())));
+
+            let res = inner.call(req).await?;
+            match (issued, csrf.storage) {
+                (true, Storage::Cookie) => {
+                    let (parts, body) = res.into_parts();
+                    Ok(Response::from_parts(csrf.seal(token).into_response_parts(parts), body))
+                }
+                _ => Ok(res),
+            }
+        })
+    }
+}
+
+impl Csrf {
+    /// returns `Err` if session is required but not available, `None` if
+    /// there is no token or it is not in the issued format
+    fn client_token(&self, req: &Request) -> Result<Option<String>, &'static str> {
+        let token = match self.storage {
+            Storage::Cookie => CookieJar::from_headers(req.headers())
+                .get(self.cookie.name())
+                .and_then(|e|self.open(e)),
+            #[cfg(feature = "session")]
+            Storage::Session => match req.extensions().get::<super::session::Session>() {
+                Some(session) => session.get::<String>(SESSION_KEY),
+                None => return Err("session layer is not applied"),
+            },
+        };
+        Ok(token.filter(|e|is_valid_token(e)))
+    }
+
+    /// token of the token cookie, `None` if the signature is invalid
+    fn open(&self, cookie: &Cookie) -> Option<String> {
+        #[cfg(feature = "secure-cookie")]
+        if let Some(keys) = &self.keys {
+            return signed::verify(keys, cookie);
+        }
+        Some(cookie.value().to_owned())
+    }
+
+    /// token cookie to be sent, signed if keys is set
+    fn seal(&self, token: String) -> Cookie {
+        let mut cookie = self.cookie.clone();
+        cookie.set_value(token);
+        #[cfg(feature = "secure-cookie")]
+        if let Some(keys) = &self.keys {
+            let value = signed::sign(keys, &cookie);
+            cookie.set_value(value);
+        }
+        cookie
+    }
+
+    /// verify request with unsafe method, the body is buffered if the token is in form field
+    async fn verify(&self, req: Request, token: &str, issued: bool) -> Result<Request, Response> {
+        if let Err(reason) = self.check_origin(req.headers()) {
+            return Err(forbidden(reason));
+        }
+        if issued {
+            return Err(forbidden("missing client token"));
+        }
+
+        if let Some(submitted) = req.headers().get(&self.header) {
+            return match constant_time_eq(submitted.as_bytes(), token.as_bytes()) {
+                true => Ok(req),
+                false => Err(forbidden("token mismatch")),
+            };
+        }
+
+        let is_form = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|e|e.to_str().ok())
+            .is_some_and(|e|e.starts_with("application/x-www-form-urlencoded"));
+        if !is_form {
+            return Err(forbidden("missing submitted token"));
+        }
+
+        let (parts, body) = req.into_parts();
+        let limit = self.body_limit;
+        let bytes = match Limited::new(body, limit).collect().await {
+            Ok(collected) => collected.to_bytes(),
+            Err(err) if err.is::<http_body_util::LengthLimitError>() => {
+                return Err(BodyError::new(LengthLimitError::new(limit as u64)).into_response());
+            }
+            Err(err) => return Err(BodyError::new(err).into_response()),
+        };
+
+        let submitted = bytes
+            .split(|e|*e == b'&')
+            .map(split_pair)
+            .find(|(name, _)|percent_decode(name) == self.field.as_bytes())
+            .map(|(_, value)|percent_decode(value));
+        match submitted {
+            Some(submitted) if constant_time_eq(&submitted, token.as_bytes()) => {
+                Ok(Request::from_parts(parts, Body::from(bytes)))
+            }
+            _ => Err(forbidden("token mismatch")),
+        }
+    }
+}
+
+/// length of base64 encoded 32 bytes token
+const TOKEN_LEN: usize = 43;
+
+fn new_token() -> String {
+    base64::encode_url(&random::bytes::<32>())
+}
+
+fn is_valid_token(token: &str) -> bool {
+    token.len() == TOKEN_LEN && token.bytes().all(|e|e.is_ascii_alphanumeric() || e == b'-' || e == b'_')
+}
+
+fn is_safe(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE)
+}
+
+fn forbidden(reason: &'static str) -> Response {
+    log::debug!("csrf validation failed: {reason}");
+    (StatusCode::FORBIDDEN, "csrf validation failed").into_response()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+/// decode url encoded form component
+fn percent_decode(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        let hex = |b: u8| (b as char).to_digit(16);
+        match input[i] {
+            b'+' => out.push(b' '),
+            b'%' if i + 2 < input.len() => match (hex(input[i + 1]), hex(input[i + 2])) {
+                (Some(hi), Some(lo)) => {
+                    out.push((hi * 16 + lo) as u8);
+                    i += 2;
+                }
+                _ => out.push(b'%'),
+            },
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    out
+}
+
+/// split form `name=value` pair
+fn split_pair(pair: &[u8]) -> (&[u8], &[u8]) {
+    match pair.iter().position(|e|*e == b'=') {
+        Some(at) => (&pair[..at], &pair[at + 1..]),
+        None => (pair, &[]),
+    }
+}
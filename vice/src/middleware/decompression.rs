@@ -0,0 +1,461 @@
+//! request body decompression
+//!
+//! [`Decompression`] decode request body with `Content-Encoding` of gzip,
+//! deflate, brotli or zstd before it reach the handler, so extractors like
+//! [`Bytes`] and [`String`] receive the decoded content
+//!
+//! the decompressed size is limited to prevent decompression bomb, exceeding
+//! the limit fails the body extractor with `413 Payload Too Large`
+//!
+//! the limit only applies to compressed bodies, request without
+//! `Content-Encoding` or with `identity` is passed through unbounded
+//!
+//! request with unsupported encoding is rejected with `415 Unsupported Media Type`
+//!
+//! # Example
+//!
+//! ```no_run
+//! use vice::{middleware::decompression::Decompression, router::{Router, get}};
+//!
+//! fn main() -> std::io::Result<()> {
+//!     let route = Router::new()
+//!         .route("/ingest", get(|body: String| async move { body.len().to_string() }))
+//!         .layer(Decompression::new().limit(16 * 1024 * 1024));
+//!     vice::listen("0.0.0.0:3000", route)
+//! }
+//! ```
+//!
+//! [`Bytes`]: bytes::Bytes
+use super::{compression::Encoding, Layer};
+use crate::http::{
+    body::{Body, BoxError, LengthLimitError},
+    IntoResponse, Request, Response,
+};
+use bytes::Bytes;
+use http::{header, HeaderMap, HeaderValue, StatusCode};
+use hyper::{
+    body::{Body as HttpBody, Frame},
+    service::Service,
+};
+use std::{
+    io::{self, Write},
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn collect(mut body: Body) -> Result<Vec<u8>, BoxError> {
+        let mut cx = Context::from_waker(std::task::Waker::noop());
+        let mut out = Vec::new();
+        while let Poll::Ready(Some(frame)) = Pin::new(&mut body).poll_frame(&mut cx) {
+            out.extend_from_slice(&frame?.into_data().unwrap());
+        }
+        Ok(out)
+    }
+
+    fn decompress(encoding: Encoding, data: &[u8], limit: u64) -> Result<Vec<u8>, BoxError> {
+        let body = DecompressBody {
+            inner: Body::from(data.to_vec()),
+            decoder: Some(Decoder::new(encoding, limit)),
+            trailers: None,
+        };
+        collect(Body::new(body))
+    }
+
+    #[test]
+    fn decode() {
+        let text = "vice ".repeat(4096);
+
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(text.as_bytes()).unwrap();
+        let gzip = gzip.finish().unwrap();
+        assert_eq!(decompress(Encoding::Gzip, &gzip, 1 << 20).unwrap(), text.as_bytes());
+
+        let zstd = zstd::encode_all(text.as_bytes(), 3).unwrap();
+        assert_eq!(decompress(Encoding::Zstd, &zstd, 1 << 20).unwrap(), text.as_bytes());
+
+        let err = decompress(Encoding::Zstd, &zstd, 1024).unwrap_err();
+        assert!(err.is::<LengthLimitError>());
+        // truncated frame must not be accepted as complete
+        assert!(decompress(Encoding::Zstd, &zstd[..zstd.len() - 4], 1 << 20).is_err());
+        assert!(decompress(Encoding::Gzip, &gzip[..gzip.len() - 4], 1 << 20).is_err());
+
+        assert!(decompress(Encoding::Gzip, b"not gzip", 1 << 20).is_err());
+
+        let mut deflate = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        deflate.write_all(text.as_bytes()).unwrap();
+        let deflate = deflate.finish().unwrap();
+        assert_eq!(decompress(Encoding::Deflate, &deflate, 1 << 20).unwrap(), text.as_bytes());
+        assert!(decompress(Encoding::Deflate, &deflate, 1024).unwrap_err().is::<LengthLimitError>());
+        assert!(decompress(Encoding::Deflate, &deflate[..deflate.len() / 2], 1 << 20).is_err());
+        let mut decoder = Decoder::new(Encoding::Deflate, 1 << 20);
+        let mut out = Vec::new();
+        for chunk in deflate.chunks(7) {
+            out.extend_from_slice(&decoder.write(chunk).unwrap());
+        }
+        out.extend_from_slice(&decoder.finish().unwrap());
+        assert_eq!(out, text.as_bytes());
+
+        let mut br = brotli::CompressorWriter::new(Vec::new(), 4096, 5, 22);
+        br.write_all(text.as_bytes()).unwrap();
+        let br = br.into_inner();
+        assert_eq!(decompress(Encoding::Brotli, &br, 1 << 20).unwrap(), text.as_bytes());
+        assert!(decompress(Encoding::Brotli, &br, 1024).unwrap_err().is::<LengthLimitError>());
+        assert!(decompress(Encoding::Brotli, &br[..br.len() - 4], 1 << 20).is_err());
+    }
+
+    #[test]
+    fn content_encoding() {
+        let mut headers = HeaderMap::new();
+        let decompression = Decompression::new().br(false);
+        assert_eq!(decompression.encoding(&headers), Ok(None));
+        headers.insert(header::CONTENT_ENCODING, HeaderValue::from_static("identity"));
+        assert_eq!(decompression.encoding(&headers), Ok(None));
+        headers.insert(header::CONTENT_ENCODING, HeaderValue::from_static("GZIP"));
+        assert_eq!(decompression.encoding(&headers), Ok(Some(Encoding::Gzip)));
+        headers.insert(header::CONTENT_ENCODING, HeaderValue::from_static("br"));
+        assert_eq!(decompression.encoding(&headers), Err(()));
+        headers.insert(header::CONTENT_ENCODING, HeaderValue::from_static("gzip, zstd"));
+        assert_eq!(decompression.encoding(&headers), Err(()));
+    }
+}
+
+/// request decompression layer
+///
+/// see [module level documentation](self) for more details
+#[derive(Clone, Debug)]
+pub struct Decompression {
+    enabled: [bool; 4],
+    limit: u64,
+}
+
+impl Decompression {
+    /// create new `Decompression` with every encoding enabled and 2MiB limit
+    pub fn new() -> Self {
+        Self { enabled: [true; 4], limit: 2 * 1024 * 1024 }
+    }
+
+    /// enable or disable gzip
+    pub fn gzip(self, enable: bool) -> Self {
+        self.set(Encoding::Gzip, enable)
+    }
+
+    /// enable or disable deflate
+    pub fn deflate(self, enable: bool) -> Self {
+        self.set(Encoding::Deflate, enable)
+    }
+
+    /// enable or disable brotli
+    pub fn br(self, enable: bool) -> Self {
+        self.set(Encoding::Brotli, enable)
+    }
+
+    /// enable or disable zstd
+    pub fn zstd(self, enable: bool) -> Self {
+        self.set(Encoding::Zstd, enable)
+    }
+
+    /// maximum decompressed body size in bytes, default to 2MiB
+    ///
+    /// request that is not compressed is not limited
+    pub fn limit(mut self, limit: u64) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    fn set(mut self, encoding: Encoding, enable: bool) -> Self {
+        self.enabled[encoding as usize] = enable;
+        self
+    }
+
+    /// returns the encoding to decode, or `Err` if it is not supported
+    fn encoding(&self, headers: &HeaderMap) -> Result<Option<Encoding>, ()> {
+        let mut codings = headers
+            .get_all(header::CONTENT_ENCODING)
+            .iter()
+            .flat_map(|e|e.to_str().map_or(vec![""], |e|e.split(',').collect()))
+            .map(str::trim)
+            .filter(|e|!e.eq_ignore_ascii_case("identity"));
+
+        let Some(coding) = codings.next() else {
+            return Ok(None);
+        };
+        match Encoding::from_token(coding) {
+            Some(encoding) if self.enabled[encoding as usize] && codings.next().is_none() => Ok(Some(encoding)),
+            _ => Err(()),
+        }
+    }
+
+    /// `415 Unsupported Media Type` response listing supported encoding
+    fn unsupported(&self) -> Response {
+        let accept = [Encoding::Gzip, Encoding::Deflate, Encoding::Brotli, Encoding::Zstd]
+            .into_iter()
+            .filter(|e|self.enabled[*e as usize])
+            .map(|e|e.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut res = (StatusCode::UNSUPPORTED_MEDIA_TYPE, "unsupported content encoding").into_response();
+        if let Ok(accept) = HeaderValue::from_str(&accept) {
+            res.headers_mut().insert(header::ACCEPT_ENCODING, accept);
+        }
+        res
+    }
+}
+
+impl Default for Decompression {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Layer<S> for Decompression {
+    type Service = DecompressionService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        DecompressionService { inner, decompression: self.clone() }
+    }
+}
+
+/// writer that fail when total written bytes exceed the limit
+struct Limited {
+    buf: Vec<u8>,
+    written: u64,
+    limit: u64,
+    exceeded: bool,
+}
+
+impl Write for Limited {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if self.written + data.len() as u64 > self.limit {
+            self.exceeded = true;
+            return Err(io::Error::other("decompressed body exceeds the limit"));
+        }
+        self.written += data.len() as u64;
+        self.buf.extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// streaming decoder writing into limited in memory buffer
+enum Decoder {
+    Gzip(flate2::write::GzDecoder<Limited>),
+    Deflate(Box<Zlib>),
+    Brotli(Box<brotli::DecompressorWriter<Limited>>),
+    Zstd(zstd::stream::zio::Writer<Limited, zstd::stream::raw::Decoder<'static>>),
+}
+
+impl Decoder {
+    fn new(encoding: Encoding, limit: u64) -> Self {
+        let w = Limited { buf: Vec::new(), written: 0, limit, exceeded: false };
+        match encoding {
+            Encoding::Gzip => Self::Gzip(flate2::write::GzDecoder::new(w)),
+            Encoding::Deflate => Self::Deflate(Box::new(Zlib { inner: flate2::Decompress::new(true), w, done: false })),
+            Encoding::Brotli => Self::Brotli(Box::new(brotli::DecompressorWriter::new(w, 4096))),
+            Encoding::Zstd => Self::Zstd(zstd::stream::zio::Writer::new(
+                w, zstd::stream::raw::Decoder::new().expect("zstd decoder"),
+            )),
+        }
+    }
+
+    fn limited(&mut self) -> &mut Limited {
+        match self {
+            Decoder::Gzip(d) => d.get_mut(),
+            Decoder::Deflate(d) => &mut d.w,
+            Decoder::Brotli(d) => d.get_mut(),
+            Decoder::Zstd(d) => d.writer_mut(),
+        }
+    }
+
+    /// decompress data, returns the produced output
+    fn write(&mut self, data: &[u8]) -> Result<Bytes, BoxError> {
+        let result = match self {
+            Decoder::Gzip(d) => d.write_all(data).and_then(|_|d.flush()),
+            Decoder::Deflate(d) => d.write(data),
+            Decoder::Brotli(d) => d.write_all(data).and_then(|_|d.flush()),
+            Decoder::Zstd(d) => d.write_all(data).and_then(|_|d.flush()),
+        };
+        self.output(result)
+    }
+
+    /// finish the stream, returns the remaining output
+    fn finish(&mut self) -> Result<Bytes, BoxError> {
+        let result = match self {
+            Decoder::Gzip(d) => d.try_finish(),
+            Decoder::Deflate(d) => d.finish(),
+            Decoder::Brotli(d) => d.close(),
+            Decoder::Zstd(d) => d.finish(),
+        };
+        self.output(result)
+    }
+
+    fn output(&mut self, result: io::Result<()>) -> Result<Bytes, BoxError> {
+        let limited = self.limited();
+        match result {
+            Ok(()) => Ok(std::mem::take(&mut limited.buf).into()),
+            Err(_) if limited.exceeded => Err(LengthLimitError::new(limited.limit).into()),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// zlib decoder that track the end of stream
+///
+/// flate2 zlib writer accept a truncated stream as complete, this report
+/// incomplete stream as error like gzip does
+struct Zlib {
+    inner: flate2::Decompress,
+    w: Limited,
+    done: bool,
+}
+
+impl Zlib {
+    fn write(&mut self, mut data: &[u8]) -> io::Result<()> {
+        let mut buf = [0; 8192];
+        while !self.done {
+            let (total_in, total_out) = (self.inner.total_in(), self.inner.total_out());
+            let status = self
+                .inner
+                .decompress(data, &mut buf, flate2::FlushDecompress::None)
+                .map_err(io::Error::other)?;
+            let consumed = (self.inner.total_in() - total_in) as usize;
+            let produced = (self.inner.total_out() - total_out) as usize;
+            data = &data[consumed..];
+            self.w.write_all(&buf[..produced])?;
+            self.done = status == flate2::Status::StreamEnd;
+            if consumed == 0 && produced == 0 {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        match self.done {
+            true => Ok(()),
+            false => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "incomplete deflate stream")),
+        }
+    }
+}
+
+/// body that decompress the inner body
+struct DecompressBody {
+    inner: Body,
+    decoder: Option<Decoder>,
+    trailers: Option<HeaderMap>,
+}
+
+impl HttpBody for DecompressBody {
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let me = self.get_mut();
+        loop {
+            let Some(decoder) = &mut me.decoder else {
+                return Poll::Ready(me.trailers.take().map(|e|Ok(Frame::trailers(e))));
+            };
+
+            let result = match ready!(Pin::new(&mut me.inner).poll_frame(cx)) {
+                Some(Ok(frame)) => match frame.into_data() {
+                    Ok(data) => decoder.write(&data),
+                    Err(frame) => {
+                        me.trailers = frame.into_trailers().ok();
+                        let result = decoder.finish();
+                        me.decoder = None;
+                        result
+                    }
+                },
+                Some(Err(err)) => Err(err),
+                None => {
+                    let result = decoder.finish();
+                    me.decoder = None;
+                    result
+                }
+            };
+
+            match result {
+                Ok(data) if data.is_empty() => continue,
+                Ok(data) => return Poll::Ready(Some(Ok(Frame::data(data)))),
+                Err(err) => {
+                    me.decoder = None;
+                    me.trailers = None;
+                    return Poll::Ready(Some(Err(err)));
+                }
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.decoder.is_none() && self.trailers.is_none()
+    }
+}
+
+/// service that decompress request body
+#[derive(Clone)]
+pub struct DecompressionService<S> {
+    inner: S,
+    decompression: Decompression,
+}
+
+impl<S> Service<Request> for DecompressionService<S>
+where
+    S: Service<Request, Response = Response>,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = DecompressionFuture<S::Future>;
+
+    fn call(&self, mut req: Request) -> Self::Future {
+        let encoding = match self.decompression.encoding(req.headers()) {
+            Ok(encoding) => encoding,
+            Err(()) => return DecompressionFuture::Reject { res: Some(self.decompression.unsupported()) },
+        };
+
+        if let Some(encoding) = encoding {
+            let headers = req.headers_mut();
+            headers.remove(header::CONTENT_ENCODING);
+            headers.remove(header::CONTENT_LENGTH);
+            let limit = self.decompression.limit;
+            req = req.map(|body| Body::new(DecompressBody {
+                inner: body,
+                decoder: Some(Decoder::new(encoding, limit)),
+                trailers: None,
+            }));
+        }
+
+        DecompressionFuture::Inner { f: self.inner.call(req) }
+    }
+}
+
+pin_project_lite::pin_project! {
+    /// future returned from [`DecompressionService`]
+    #[project = DecompressionProj]
+    pub enum DecompressionFuture<F> {
+        Inner { #[pin] f: F },
+        Reject { res: Option<Response> },
+    }
+}
+
+impl<F,E> Future for DecompressionFuture<F>
+where
+    F: Future<Output = Result<Response,E>>,
+{
+    type Output = Result<Response,E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project() {
+            DecompressionProj::Inner { f } => f.poll(cx),
+            DecompressionProj::Reject { res } => Poll::Ready(Ok(res.take().expect("poll after complete"))),
+        }
+    }
+}
@@ -0,0 +1,469 @@
+//! trusted `Forwarded` and `X-Forwarded-*` header resolution
+//!
+//! when the server is behind a reverse proxy, the peer address is the proxy,
+//! the proxy forward the client information via [RFC 7239] `Forwarded` header
+//! or the de-facto `X-Forwarded-For`, `X-Forwarded-Proto` and `X-Forwarded-Host` header
+//!
+//! those headers can be sent by anyone, so it only honoured when the immediate peer
+//! is one of the trusted proxies
+//!
+//! # Example
+//!
+//! ```no_run
+//! use vice::{
+//!     middleware::forwarded::{ClientIp, Forwarded},
+//!     router::{Router, get},
+//! };
+//!
+//! async fn handle(ClientIp(ip): ClientIp) -> String {
+//!     format!("hello {ip}")
+//! }
+//!
+//! fn main() -> std::io::Result<()> {
+//!     let route = Router::new()
+//!         .route("/", get(handle))
+//!         .layer(Forwarded::new(["10.0.0.0/8".parse().unwrap()]));
+//!     vice::listen("0.0.0.0:3000", route)
+//! }
+//! ```
+//!
+//! [RFC 7239]: https://datatracker.ietf.org/doc/html/rfc7239
+use super::Layer;
+use crate::{
+    http::{FromRequestParts, Request},
+    runtime::{connect_info::MissingConnectInfo, Connection},
+    util::response::BadRequest,
+};
+use http::{header::{self, HeaderName}, request, uri, HeaderMap};
+use hyper::service::Service;
+use std::{
+    convert::Infallible,
+    future::{ready, Ready},
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    sync::Arc,
+};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn resolve(peer: &str, headers: &[(&'static str, &'static str)]) -> (ClientIp, Scheme, Option<Host>) {
+        let forwarded = Forwarded::new(["10.0.0.0/8".parse().unwrap(), "::1".parse().unwrap()]);
+        let mut map = HeaderMap::new();
+        for (key,value) in headers {
+            map.append(*key, value.parse().unwrap());
+        }
+        forwarded.resolve(peer.parse().unwrap(), &map)
+    }
+
+    #[test]
+    fn cidr() {
+        let cidr: Cidr = "192.168.0.0/16".parse().unwrap();
+        assert!(cidr.contains("192.168.10.1".parse().unwrap()));
+        assert!(!cidr.contains("192.169.0.1".parse().unwrap()));
+        assert!(cidr.contains("::ffff:192.168.0.1".parse().unwrap()));
+        assert!("0.0.0.0/0".parse::<Cidr>().unwrap().contains("1.1.1.1".parse().unwrap()));
+        assert!("fd00::/8".parse::<Cidr>().unwrap().contains("fd12::1".parse().unwrap()));
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+    }
+
+    #[test]
+    fn untrusted_peer() {
+        let (ip, scheme, host) = resolve("1.1.1.1:80", &[("x-forwarded-for", "2.2.2.2"), ("host", "a.com")]);
+        assert_eq!(ip.0, "1.1.1.1".parse::<IpAddr>().unwrap());
+        assert_eq!(scheme.0, uri::Scheme::HTTP);
+        assert_eq!(host.unwrap().0, "a.com");
+    }
+
+    #[test]
+    fn x_forwarded() {
+        let (ip, scheme, host) = resolve("10.0.0.1:80", &[
+            ("x-forwarded-for", "6.6.6.6, 2.2.2.2, 10.0.0.2"),
+            ("x-forwarded-proto", "https"),
+            ("x-forwarded-host", "b.com"),
+            ("host", "a.com"),
+        ]);
+        assert_eq!(ip.0, "2.2.2.2".parse::<IpAddr>().unwrap());
+        assert_eq!(scheme.0, uri::Scheme::HTTPS);
+        assert_eq!(host.unwrap().0, "b.com");
+    }
+
+    #[test]
+    fn forwarded() {
+        let (ip, scheme, host) = resolve("[::1]:80", &[
+            ("forwarded", r#"for=6.6.6.6;proto=http, for="[2001:db8::1]:4711";proto=https;host=c.com"#),
+            ("forwarded", "for=10.1.1.1;proto=http"),
+            ("x-forwarded-for", "3.3.3.3"),
+        ]);
+        assert_eq!(ip.0, "2001:db8::1".parse::<IpAddr>().unwrap());
+        assert_eq!(scheme.0, uri::Scheme::HTTPS);
+        assert_eq!(host.unwrap().0, "c.com");
+    }
+
+    #[test]
+    fn forwarded_quoted() {
+        let (ip, _, host) = resolve("10.0.0.1:80", &[
+            ("forwarded", r#"for="[2001:db8::1]:80";host="a.com,b\"c", for=10.0.0.2"#),
+        ]);
+        assert_eq!(ip.0, "2001:db8::1".parse::<IpAddr>().unwrap());
+        assert_eq!(host.unwrap().0, r#"a.com,b"c"#);
+    }
+}
+
+const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+const X_FORWARDED_PROTO: HeaderName = HeaderName::from_static("x-forwarded-proto");
+const X_FORWARDED_HOST: HeaderName = HeaderName::from_static("x-forwarded-host");
+
+/// trusted proxy configuration
+///
+/// see [module level documentation](self) for more details
+#[derive(Clone, Debug, Default)]
+pub struct Forwarded {
+    trusted: Arc<[Cidr]>,
+}
+
+impl Forwarded {
+    /// create new `Forwarded` that trust given networks
+    pub fn new(trusted: impl IntoIterator<Item = Cidr>) -> Self {
+        Self { trusted: trusted.into_iter().collect() }
+    }
+
+    /// is the address one of the trusted proxies
+    pub fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.trusted.iter().any(|cidr|cidr.contains(ip))
+    }
+
+    /// resolve client information from peer address and request headers
+    pub fn resolve(&self, peer: SocketAddr, headers: &HeaderMap) -> (ClientIp, Scheme, Option<Host>) {
+        let mut client = ClientIp(peer.ip());
+        let mut scheme = Scheme(uri::Scheme::HTTP);
+        let mut host = header_str(headers.get(header::HOST)).map(|e|Host(e.to_owned()));
+
+        if !self.is_trusted(peer.ip()) {
+            return (client, scheme, host);
+        }
+
+        if headers.contains_key(header::FORWARDED) {
+            let elements = headers
+                .get_all(header::FORWARDED)
+                .iter()
+                .filter_map(|e|e.to_str().ok())
+                .flat_map(|e|split_quoted(e, ','))
+                .map(ForwardedElement::parse)
+                .collect::<Vec<_>>();
+
+            if let Some(hop) = self.client_hop(elements.iter().map(|e|e.for_ip)) {
+                let element = &elements[hop];
+                if let Some(ip) = element.for_ip {
+                    client = ClientIp(ip);
+                }
+                if let Some(proto) = element.proto.as_deref().and_then(|e|uri::Scheme::from_str(e).ok()) {
+                    scheme = Scheme(proto);
+                }
+                if let Some(value) = &element.host {
+                    host = Some(Host(value.clone()));
+                }
+            }
+        } else if headers.contains_key(X_FORWARDED_FOR) {
+            let ips = headers
+                .get_all(X_FORWARDED_FOR)
+                .iter()
+                .filter_map(|e|e.to_str().ok())
+                .flat_map(|e|e.split(','))
+                .map(|e|parse_node(e.trim()))
+                .collect::<Vec<_>>();
+
+            if let Some(ip) = self.client_hop(ips.iter().copied()).and_then(|hop|ips[hop]) {
+                client = ClientIp(ip);
+            }
+            if let Some(proto) = last_value(headers, &X_FORWARDED_PROTO).and_then(|e|uri::Scheme::from_str(e).ok()) {
+                scheme = Scheme(proto);
+            }
+            if let Some(value) = last_value(headers, &X_FORWARDED_HOST) {
+                host = Some(Host(value.to_owned()));
+            }
+        }
+
+        (client, scheme, host)
+    }
+
+    /// index of the rightmost hop that is not a trusted proxy
+    ///
+    /// if every hop is trusted, the leftmost hop is the client
+    fn client_hop(&self, hops: impl DoubleEndedIterator<Item = Option<IpAddr>> + ExactSizeIterator) -> Option<usize> {
+        let len = hops.len();
+        for (i,ip) in hops.enumerate().rev() {
+            match ip {
+                Some(ip) if self.is_trusted(ip) => continue,
+                _ => return Some(i),
+            }
+        }
+        (len > 0).then_some(0)
+    }
+}
+
+impl<S> Layer<S> for Forwarded {
+    type Service = ForwardedService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ForwardedService::new(inner, self.clone())
+    }
+}
+
+/// a single element of `Forwarded` header
+struct ForwardedElement {
+    for_ip: Option<IpAddr>,
+    proto: Option<String>,
+    host: Option<String>,
+}
+
+impl ForwardedElement {
+    fn parse(element: &str) -> Self {
+        let mut me = Self { for_ip: None, proto: None, host: None };
+        for pair in split_quoted(element, ';') {
+            let Some((key,value)) = pair.trim().split_once('=') else {
+                continue;
+            };
+            let value = unquote(value.trim());
+            if key.eq_ignore_ascii_case("for") {
+                me.for_ip = parse_node(&value);
+            } else if key.eq_ignore_ascii_case("proto") {
+                me.proto = Some(value);
+            } else if key.eq_ignore_ascii_case("host") {
+                me.host = Some(value);
+            }
+        }
+        me
+    }
+}
+
+/// split on separator that is not inside quoted-string
+fn split_quoted(input: &str, sep: char) -> Vec<&str> {
+    let mut parts = vec![];
+    let (mut start, mut quoted, mut escaped) = (0, false, false);
+    for (i,c) in input.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            c if c == sep && !quoted => {
+                parts.push(&input[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&input[start..]);
+    parts
+}
+
+/// unescape quoted-string, token is returned as is
+fn unquote(value: &str) -> String {
+    let Some(inner) = value.strip_prefix('"').and_then(|e|e.strip_suffix('"')) else {
+        return value.to_owned();
+    };
+    let mut out = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => out.extend(chars.next()),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// parse node which can be ip, ip with port, or bracketed ipv6 with optional port
+fn parse_node(node: &str) -> Option<IpAddr> {
+    if let Some(rest) = node.strip_prefix('[') {
+        return rest.split_once(']').and_then(|(ip,_)|ip.parse().ok());
+    }
+    node.parse::<IpAddr>().ok().or_else(||node.parse::<SocketAddr>().ok().map(|e|e.ip()))
+}
+
+fn header_str(value: Option<&http::HeaderValue>) -> Option<&str> {
+    value.and_then(|e|e.to_str().ok())
+}
+
+fn last_value<'a>(headers: &'a HeaderMap, name: &HeaderName) -> Option<&'a str> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|e|e.to_str().ok())
+        .flat_map(|e|e.split(','))
+        .map(str::trim)
+        .rfind(|e|!e.is_empty())
+}
+
+/// service that resolve client information from trusted proxies headers
+///
+/// the resolved [`ClientIp`], [`Scheme`] and [`Host`] is inserted into request extensions
+#[derive(Clone)]
+pub struct ForwardedService<S> {
+    inner: S,
+    forwarded: Forwarded,
+}
+
+impl<S> ForwardedService<S> {
+    /// create new `ForwardedService`
+    pub fn new(inner: S, forwarded: Forwarded) -> Self {
+        Self { inner, forwarded }
+    }
+}
+
+impl<S,B> Service<Request<B>> for ForwardedService<S>
+where
+    S: Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn call(&self, mut req: Request<B>) -> Self::Future {
+        let conn = req.extensions().get::<Connection>().copied();
+        let peer = conn.map(|e|e.peer_addr()).or_else(||req.extensions().get::<SocketAddr>().copied());
+
+        if let Some(peer) = peer {
+            let (client, scheme, host) = self.forwarded.resolve(peer, req.headers());
+            req.extensions_mut().insert(client);
+            req.extensions_mut().insert(scheme);
+            if let Some(host) = host {
+                req.extensions_mut().insert(host);
+            }
+        }
+
+        self.inner.call(req)
+    }
+}
+
+/// network address range in CIDR notation
+///
+/// # Example
+///
+/// ```
+/// use vice::middleware::forwarded::Cidr;
+///
+/// let cidr: Cidr = "10.0.0.0/8".parse().unwrap();
+/// assert!(cidr.contains("10.1.2.3".parse().unwrap()));
+///
+/// let single: Cidr = "::1".parse().unwrap();
+/// assert!(single.contains("::1".parse().unwrap()));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    /// create new `Cidr`, returns `None` if prefix is longer than the address
+    pub fn new(addr: IpAddr, prefix: u8) -> Option<Self> {
+        let max = match addr { IpAddr::V4(_) => 32, IpAddr::V6(_) => 128 };
+        (prefix <= max).then_some(Self { addr, prefix })
+    }
+
+    /// is the address inside this network
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl From<IpAddr> for Cidr {
+    fn from(addr: IpAddr) -> Self {
+        let prefix = match addr { IpAddr::V4(_) => 32, IpAddr::V6(_) => 128 };
+        Self { addr, prefix }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = CidrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('/') {
+            Some((addr,prefix)) => {
+                let addr = addr.parse().map_err(|_|CidrError)?;
+                let prefix = prefix.parse().map_err(|_|CidrError)?;
+                Cidr::new(addr, prefix).ok_or(CidrError)
+            }
+            None => Ok(Cidr::from(s.parse::<IpAddr>().map_err(|_|CidrError)?)),
+        }
+    }
+}
+
+/// error when parsing [`Cidr`]
+#[derive(thiserror::Error, Debug)]
+#[error("invalid cidr notation")]
+pub struct CidrError;
+
+/// the resolved client ip address
+///
+/// when [`ForwardedService`] is not used, this is the peer address
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+impl FromRequestParts for ClientIp {
+    type Error = MissingConnectInfo;
+    type Future = Ready<Result<Self,Self::Error>>;
+
+    fn from_request_parts(parts: &mut request::Parts) -> Self::Future {
+        let ext = &parts.extensions;
+        ready(
+            ext.get::<ClientIp>()
+                .copied()
+                .or_else(||ext.get::<SocketAddr>().map(|e|ClientIp(e.ip())))
+                .ok_or(MissingConnectInfo)
+        )
+    }
+}
+
+/// the resolved request scheme
+///
+/// when [`ForwardedService`] is not used, this is the request uri scheme,
+/// defaulting to `http`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Scheme(pub uri::Scheme);
+
+impl FromRequestParts for Scheme {
+    type Error = Infallible;
+    type Future = Ready<Result<Self,Self::Error>>;
+
+    fn from_request_parts(parts: &mut request::Parts) -> Self::Future {
+        let scheme = match parts.extensions.get::<Scheme>() {
+            Some(scheme) => scheme.clone(),
+            None => Scheme(parts.uri.scheme().cloned().unwrap_or(uri::Scheme::HTTP)),
+        };
+        ready(Ok(scheme))
+    }
+}
+
+/// the resolved request host
+///
+/// when [`ForwardedService`] is not used, this is the `Host` header
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Host(pub String);
+
+impl FromRequestParts for Host {
+    type Error = BadRequest<&'static str>;
+    type Future = Ready<Result<Self,Self::Error>>;
+
+    fn from_request_parts(parts: &mut request::Parts) -> Self::Future {
+        let host = parts
+            .extensions
+            .get::<Host>()
+            .cloned()
+            .or_else(||header_str(parts.headers.get(header::HOST)).map(|e|Host(e.to_owned())))
+            .or_else(||parts.uri.authority().map(|e|Host(e.to_string())));
+        ready(host.ok_or(BadRequest::new("missing host")))
+    }
+}
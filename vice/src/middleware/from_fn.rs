@@ -0,0 +1,103 @@
+//! middleware from async function
+use super::Layer;
+use crate::{
+    http::{IntoResponse, Request, Response},
+    util::futures::{FutureExt, Map},
+};
+use hyper::service::Service;
+use std::{convert::Infallible, future::Future, pin::Pin, sync::Arc};
+
+/// create middleware from async function
+///
+/// the function receive the request and [`Next`] which call the inner service
+///
+/// see [module level documentation](super) for example
+pub fn from_fn<F>(f: F) -> FromFn<F> {
+    FromFn { f }
+}
+
+/// [`Layer`] returned from [`from_fn`]
+#[derive(Clone, Copy)]
+pub struct FromFn<F> {
+    f: F,
+}
+
+impl<F,S> Layer<S> for FromFn<F>
+where
+    F: Clone,
+{
+    type Service = FromFnService<F,S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        FromFnService { f: self.f.clone(), inner: Arc::new(inner) }
+    }
+}
+
+/// middleware service created by [`from_fn`]
+pub struct FromFnService<F,S> {
+    f: F,
+    inner: Arc<S>,
+}
+
+impl<F,S> Clone for FromFnService<F,S>
+where
+    F: Clone,
+{
+    fn clone(&self) -> Self {
+        Self { f: self.f.clone(), inner: self.inner.clone() }
+    }
+}
+
+impl<F,S,Fut> Service<Request> for FromFnService<F,S>
+where
+    F: Fn(Request, Next) -> Fut,
+    Fut: Future,
+    Fut::Output: IntoResponse,
+    S: Service<Request, Response = Response, Error = Infallible> + Send + Sync + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Map<Fut, fn(Fut::Output) -> Result<Response,Infallible>>;
+
+    fn call(&self, req: Request) -> Self::Future {
+        fn into_response<R: IntoResponse>(res: R) -> Result<Response,Infallible> {
+            Ok(res.into_response())
+        }
+        (self.f)(req, Next::new(self.inner.clone())).map(into_response)
+    }
+}
+
+/// future returned from [`Next::run`]
+pub type NextFuture = Pin<Box<dyn Future<Output = Response> + Send + 'static>>;
+
+/// the rest of the middleware stack, including the route
+pub struct Next {
+    inner: Box<dyn FnOnce(Request) -> NextFuture + Send>,
+}
+
+impl Next {
+    /// create new `Next` that call given service
+    pub fn new<S>(inner: Arc<S>) -> Self
+    where
+        S: Service<Request, Response = Response, Error = Infallible> + Send + Sync + 'static,
+        S::Future: Send + 'static,
+    {
+        Self {
+            inner: Box::new(move |req| {
+                let f = inner.call(req);
+                Box::pin(async move {
+                    match f.await {
+                        Ok(res) => res,
+                        Err(err) => match err { },
+                    }
+                })
+            }),
+        }
+    }
+
+    /// call the inner service
+    pub fn run(self, req: Request) -> NextFuture {
+        (self.inner)(req)
+    }
+}
@@ -0,0 +1,403 @@
+//! JSON Web Token authentication
+//!
+//! [`Jwt`] verify the `Bearer` token of `Authorization` header using locally
+//! supplied keys or a JWKS file, supported algorithms are `HS256`, `RS256` and
+//! `ES256`
+//!
+//! the `exp` claim is required, `nbf`, `aud` and `iss` is checked when present,
+//! all time based claims tolerate [`Jwt::leeway`] of clock skew
+//!
+//! verified payload can be deserialized by handler using [`Claims`], invalid
+//! token is rejected with `401 Unauthorized` and `WWW-Authenticate` challenge
+//!
+//! # Example
+//!
+//! ```no_run
+//! use serde::Deserialize;
+//! use vice::{
+//!     middleware::jwt::{Claims, Jwt, JwtKey},
+//!     router::{Router, get},
+//! };
+//!
+//! #[derive(Clone, Deserialize)]
+//! struct User {
+//!     sub: String,
+//! }
+//!
+//! async fn handle(Claims(user): Claims<User>) -> String {
+//!     format!("hello {}", user.sub)
+//! }
+//!
+//! fn main() -> std::io::Result<()> {
+//!     let jwt = Jwt::new(JwtKey::hs256(b"secret")).issuer(["vice"]);
+//!     let route = Router::new()
+//!         .route("/", get(handle))
+//!         .layer(jwt);
+//!     vice::listen("0.0.0.0:3000", route)
+//! }
+//! ```
+use super::{
+    auth::{credentials, unauthorized, Bearer},
+    Layer,
+};
+use crate::http::{FromRequestParts, IntoResponse, Request, Response};
+use http::{request, StatusCode};
+use hyper::service::Service;
+use jsonwebtoken::{
+    errors::ErrorKind,
+    jwk::{AlgorithmParameters, Jwk, JwkSet, PublicKeyUse},
+    Algorithm, DecodingKey, Validation,
+};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::{
+    future::{ready, Ready},
+    io,
+    path::Path,
+    pin::Pin,
+    str::FromStr,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use jsonwebtoken::{encode, get_current_timestamp, EncodingKey, Header};
+    use crate::util::base64;
+    use serde_json::json;
+
+    fn token(claims: Value) -> String {
+        encode(&Header::default(), &claims, &EncodingKey::from_secret(b"secret")).unwrap()
+    }
+
+    #[test]
+    fn verify() {
+        let now = get_current_timestamp();
+        let jwt = Jwt::new(JwtKey::hs256(b"secret")).leeway(Duration::from_secs(30));
+        assert_eq!(jwt.verify(&token(json!({ "sub": "bob", "exp": now + 60 }))).unwrap()["sub"], "bob");
+        assert!(jwt.verify(&token(json!({ "exp": now - 10 }))).is_ok());
+        assert!(jwt.verify(&token(json!({ "exp": now - 60 }))).is_err());
+        assert!(jwt.verify(&token(json!({ "exp": now + 60, "nbf": now + 60 }))).is_err());
+        assert!(jwt.verify(&token(json!({ "sub": "bob" }))).is_err());
+
+        let jwt = jwt.audience(["api"]).issuer(["vice"]);
+        assert!(jwt.verify(&token(json!({ "exp": now + 60, "aud": "api", "iss": "vice" }))).is_ok());
+        assert!(jwt.verify(&token(json!({ "exp": now + 60, "aud": "web", "iss": "vice" }))).is_err());
+        assert!(jwt.verify(&token(json!({ "exp": now + 60, "aud": "api", "iss": "other" }))).is_err());
+        assert!(jwt.verify(&token(json!({ "exp": now + 60, "aud": "api" }))).is_err());
+
+        let other = encode(&Header::default(), &json!({ "exp": now + 60 }), &EncodingKey::from_secret(b"other")).unwrap();
+        assert!(jwt.verify(&other).is_err());
+    }
+
+    fn token_with(kid: Option<&str>, secret: &[u8], claims: Value) -> String {
+        let header = Header { kid: kid.map(ToOwned::to_owned), ..Header::default() };
+        encode(&header, &claims, &EncodingKey::from_secret(secret)).unwrap()
+    }
+
+    #[test]
+    fn rotated_jwks() {
+        let claims = json!({ "exp": get_current_timestamp() + 60 });
+        let jwk = |kid: &str, secret: &[u8]| json!({ "kty": "oct", "kid": kid, "k": base64::encode_url(secret) });
+        let path = std::env::temp_dir().join(format!("vice-jwks-{}.json", std::process::id()));
+        std::fs::write(&path, json!({ "keys": [jwk("old", b"old secret"), jwk("new", b"new secret")] }).to_string()).unwrap();
+        let jwt = Jwt::from_jwks(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(jwt.verify(&token_with(Some("new"), b"new secret", claims.clone())).is_ok());
+        assert!(jwt.verify(&token_with(Some("old"), b"old secret", claims.clone())).is_ok());
+        assert!(jwt.verify(&token_with(Some("old"), b"new secret", claims.clone())).is_err());
+        assert!(jwt.verify(&token_with(Some("gone"), b"new secret", claims.clone())).is_err());
+        assert!(jwt.verify(&token_with(None, b"new secret", claims)).is_ok());
+    }
+
+    #[test]
+    fn kid_less_key_first() {
+        let now = get_current_timestamp();
+        let claims = json!({ "exp": now + 60 });
+        let jwt = Jwt::new(JwtKey::hs256(b"any")).key(JwtKey::hs256(b"bound").kid("bound"));
+
+        assert!(jwt.verify(&token_with(Some("bound"), b"bound", claims.clone())).is_ok());
+        assert!(jwt.verify(&token_with(Some("bound"), b"any", claims.clone())).is_err());
+        assert!(jwt.verify(&token_with(Some("other"), b"any", claims.clone())).is_ok());
+        assert!(jwt.verify(&token_with(Some("other"), b"bound", claims.clone())).is_err());
+        assert!(jwt.verify(&token_with(None, b"bound", claims)).is_ok());
+
+        let expired = jwt.verify(&token_with(None, b"any", json!({ "exp": now - 120 }))).unwrap_err();
+        assert!(matches!(expired.kind(), ErrorKind::ExpiredSignature));
+    }
+}
+
+/// key used to verify token signature
+#[derive(Clone)]
+pub struct JwtKey {
+    kid: Option<String>,
+    algorithm: Algorithm,
+    key: DecodingKey,
+}
+
+impl JwtKey {
+    /// `HS256` shared secret
+    pub fn hs256(secret: &[u8]) -> Self {
+        Self { kid: None, algorithm: Algorithm::HS256, key: DecodingKey::from_secret(secret) }
+    }
+
+    /// `RS256` PEM encoded public key
+    pub fn rs256_pem(pem: &[u8]) -> Result<Self, JwtError> {
+        Ok(Self { kid: None, algorithm: Algorithm::RS256, key: DecodingKey::from_rsa_pem(pem)? })
+    }
+
+    /// `ES256` PEM encoded public key
+    pub fn es256_pem(pem: &[u8]) -> Result<Self, JwtError> {
+        Ok(Self { kid: None, algorithm: Algorithm::ES256, key: DecodingKey::from_ec_pem(pem)? })
+    }
+
+    /// only use this key for token with matching `kid` header
+    pub fn kid(mut self, kid: impl Into<String>) -> Self {
+        self.kid = Some(kid.into());
+        self
+    }
+
+    /// returns `None` if the key is not for signature, or the algorithm is unsupported
+    fn from_jwk(jwk: &Jwk) -> Option<Result<Self, JwtError>> {
+        if matches!(jwk.common.public_key_use, Some(PublicKeyUse::Encryption)) {
+            return None;
+        }
+        let algorithm = match (jwk.common.key_algorithm, &jwk.algorithm) {
+            (Some(alg), _) => Algorithm::from_str(&alg.to_string()).ok()?,
+            (None, AlgorithmParameters::OctetKey(_)) => Algorithm::HS256,
+            (None, AlgorithmParameters::RSA(_)) => Algorithm::RS256,
+            (None, AlgorithmParameters::EllipticCurve(_)) => Algorithm::ES256,
+            (None, AlgorithmParameters::OctetKeyPair(_)) => return None,
+        };
+        if !matches!(algorithm, Algorithm::HS256 | Algorithm::RS256 | Algorithm::ES256) {
+            return None;
+        }
+        let key = match DecodingKey::from_jwk(jwk) {
+            Ok(key) => key,
+            Err(err) => return Some(Err(err.into())),
+        };
+        Some(Ok(Self { kid: jwk.common.key_id.clone(), algorithm, key }))
+    }
+}
+
+/// error when loading verification key
+#[derive(thiserror::Error, Debug)]
+pub enum JwtError {
+    #[error("failed to read jwks: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid jwks: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("invalid key: {0}")]
+    Key(#[from] jsonwebtoken::errors::Error),
+}
+
+/// JWT authentication layer
+///
+/// see [module level documentation](self) for more details
+#[derive(Clone)]
+pub struct Jwt {
+    keys: Vec<JwtKey>,
+    validation: Validation,
+    realm: Arc<str>,
+}
+
+impl Jwt {
+    /// create new `Jwt` with given key
+    ///
+    /// default leeway is 60 seconds, `aud` and `iss` is not checked
+    pub fn new(key: JwtKey) -> Self {
+        let mut validation = Validation::new(key.algorithm);
+        validation.validate_nbf = true;
+        validation.validate_aud = false;
+        Self { keys: vec![key], validation, realm: Arc::from("api") }
+    }
+
+    /// create new `Jwt` with keys from JWKS file
+    ///
+    /// keys for encryption or unsupported algorithm is ignored
+    pub fn from_jwks(path: impl AsRef<Path>) -> Result<Self, JwtError> {
+        let jwks: JwkSet = serde_json::from_slice(&std::fs::read(path)?)?;
+        let mut keys = jwks.keys.iter().filter_map(JwtKey::from_jwk);
+        let Some(key) = keys.next() else {
+            return Err(jsonwebtoken::errors::Error::from(ErrorKind::InvalidKeyFormat).into());
+        };
+        keys.try_fold(Self::new(key?), |jwt, key| Ok(jwt.key(key?)))
+    }
+
+    /// add another verification key
+    pub fn key(mut self, key: JwtKey) -> Self {
+        self.keys.push(key);
+        self
+    }
+
+    /// accepted `aud` claim, token without `aud` claim is rejected
+    pub fn audience<T: ToString>(mut self, aud: impl IntoIterator<Item = T>) -> Self {
+        self.validation.set_audience(&aud.into_iter().collect::<Vec<_>>());
+        self.validation.validate_aud = true;
+        self.validation.required_spec_claims.insert("aud".to_owned());
+        self
+    }
+
+    /// accepted `iss` claim, token without `iss` claim is rejected
+    pub fn issuer<T: ToString>(mut self, iss: impl IntoIterator<Item = T>) -> Self {
+        self.validation.set_issuer(&iss.into_iter().collect::<Vec<_>>());
+        self.validation.required_spec_claims.insert("iss".to_owned());
+        self
+    }
+
+    /// clock skew tolerance of `exp` and `nbf` claim
+    pub fn leeway(mut self, leeway: Duration) -> Self {
+        self.validation.leeway = leeway.as_secs();
+        self
+    }
+
+    /// realm of `WWW-Authenticate` challenge, default to `api`
+    pub fn realm(mut self, realm: impl Into<Arc<str>>) -> Self {
+        self.realm = realm.into();
+        self
+    }
+
+    /// verify token and returns its claims
+    ///
+    /// key with the exact `kid` is used when there is one, otherwise every key
+    /// with matching algorithm is tried, except keys bound to another `kid`
+    fn verify(&self, token: &str) -> Result<Value, jsonwebtoken::errors::Error> {
+        let header = jsonwebtoken::decode_header(token)?;
+        let keys = self.keys.iter().filter(|e|e.algorithm == header.alg);
+        if let Some(kid) = &header.kid
+            && let Some(key) = keys.clone().find(|e|e.kid.as_ref() == Some(kid))
+        {
+            return self.decode(token, key);
+        }
+
+        let mut error = jsonwebtoken::errors::Error::from(ErrorKind::InvalidAlgorithm);
+        for key in keys.filter(|e|e.kid.is_none() || header.kid.is_none()) {
+            match self.decode(token, key) {
+                Ok(claims) => return Ok(claims),
+                // signature mismatch of other keys should not hide e.g. expired token
+                Err(err) if matches!(err.kind(), ErrorKind::InvalidSignature)
+                    && !matches!(error.kind(), ErrorKind::InvalidAlgorithm) => {}
+                Err(err) => error = err,
+            }
+        }
+        Err(error)
+    }
+
+    fn decode(&self, token: &str, key: &JwtKey) -> Result<Value, jsonwebtoken::errors::Error> {
+        let mut validation = self.validation.clone();
+        validation.algorithms = vec![key.algorithm];
+        Ok(jsonwebtoken::decode(token, &key.key, &validation)?.claims)
+    }
+}
+
+impl<S> Layer<S> for Jwt {
+    type Service = JwtService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        JwtService { inner, jwt: Arc::new(self.clone()) }
+    }
+}
+
+/// service that verify JWT of the request
+#[derive(Clone)]
+pub struct JwtService<S> {
+    inner: S,
+    jwt: Arc<Jwt>,
+}
+
+impl<S,B> Service<Request<B>> for JwtService<S>
+where
+    S: Service<Request<B>, Response = Response>,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = JwtFuture<S::Future>;
+
+    fn call(&self, mut req: Request<B>) -> Self::Future {
+        let Some(token) = credentials::<Bearer>(req.headers()) else {
+            return JwtFuture::Rejected { res: Some(unauthorized::<Bearer>(&self.jwt.realm, false)) };
+        };
+        match self.jwt.verify(&token.0) {
+            Ok(claims) => {
+                req.extensions_mut().insert(RawClaims(Arc::new(claims)));
+                JwtFuture::Inner { f: self.inner.call(req) }
+            }
+            Err(err) => {
+                log::debug!("jwt rejected: {err}");
+                JwtFuture::Rejected { res: Some(unauthorized::<Bearer>(&self.jwt.realm, true)) }
+            }
+        }
+    }
+}
+
+pin_project_lite::pin_project! {
+    /// future returned from [`JwtService`]
+    #[project = JwtProj]
+    pub enum JwtFuture<F> {
+        Inner { #[pin] f: F },
+        Rejected { res: Option<Response> },
+    }
+}
+
+impl<F,E> Future for JwtFuture<F>
+where
+    F: Future<Output = Result<Response,E>>,
+{
+    type Output = Result<Response,E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project() {
+            JwtProj::Inner { f } => f.poll(cx),
+            JwtProj::Rejected { res } => Poll::Ready(Ok(res.take().expect("poll after complete"))),
+        }
+    }
+}
+
+/// verified claims inserted by [`JwtService`]
+#[derive(Clone)]
+struct RawClaims(Arc<Value>);
+
+/// verified token payload
+///
+/// when used as extractor, [`Jwt`] must be applied
+#[derive(Clone, Debug)]
+pub struct Claims<T>(pub T);
+
+impl<T> FromRequestParts for Claims<T>
+where
+    T: DeserializeOwned,
+{
+    type Error = ClaimsError;
+    type Future = Ready<Result<Self,Self::Error>>;
+
+    fn from_request_parts(parts: &mut request::Parts) -> Self::Future {
+        let result = match parts.extensions.get::<RawClaims>() {
+            Some(RawClaims(claims)) => T::deserialize(&**claims).map(Claims).map_err(ClaimsError::Invalid),
+            None => Err(ClaimsError::Missing),
+        };
+        ready(result)
+    }
+}
+
+/// error when extracting [`Claims`]
+#[derive(thiserror::Error, Debug)]
+pub enum ClaimsError {
+    /// [`Jwt`] is not applied
+    #[error("jwt claims is not available")]
+    Missing,
+    /// claims does not match the expected type
+    #[error("invalid jwt claims: {0}")]
+    Invalid(serde_json::Error),
+}
+
+impl IntoResponse for ClaimsError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::Missing => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()).into_response(),
+            Self::Invalid(_) => (StatusCode::UNAUTHORIZED, self.to_string()).into_response(),
+        }
+    }
+}
@@ -0,0 +1,420 @@
+//! prometheus metrics
+//!
+//! [`Metrics`] record request count and latency histogram labelled by
+//! [`MatchedPath`], method and status class, and in flight requests labelled
+//! by [`MatchedPath`] and method
+//!
+//! the in flight route label is empty until the router match the request
+//!
+//! [`Metrics::handler`] render the metrics in prometheus text exposition format,
+//! including connection counters from the runtime when [`ServerStats`] is attached
+//!
+//! # Example
+//!
+//! ```no_run
+//! use vice::{middleware::metrics::Metrics, router::{Router, get}, runtime::Server};
+//!
+//! fn main() -> std::io::Result<()> {
+//!     let server = Server::bind("0.0.0.0:3000")?;
+//!     let metrics = Metrics::new().server_stats(server.stats());
+//!     let route = Router::new()
+//!         .route("/", get(||async { "Vice Dev" }))
+//!         .route("/metrics", metrics.handler())
+//!         .layer(metrics);
+//!     server.serve(route)
+//! }
+//! ```
+use super::Layer;
+use crate::{
+    http::{IntoResponse, Request, Response},
+    router::{MatchedPath, RouteSlot},
+    runtime::ServerStats,
+};
+use http::Method;
+use hyper::service::Service;
+use std::{
+    collections::BTreeMap,
+    convert::Infallible,
+    fmt::Write,
+    future::{ready, Ready},
+    pin::Pin,
+    sync::{Arc, Mutex, OnceLock},
+    task::{Context, Poll},
+    time::Instant,
+};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn render() {
+        let metrics = Metrics::with_buckets([0.1, 1.0]);
+        let slot = RouteSlot::default();
+        let guard = metrics.inner.start(&Method::GET, &slot);
+        metrics.inner.observe("/users", &Method::GET, 200, 0.05);
+        metrics.inner.observe("/users", &Method::GET, 201, 0.5);
+        metrics.inner.observe(UNMATCHED, &Method::POST, 404, 2.0);
+
+        let out = metrics.render();
+        assert!(out.contains("http_requests_total{method=\"GET\",route=\"/users\",status=\"2xx\"} 2\n"));
+        assert!(out.contains("http_request_duration_seconds_bucket{method=\"GET\",route=\"/users\",status=\"2xx\",le=\"0.1\"} 1\n"));
+        assert!(out.contains("http_request_duration_seconds_bucket{method=\"GET\",route=\"/users\",status=\"2xx\",le=\"1\"} 2\n"));
+        assert!(out.contains("http_request_duration_seconds_bucket{method=\"POST\",route=\"\",status=\"4xx\",le=\"+Inf\"} 1\n"));
+        assert!(out.contains("http_request_duration_seconds_sum{method=\"GET\",route=\"/users\",status=\"2xx\"} 0.55\n"));
+        assert!(out.contains("http_requests_in_flight{method=\"GET\",route=\"\"} 1\n"));
+
+        // gauge move to the route once the router match
+        let _ = slot.0.set("/users");
+        let mut guard = guard;
+        guard.relabel();
+        let out = metrics.render();
+        assert!(out.contains("http_requests_in_flight{method=\"GET\",route=\"\"} 0\n"));
+        assert!(out.contains("http_requests_in_flight{method=\"GET\",route=\"/users\"} 1\n"));
+        drop(guard);
+        assert!(metrics.render().contains("http_requests_in_flight{method=\"GET\",route=\"/users\"} 0\n"));
+    }
+
+    #[test]
+    fn in_flight_route() {
+        let metrics = Metrics::new();
+        let route = crate::router::Router::new()
+            .route("/users", crate::router::get(std::future::pending::<()>))
+            .layer(metrics.clone());
+        let mut req = Request::new(Default::default());
+        *req.uri_mut() = "/users".parse().unwrap();
+
+        let mut cx = Context::from_waker(std::task::Waker::noop());
+        let mut f = Box::pin(route.call(req));
+        assert!(f.as_mut().poll(&mut cx).is_pending());
+        assert!(metrics.render().contains("http_requests_in_flight{method=\"GET\",route=\"/users\"} 1\n"));
+    }
+
+    #[test]
+    fn server_stats() {
+        let stats = ServerStats::default();
+        let _conn = stats.connection();
+        // order relative to cloning does not matter
+        let metrics = Metrics::new();
+        let _handler = metrics.handler();
+        let out = metrics.server_stats(stats).render();
+        assert!(out.contains("vice_connections_total 1\n"));
+        assert!(out.contains("vice_connections_active 1\n"));
+        assert!(out.contains("vice_requests_in_flight 0\n"));
+        assert!(out.contains("vice_requests_shed_total 0\n"));
+    }
+}
+
+/// route label of request that does not match any route path
+const UNMATCHED: &str = "";
+
+const DEFAULT_BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// metrics registry and instrumentation layer
+///
+/// cloning `Metrics` returns a handle to the same registry,
+/// see [module level documentation](self) for more details
+#[derive(Clone)]
+pub struct Metrics {
+    inner: Arc<Registry>,
+}
+
+struct Registry {
+    buckets: Box<[f64]>,
+    requests: Mutex<BTreeMap<(&'static str, &'static str, &'static str), Series>>,
+    in_flight: Mutex<BTreeMap<(&'static str, &'static str), i64>>,
+    server: OnceLock<ServerStats>,
+}
+
+struct Series {
+    count: u64,
+    sum: f64,
+    buckets: Box<[u64]>,
+}
+
+impl Metrics {
+    /// create new `Metrics` with default latency buckets
+    pub fn new() -> Self {
+        Self::with_buckets(DEFAULT_BUCKETS)
+    }
+
+    /// create new `Metrics` with given latency buckets in seconds
+    pub fn with_buckets(buckets: impl IntoIterator<Item = f64>) -> Self {
+        let mut buckets = buckets.into_iter().collect::<Vec<_>>();
+        buckets.sort_by(f64::total_cmp);
+        Self {
+            inner: Arc::new(Registry {
+                buckets: buckets.into(),
+                requests: Mutex::default(),
+                in_flight: Mutex::default(),
+                server: OnceLock::new(),
+            }),
+        }
+    }
+
+    /// include runtime connection counters in rendered metrics
+    ///
+    /// this apply to every clone of the `Metrics`, only the stats of the
+    /// first call is used
+    pub fn server_stats(self, stats: ServerStats) -> Self {
+        let _ = self.inner.server.set(stats);
+        self
+    }
+
+    /// service that render metrics in prometheus text format
+    pub fn handler(&self) -> MetricsHandler {
+        MetricsHandler { metrics: self.clone() }
+    }
+
+    /// render metrics in prometheus text format
+    pub fn render(&self) -> String {
+        let mut out = String::with_capacity(1024);
+        let registry = &self.inner;
+
+        {
+            let requests = registry.requests.lock().unwrap_or_else(|e|e.into_inner());
+
+            out.push_str("# HELP http_requests_total Total number of HTTP requests.\n");
+            out.push_str("# TYPE http_requests_total counter\n");
+            for ((route, method, status), series) in requests.iter() {
+                let _ = writeln!(out, "http_requests_total{{method=\"{method}\",route=\"{}\",status=\"{status}\"}} {}",
+                    Escape(route), series.count);
+            }
+
+            out.push_str("# HELP http_request_duration_seconds HTTP request latency.\n");
+            out.push_str("# TYPE http_request_duration_seconds histogram\n");
+            for ((route, method, status), series) in requests.iter() {
+                let labels = format!("method=\"{method}\",route=\"{}\",status=\"{status}\"", Escape(route));
+                for (le, count) in registry.buckets.iter().zip(series.buckets.iter()) {
+                    let _ = writeln!(out, "http_request_duration_seconds_bucket{{{labels},le=\"{le}\"}} {count}");
+                }
+                let _ = writeln!(out, "http_request_duration_seconds_bucket{{{labels},le=\"+Inf\"}} {}", series.count);
+                let _ = writeln!(out, "http_request_duration_seconds_sum{{{labels}}} {}", series.sum);
+                let _ = writeln!(out, "http_request_duration_seconds_count{{{labels}}} {}", series.count);
+            }
+        }
+
+        {
+            let in_flight = registry.in_flight.lock().unwrap_or_else(|e|e.into_inner());
+            out.push_str("# HELP http_requests_in_flight Number of HTTP requests being processed.\n");
+            out.push_str("# TYPE http_requests_in_flight gauge\n");
+            for ((route, method), count) in in_flight.iter() {
+                let _ = writeln!(out, "http_requests_in_flight{{method=\"{method}\",route=\"{}\"}} {count}", Escape(route));
+            }
+        }
+
+        if let Some(stats) = registry.server.get() {
+            let counters = [
+                ("vice_connections_total", "counter", "Total number of accepted connections.", stats.connections_total()),
+                ("vice_connections_active", "gauge", "Number of open connections.", stats.connections_active()),
+                ("vice_accept_paused_total", "counter", "Number of times accept is paused by connection limit.", stats.accept_paused()),
+                ("vice_requests_in_flight", "gauge", "Number of requests being processed by the runtime.", stats.requests_in_flight()),
+                ("vice_requests_shed_total", "counter", "Number of requests rejected by request limit.", stats.requests_shed()),
+            ];
+            for (name, kind, help, value) in counters {
+                let _ = write!(out, "# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {value}\n");
+            }
+        }
+
+        out
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Registry {
+    fn start(self: &Arc<Self>, method: &Method, slot: &RouteSlot) -> InFlightGuard {
+        let key = (slot.0.get().copied().unwrap_or(UNMATCHED), method_label(method));
+        *self.in_flight.lock().unwrap_or_else(|e|e.into_inner()).entry(key).or_default() += 1;
+        InFlightGuard { registry: self.clone(), key, slot: slot.clone() }
+    }
+
+    fn observe(&self, route: &'static str, method: &Method, status: u16, latency: f64) {
+        let class = match status {
+            100..=199 => "1xx",
+            200..=299 => "2xx",
+            300..=399 => "3xx",
+            400..=499 => "4xx",
+            _ => "5xx",
+        };
+        let mut requests = self.requests.lock().unwrap_or_else(|e|e.into_inner());
+        let series = requests.entry((route, method_label(method), class)).or_insert_with(|| Series {
+            count: 0,
+            sum: 0.0,
+            buckets: vec![0; self.buckets.len()].into(),
+        });
+        series.count += 1;
+        series.sum += latency;
+        for (le, count) in self.buckets.iter().zip(series.buckets.iter_mut()) {
+            if latency <= *le {
+                *count += 1;
+            }
+        }
+    }
+}
+
+/// method label, non standard method is grouped to prevent label explosion
+fn method_label(method: &Method) -> &'static str {
+    match *method {
+        Method::GET => "GET",
+        Method::POST => "POST",
+        Method::PUT => "PUT",
+        Method::DELETE => "DELETE",
+        Method::HEAD => "HEAD",
+        Method::OPTIONS => "OPTIONS",
+        Method::CONNECT => "CONNECT",
+        Method::PATCH => "PATCH",
+        Method::TRACE => "TRACE",
+        _ => "OTHER",
+    }
+}
+
+/// escape prometheus label value
+struct Escape<'a>(&'a str);
+
+impl std::fmt::Display for Escape<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for ch in self.0.chars() {
+            match ch {
+                '\\' => f.write_str("\\\\")?,
+                '"' => f.write_str("\\\"")?,
+                '\n' => f.write_str("\\n")?,
+                ch => f.write_char(ch)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// decrement in flight gauge on drop
+pub struct InFlightGuard {
+    registry: Arc<Registry>,
+    /// route and method label
+    key: (&'static str, &'static str),
+    slot: RouteSlot,
+}
+
+impl InFlightGuard {
+    /// move the gauge to the matched route once the router match
+    fn relabel(&mut self) {
+        if self.key.0 != UNMATCHED {
+            return;
+        }
+        let Some(route) = self.slot.0.get().copied() else {
+            return;
+        };
+        let mut in_flight = self.registry.in_flight.lock().unwrap_or_else(|e|e.into_inner());
+        if let Some(count) = in_flight.get_mut(&self.key) {
+            *count -= 1;
+        }
+        self.key.0 = route;
+        *in_flight.entry(self.key).or_default() += 1;
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if let Some(count) = self.registry.in_flight.lock().unwrap_or_else(|e|e.into_inner()).get_mut(&self.key) {
+            *count -= 1;
+        }
+    }
+}
+
+impl<S> Layer<S> for Metrics {
+    type Service = MetricsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MetricsService { inner, metrics: self.clone() }
+    }
+}
+
+/// service that record request metrics
+#[derive(Clone)]
+pub struct MetricsService<S> {
+    inner: S,
+    metrics: Metrics,
+}
+
+impl<S,B> Service<Request<B>> for MetricsService<S>
+where
+    S: Service<Request<B>, Response = Response>,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = MetricsFuture<S::Future>;
+
+    fn call(&self, mut req: Request<B>) -> Self::Future {
+        let method = req.method().clone();
+        let slot = RouteSlot::default();
+        req.extensions_mut().insert(slot.clone());
+        let inner = self.inner.call(req);
+        // router typically match synchronously in `call`, so the route is known here
+        let guard = self.metrics.inner.start(&method, &slot);
+        MetricsFuture {
+            inner,
+            method,
+            start: Instant::now(),
+            guard: Some(guard),
+        }
+    }
+}
+
+pin_project_lite::pin_project! {
+    /// future returned from [`MetricsService`]
+    pub struct MetricsFuture<F> {
+        #[pin]
+        inner: F,
+        method: Method,
+        start: Instant,
+        guard: Option<InFlightGuard>,
+    }
+}
+
+impl<F,E> Future for MetricsFuture<F>
+where
+    F: Future<Output = Result<Response,E>>,
+{
+    type Output = Result<Response,E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let me = self.project();
+        if let Some(guard) = me.guard {
+            guard.relabel();
+        }
+        let res = std::task::ready!(me.inner.poll(cx));
+        if let Some(guard) = me.guard.take() {
+            let status = match &res {
+                Ok(res) => res.status().as_u16(),
+                Err(_) => 500,
+            };
+            let route = res
+                .as_ref()
+                .ok()
+                .and_then(|e|e.extensions().get::<MatchedPath>())
+                .map_or(UNMATCHED, |e|e.as_str());
+            guard.registry.observe(route, me.method, status, me.start.elapsed().as_secs_f64());
+        }
+        Poll::Ready(res)
+    }
+}
+
+/// service that render metrics, returned from [`Metrics::handler`]
+#[derive(Clone)]
+pub struct MetricsHandler {
+    metrics: Metrics,
+}
+
+impl<B> Service<Request<B>> for MetricsHandler {
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Ready<Result<Response,Infallible>>;
+
+    fn call(&self, _: Request<B>) -> Self::Future {
+        let body = self.metrics.render();
+        ready(Ok((("Content-Type", "text/plain; version=0.0.4"), body).into_response()))
+    }
+}
@@ -0,0 +1,527 @@
+//! rate limiting
+//!
+//! [`RateLimit`] limit request rate per key using token bucket or sliding window
+//! algorithm, the key is the client ip by default, and can be taken from a header
+//! or a custom function
+//!
+//! request that exceeds the quota is rejected with `429 Too Many Requests` and
+//! `Retry-After` header, every response contains `RateLimit-Limit`,
+//! `RateLimit-Remaining` and `RateLimit-Reset` headers
+//!
+//! state is kept in memory in a sharded map, keys that are idle long enough to
+//! be fully replenished are evicted periodically while handling requests, one
+//! shard at a time so no single request pays for sweeping the whole map
+//!
+//! every `RateLimit` have its own state, so per route quota can be applied with
+//! [`Router::route_layer`]
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use vice::{
+//!     middleware::rate_limit::{Key, Quota, RateLimit},
+//!     router::{Router, get},
+//! };
+//!
+//! fn main() -> std::io::Result<()> {
+//!     let route = Router::new()
+//!         .route("/login", get(||async { "login" }))
+//!         .route_layer(RateLimit::new(Quota::new(5, Duration::from_secs(60))).sliding_window())
+//!         .route("/", get(||async { "Vice Dev" }))
+//!         .layer(RateLimit::new(Quota::per_second(20).burst(40)).key(Key::header("x-api-key")));
+//!     vice::listen("0.0.0.0:3000", route)
+//! }
+//! ```
+//!
+//! [`Router::route_layer`]: crate::router::Router::route_layer
+use super::{forwarded::ClientIp, Layer};
+use crate::{
+    http::{IntoResponse, Request, Response},
+    runtime::Connection,
+};
+use http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
+use hyper::service::Service;
+use std::{
+    collections::HashMap,
+    fmt,
+    hash::{BuildHasher, RandomState},
+    net::SocketAddr,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::{ready, Context, Poll},
+    time::{Duration, Instant},
+};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn token_bucket() {
+        let quota = Quota::new(2, Duration::from_secs(2)).burst(3);
+        let now = Instant::now();
+        let mut state = State::new(Algorithm::TokenBucket, now);
+        for remaining in [2, 1, 0] {
+            assert_eq!(state.check(&quota, now), Decision::Allow { remaining, reset: Duration::from_secs(3 - remaining as u64) });
+        }
+        assert_eq!(state.check(&quota, now), Decision::Deny { retry_after: Duration::from_secs(1) });
+        assert!(matches!(state.check(&quota, now + Duration::from_secs(1)), Decision::Allow { remaining: 0, .. }));
+        assert!(!state.is_idle(&quota, now + Duration::from_secs(2)));
+        assert!(state.is_idle(&quota, now + Duration::from_secs(4)));
+    }
+
+    #[test]
+    fn sliding_window() {
+        let quota = Quota::new(4, Duration::from_secs(10));
+        let now = Instant::now();
+        let mut state = State::new(Algorithm::SlidingWindow, now);
+        for _ in 0..4 {
+            assert!(matches!(state.check(&quota, now), Decision::Allow { .. }));
+        }
+        assert_eq!(state.check(&quota, now + Duration::from_secs(5)), Decision::Deny { retry_after: Duration::from_secs(5) });
+
+        // previous window weighted by 50%, 4 * 0.5 = 2 remaining
+        let later = now + Duration::from_secs(15);
+        assert!(matches!(state.check(&quota, later), Decision::Allow { remaining: 1, .. }));
+        assert!(matches!(state.check(&quota, later), Decision::Allow { remaining: 0, .. }));
+        assert!(matches!(state.check(&quota, later), Decision::Deny { .. }));
+        assert!(state.is_idle(&quota, now + Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn evict() {
+        let quota = Quota::per_second(1);
+        let store = Store::new();
+        let now = Instant::now();
+        for key in 0..256 {
+            store.check(key.to_string(), Algorithm::TokenBucket, &quota, now);
+        }
+        let lens = || store.shards.iter().map(|e|e.lock().unwrap().len()).collect::<Vec<_>>();
+        let before = lens();
+
+        // only one shard is swept per call
+        let later = now + Duration::from_secs(10);
+        store.evict(&quota, Duration::ZERO, later);
+        let after = lens();
+        assert_eq!(after[0], 0);
+        assert_eq!(after[1..], before[1..]);
+
+        // next shard is swept once interval / SHARDS have passed
+        store.evict(&quota, Duration::from_secs(160), later);
+        store.evict(&quota, Duration::from_secs(160), later + Duration::from_secs(9));
+        assert_eq!(lens()[1], 0);
+        assert_eq!(lens()[2..], before[2..]);
+        store.evict(&quota, Duration::from_secs(160), later + Duration::from_secs(10));
+        assert_eq!(lens()[2], 0);
+
+        for _ in 0..SHARDS {
+            store.evict(&quota, Duration::ZERO, later + Duration::from_secs(60));
+        }
+        assert!(lens().iter().all(|e|*e == 0));
+    }
+}
+
+/// number of shards of the in memory store
+const SHARDS: usize = 16;
+
+/// request quota
+#[derive(Clone, Copy, Debug)]
+pub struct Quota {
+    limit: u32,
+    period: Duration,
+    burst: u32,
+}
+
+impl Quota {
+    /// allow `limit` requests every `period`
+    ///
+    /// # Panics
+    ///
+    /// panics if `limit` or `period` is zero
+    pub fn new(limit: u32, period: Duration) -> Self {
+        assert!(limit != 0 && !period.is_zero(), "quota should not be zero");
+        Self { limit, period, burst: limit }
+    }
+
+    /// allow `limit` requests every second
+    pub fn per_second(limit: u32) -> Self {
+        Self::new(limit, Duration::from_secs(1))
+    }
+
+    /// allow `limit` requests every minute
+    pub fn per_minute(limit: u32) -> Self {
+        Self::new(limit, Duration::from_secs(60))
+    }
+
+    /// maximum token bucket capacity, default to the limit
+    ///
+    /// only used by token bucket algorithm
+    pub fn burst(mut self, burst: u32) -> Self {
+        self.burst = burst.max(1);
+        self
+    }
+
+    /// token replenished per second
+    fn rate(&self) -> f64 {
+        self.limit as f64 / self.period.as_secs_f64()
+    }
+}
+
+/// rate limiting algorithm
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    /// tokens are replenished continuously, allowing burst up to the capacity
+    TokenBucket,
+    /// request count is estimated from current and weighted previous window
+    SlidingWindow,
+}
+
+/// rate limit key
+#[derive(Clone)]
+pub enum Key {
+    /// client ip, from [`ClientIp`] if [`Forwarded`] is applied, otherwise peer address
+    ///
+    /// [`Forwarded`]: super::forwarded::Forwarded
+    Ip,
+    /// value of request header, request without the header is not limited
+    Header(HeaderName),
+    /// custom function, request with `None` key is not limited
+    Fn(Arc<KeyFn>),
+}
+
+/// custom key function of [`Key::Fn`]
+pub type KeyFn = dyn Fn(&Request) -> Option<String> + Send + Sync;
+
+impl Key {
+    /// key by value of request header
+    ///
+    /// # Panics
+    ///
+    /// panics if `name` is not valid header name
+    pub fn header(name: &str) -> Self {
+        Self::Header(name.parse().expect("invalid header name"))
+    }
+
+    /// key by custom function
+    pub fn from_fn<F>(f: F) -> Self
+    where
+        F: Fn(&Request) -> Option<String> + Send + Sync + 'static,
+    {
+        Self::Fn(Arc::new(f))
+    }
+
+    fn get(&self, req: &Request) -> Option<String> {
+        match self {
+            Key::Ip => {
+                let ext = req.extensions();
+                ext.get::<ClientIp>()
+                    .map(|e|e.0)
+                    .or_else(||ext.get::<Connection>().map(|e|e.peer_addr().ip()))
+                    .or_else(||ext.get::<SocketAddr>().map(|e|e.ip()))
+                    .map(|e|e.to_string())
+            }
+            Key::Header(name) => req.headers().get(name).map(|e|String::from_utf8_lossy(e.as_bytes()).into_owned()),
+            Key::Fn(f) => f(req),
+        }
+    }
+}
+
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ip => f.write_str("Ip"),
+            Self::Header(name) => f.debug_tuple("Header").field(name).finish(),
+            Self::Fn(_) => f.write_str("Fn"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Decision {
+    Allow { remaining: u32, reset: Duration },
+    Deny { retry_after: Duration },
+}
+
+/// rate limit state of a single key
+enum State {
+    TokenBucket { tokens: f64, last: Instant },
+    SlidingWindow { start: Instant, current: u32, previous: u32 },
+}
+
+impl State {
+    fn new(algorithm: Algorithm, now: Instant) -> Self {
+        match algorithm {
+            Algorithm::TokenBucket => State::TokenBucket { tokens: f64::INFINITY, last: now },
+            Algorithm::SlidingWindow => State::SlidingWindow { start: now, current: 0, previous: 0 },
+        }
+    }
+
+    fn check(&mut self, quota: &Quota, now: Instant) -> Decision {
+        match self {
+            State::TokenBucket { tokens, last } => {
+                let rate = quota.rate();
+                let capacity = quota.burst as f64;
+                *tokens = (*tokens + now.saturating_duration_since(*last).as_secs_f64() * rate).min(capacity);
+                *last = now;
+
+                if *tokens < 1.0 {
+                    return Decision::Deny { retry_after: Duration::from_secs_f64((1.0 - *tokens) / rate) };
+                }
+                *tokens -= 1.0;
+                Decision::Allow {
+                    remaining: *tokens as u32,
+                    reset: Duration::from_secs_f64((capacity - *tokens) / rate),
+                }
+            }
+            State::SlidingWindow { start, current, previous } => {
+                let period = quota.period;
+                let mut elapsed = now.saturating_duration_since(*start);
+                if elapsed >= period * 2 {
+                    (*start, *current, *previous) = (now, 0, 0);
+                    elapsed = Duration::ZERO;
+                } else if elapsed >= period {
+                    (*start, *previous, *current) = (*start + period, *current, 0);
+                    elapsed -= period;
+                }
+
+                let weight = 1.0 - elapsed.as_secs_f64() / period.as_secs_f64();
+                let estimated = *previous as f64 * weight + *current as f64;
+                let limit = quota.limit as f64;
+                let reset = period - elapsed;
+
+                if estimated + 1.0 > limit {
+                    // wait until the weighted previous window drop enough,
+                    // or until the current window end
+                    let retry_after = match *previous {
+                        0 => reset,
+                        previous if *current < quota.limit => {
+                            let excess = estimated + 1.0 - limit;
+                            period.mul_f64(excess / previous as f64).min(reset)
+                        }
+                        _ => reset,
+                    };
+                    return Decision::Deny { retry_after };
+                }
+                *current += 1;
+                Decision::Allow { remaining: (limit - estimated - 1.0) as u32, reset }
+            }
+        }
+    }
+
+    /// returns `true` if the state is equal to a fresh state
+    fn is_idle(&self, quota: &Quota, now: Instant) -> bool {
+        match self {
+            State::TokenBucket { tokens, last } => {
+                let refill = now.saturating_duration_since(*last).as_secs_f64() * quota.rate();
+                *tokens + refill >= quota.burst as f64
+            }
+            State::SlidingWindow { start, .. } => now.saturating_duration_since(*start) >= quota.period * 2,
+        }
+    }
+}
+
+/// sharded in memory state store
+struct Store {
+    shards: Box<[Mutex<HashMap<String, State>>]>,
+    hasher: RandomState,
+    epoch: Instant,
+    /// milliseconds since `epoch` of the next shard eviction
+    next_evict: AtomicU64,
+    /// index of the next shard to evict
+    next_shard: AtomicUsize,
+}
+
+impl Store {
+    fn new() -> Self {
+        Self {
+            shards: (0..SHARDS).map(|_|Mutex::default()).collect(),
+            hasher: RandomState::new(),
+            epoch: Instant::now(),
+            next_evict: AtomicU64::new(0),
+            next_shard: AtomicUsize::new(0),
+        }
+    }
+
+    fn check(&self, key: String, algorithm: Algorithm, quota: &Quota, now: Instant) -> Decision {
+        let shard = &self.shards[self.hasher.hash_one(&key) as usize % SHARDS];
+        let mut shard = shard.lock().unwrap_or_else(|e|e.into_inner());
+        shard.entry(key).or_insert_with(||State::new(algorithm, now)).check(quota, now)
+    }
+
+    /// remove idle keys of the next shard, every shard is swept once per interval
+    fn evict(&self, quota: &Quota, interval: Duration, now: Instant) {
+        let now_ms = now.saturating_duration_since(self.epoch).as_millis() as u64;
+        let next = self.next_evict.load(Ordering::Relaxed);
+        if now_ms < next {
+            return;
+        }
+        let after = now_ms + (interval / SHARDS as u32).as_millis() as u64;
+        if self.next_evict.compare_exchange(next, after, Ordering::Relaxed, Ordering::Relaxed).is_err() {
+            return;
+        }
+        let shard = &self.shards[self.next_shard.fetch_add(1, Ordering::Relaxed) % SHARDS];
+        shard.lock().unwrap_or_else(|e|e.into_inner()).retain(|_,state|!state.is_idle(quota, now));
+    }
+}
+
+/// rate limiting layer
+///
+/// cloning `RateLimit` returns a handle to the same state,
+/// see [module level documentation](self) for more details
+#[derive(Clone)]
+pub struct RateLimit {
+    quota: Quota,
+    algorithm: Algorithm,
+    key: Key,
+    eviction_interval: Duration,
+    store: Arc<Store>,
+}
+
+impl RateLimit {
+    /// create new `RateLimit` using token bucket algorithm keyed by client ip
+    pub fn new(quota: Quota) -> Self {
+        Self {
+            quota,
+            algorithm: Algorithm::TokenBucket,
+            key: Key::Ip,
+            eviction_interval: Duration::from_secs(60),
+            store: Arc::new(Store::new()),
+        }
+    }
+
+    /// use token bucket algorithm
+    pub fn token_bucket(mut self) -> Self {
+        self.algorithm = Algorithm::TokenBucket;
+        self
+    }
+
+    /// use sliding window algorithm
+    pub fn sliding_window(mut self) -> Self {
+        self.algorithm = Algorithm::SlidingWindow;
+        self
+    }
+
+    /// key used to group requests, default to [`Key::Ip`]
+    pub fn key(mut self, key: Key) -> Self {
+        self.key = key;
+        self
+    }
+
+    /// how often idle keys are evicted, default to 60 seconds
+    pub fn eviction_interval(mut self, interval: Duration) -> Self {
+        self.eviction_interval = interval;
+        self
+    }
+
+    fn limit_value(&self) -> HeaderValue {
+        match self.algorithm {
+            Algorithm::TokenBucket => self.quota.burst.into(),
+            Algorithm::SlidingWindow => self.quota.limit.into(),
+        }
+    }
+}
+
+impl fmt::Debug for RateLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimit")
+            .field("quota", &self.quota)
+            .field("algorithm", &self.algorithm)
+            .field("key", &self.key)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S> Layer<S> for RateLimit {
+    type Service = RateLimitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimitService { inner, limit: self.clone() }
+    }
+}
+
+const RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+const RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+const RATELIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+
+/// round duration up to whole seconds
+fn secs(duration: Duration) -> u64 {
+    duration.as_secs() + u64::from(duration.subsec_nanos() != 0)
+}
+
+/// service that limit request rate
+#[derive(Clone)]
+pub struct RateLimitService<S> {
+    inner: S,
+    limit: RateLimit,
+}
+
+impl<S> Service<Request> for RateLimitService<S>
+where
+    S: Service<Request, Response = Response>,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = RateLimitFuture<S::Future>;
+
+    fn call(&self, req: Request) -> Self::Future {
+        let Some(key) = self.limit.key.get(&req) else {
+            return RateLimitFuture::Inner { f: self.inner.call(req), headers: None };
+        };
+
+        let limit = &self.limit;
+        let now = Instant::now();
+        limit.store.evict(&limit.quota, limit.eviction_interval, now);
+
+        let mut headers = HeaderMap::with_capacity(4);
+        headers.insert(RATELIMIT_LIMIT, limit.limit_value());
+
+        match limit.store.check(key, limit.algorithm, &limit.quota, now) {
+            Decision::Allow { remaining, reset } => {
+                headers.insert(RATELIMIT_REMAINING, remaining.into());
+                headers.insert(RATELIMIT_RESET, secs(reset).into());
+                RateLimitFuture::Inner { f: self.inner.call(req), headers: Some(headers) }
+            }
+            Decision::Deny { retry_after } => {
+                let retry_after = secs(retry_after);
+                headers.insert(RATELIMIT_REMAINING, 0.into());
+                headers.insert(RATELIMIT_RESET, retry_after.into());
+                headers.insert(http::header::RETRY_AFTER, retry_after.into());
+                let res = (StatusCode::TOO_MANY_REQUESTS, headers, "too many requests").into_response();
+                RateLimitFuture::Limited { res: Some(res) }
+            }
+        }
+    }
+}
+
+pin_project_lite::pin_project! {
+    /// future returned from [`RateLimitService`]
+    #[project = RateLimitProj]
+    pub enum RateLimitFuture<F> {
+        Inner { #[pin] f: F, headers: Option<HeaderMap> },
+        Limited { res: Option<Response> },
+    }
+}
+
+impl<F,E> Future for RateLimitFuture<F>
+where
+    F: Future<Output = Result<Response,E>>,
+{
+    type Output = Result<Response,E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project() {
+            RateLimitProj::Inner { f, headers } => {
+                let mut res = ready!(f.poll(cx));
+                if let (Ok(res), Some(headers)) = (&mut res, headers.take()) {
+                    res.headers_mut().extend(headers);
+                }
+                Poll::Ready(res)
+            }
+            RateLimitProj::Limited { res } => Poll::Ready(Ok(res.take().expect("poll after complete"))),
+        }
+    }
+}
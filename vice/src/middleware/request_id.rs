@@ -0,0 +1,285 @@
+//! request id generation and propagation
+//!
+//! [`RequestIdLayer`] read incoming request id header, or generate new one when
+//! absent, then store it in request extensions and echo it on the response
+//!
+//! the request id is also included in [`AccessLog`] json output, and recorded
+//! in the request span when `tracing` feature is enabled
+//!
+//! # Example
+//!
+//! ```no_run
+//! use vice::{
+//!     middleware::request_id::{RequestId, RequestIdLayer},
+//!     router::{Router, get},
+//! };
+//!
+//! async fn handle(id: RequestId) -> String {
+//!     format!("request {id}")
+//! }
+//!
+//! fn main() -> std::io::Result<()> {
+//!     let route = Router::new()
+//!         .route("/", get(handle))
+//!         .layer(RequestIdLayer::new());
+//!     vice::listen("0.0.0.0:3000", route)
+//! }
+//! ```
+//!
+//! [`AccessLog`]: super::access_log::AccessLog
+use super::Layer;
+use crate::{
+    http::{FromRequestParts, IntoResponse, Request, Response},
+    util::random,
+};
+use http::{request, HeaderName, HeaderValue, StatusCode};
+use hyper::service::Service;
+use std::{
+    fmt,
+    future::{ready, Ready},
+    pin::Pin,
+    sync::Arc,
+    task::{ready, Context, Poll},
+};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::convert::Infallible;
+
+    fn call(layer: &RequestIdLayer, id: Option<&str>) -> (RequestId, Response) {
+        let service = layer.clone().layer(hyper::service::service_fn(|req: Request| async move {
+            let id = req.extensions().get::<RequestId>().unwrap().clone();
+            assert_eq!(req.headers()[X_REQUEST_ID], id.as_str());
+            Ok::<Response,Infallible>(id.to_string().into_response())
+        }));
+        let mut req = Request::new(Default::default());
+        if let Some(id) = id {
+            req.headers_mut().insert(X_REQUEST_ID, id.parse().unwrap());
+        }
+
+        let mut cx = Context::from_waker(std::task::Waker::noop());
+        let Poll::Ready(Ok(res)) = std::pin::pin!(service.call(req)).poll(&mut cx) else {
+            unreachable!()
+        };
+        let id = res.extensions().get::<RequestId>().unwrap().clone();
+        assert_eq!(res.headers()[X_REQUEST_ID], id.as_str());
+        assert_eq!(res.body().as_bytes(), Some(id.as_str().as_bytes()));
+        (id, res)
+    }
+
+    #[test]
+    fn request_id() {
+        let layer = RequestIdLayer::new();
+        assert_eq!(call(&layer, Some("abc-123")).0.as_str(), "abc-123");
+        assert_eq!(call(&layer, None).0.as_str().len(), 36);
+
+        let too_long = "a".repeat(MAX_LEN + 1);
+        assert_ne!(call(&layer, Some(&too_long)).0.as_str(), too_long);
+        assert_ne!(call(&layer, Some("<script>")).0.as_str(), "<script>");
+        assert_ne!(call(&layer, Some("a b")).0.as_str(), "a b");
+
+        let layer = RequestIdLayer::new().trust_incoming(false).format(IdFormat::Ulid);
+        let (id, _) = call(&layer, Some("abc-123"));
+        assert_eq!(id.as_str().len(), 26);
+    }
+}
+
+/// default request id header
+pub const X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// maximum length of accepted incoming request id
+const MAX_LEN: usize = 128;
+
+/// the request id
+///
+/// when used as extractor, [`RequestIdLayer`] must be applied
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestId(Arc<str>);
+
+impl RequestId {
+    /// create new `RequestId`
+    pub fn new(id: impl Into<Arc<str>>) -> Self {
+        Self(id.into())
+    }
+
+    /// returns the id as string
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromRequestParts for RequestId {
+    type Error = MissingRequestId;
+    type Future = Ready<Result<Self,Self::Error>>;
+
+    fn from_request_parts(parts: &mut request::Parts) -> Self::Future {
+        ready(parts.extensions.get::<RequestId>().cloned().ok_or(MissingRequestId))
+    }
+}
+
+/// error returned when [`RequestIdLayer`] is not applied
+#[derive(thiserror::Error, Debug)]
+#[error("request id is not available")]
+pub struct MissingRequestId;
+
+impl IntoResponse for MissingRequestId {
+    fn into_response(self) -> Response {
+        (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()).into_response()
+    }
+}
+
+/// format of generated request id
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IdFormat {
+    /// random version 4 uuid
+    #[default]
+    Uuid,
+    /// [ulid](https://github.com/ulid/spec), sortable by creation time
+    Ulid,
+}
+
+/// request id layer
+///
+/// see [module level documentation](self) for more details
+#[derive(Clone, Debug)]
+pub struct RequestIdLayer {
+    header: HeaderName,
+    format: IdFormat,
+    trust_incoming: bool,
+}
+
+impl RequestIdLayer {
+    /// create new `RequestIdLayer` using `X-Request-Id` header and uuid format
+    pub fn new() -> Self {
+        Self { header: X_REQUEST_ID, format: IdFormat::Uuid, trust_incoming: true }
+    }
+
+    /// header used to read and echo request id
+    pub fn header(mut self, header: HeaderName) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// format of generated request id
+    pub fn format(mut self, format: IdFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// whether to use request id sent by the client, default to `true`
+    pub fn trust_incoming(mut self, trust: bool) -> Self {
+        self.trust_incoming = trust;
+        self
+    }
+
+    fn resolve<B>(&self, req: &Request<B>) -> RequestId {
+        let incoming = self
+            .trust_incoming
+            .then(||req.headers().get(&self.header))
+            .flatten()
+            .and_then(|e|e.to_str().ok())
+            .filter(|e|is_valid_id(e));
+
+        match incoming {
+            Some(id) => RequestId::new(id),
+            None => match self.format {
+                IdFormat::Uuid => RequestId::new(random::uuid()),
+                IdFormat::Ulid => RequestId::new(random::ulid()),
+            },
+        }
+    }
+}
+
+/// incoming id must be non empty, at most [`MAX_LEN`] long, and only contains
+/// alphanumeric or `-_.:+/=@`, so it is safe to be logged and echoed
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_LEN
+        && id.bytes().all(|e|e.is_ascii_alphanumeric() || b"-_.:+/=@".contains(&e))
+}
+
+impl Default for RequestIdLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Layer<S> for RequestIdLayer {
+    type Service = RequestIdService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestIdService { inner, layer: self.clone() }
+    }
+}
+
+/// service that assign request id
+#[derive(Clone)]
+pub struct RequestIdService<S> {
+    inner: S,
+    layer: RequestIdLayer,
+}
+
+impl<S,B> Service<Request<B>> for RequestIdService<S>
+where
+    S: Service<Request<B>, Response = Response>,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = RequestIdFuture<S::Future>;
+
+    fn call(&self, mut req: Request<B>) -> Self::Future {
+        let id = self.layer.resolve(&req);
+
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("request_id", id.as_str());
+
+        if let Ok(value) = HeaderValue::from_str(id.as_str()) {
+            req.headers_mut().insert(&self.layer.header, value);
+        }
+        req.extensions_mut().insert(id.clone());
+
+        RequestIdFuture {
+            inner: self.inner.call(req),
+            id: Some(id),
+            header: self.layer.header.clone(),
+        }
+    }
+}
+
+pin_project_lite::pin_project! {
+    /// future returned from [`RequestIdService`]
+    pub struct RequestIdFuture<F> {
+        #[pin]
+        inner: F,
+        id: Option<RequestId>,
+        header: HeaderName,
+    }
+}
+
+impl<F,E> Future for RequestIdFuture<F>
+where
+    F: Future<Output = Result<Response,E>>,
+{
+    type Output = Result<Response,E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let me = self.project();
+        let mut res = ready!(me.inner.poll(cx));
+        if let (Ok(res), Some(id)) = (&mut res, me.id.take()) {
+            if !res.headers().contains_key(&*me.header)
+                && let Ok(value) = HeaderValue::from_str(id.as_str())
+            {
+                res.headers_mut().insert(me.header.clone(), value);
+            }
+            res.extensions_mut().insert(id);
+        }
+        Poll::Ready(res)
+    }
+}
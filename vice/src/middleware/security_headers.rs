@@ -0,0 +1,298 @@
+//! security response headers
+//!
+//! [`SecurityHeaders`] set common security headers on every response, header
+//! already set by the handler is never overridden
+//!
+//! the default headers are:
+//!
+//! - `Strict-Transport-Security: max-age=31536000; includeSubDomains`
+//! - `X-Content-Type-Options: nosniff`
+//! - `X-Frame-Options: DENY`
+//! - `Referrer-Policy: strict-origin-when-cross-origin`
+//! - `Cross-Origin-Opener-Policy: same-origin`
+//! - `Cross-Origin-Resource-Policy: same-origin`
+//!
+//! every `{nonce}` in `Content-Security-Policy` is replaced with random nonce
+//! generated per request, which is available to handler as [`CspNonce`]
+//!
+//! # Example
+//!
+//! ```no_run
+//! use vice::{
+//!     middleware::security_headers::{CspNonce, SecurityHeaders},
+//!     router::{Router, get},
+//! };
+//!
+//! async fn page(nonce: CspNonce) -> String {
+//!     format!(r#"<script nonce="{nonce}">console.log("Vice Dev")</script>"#)
+//! }
+//!
+//! fn main() -> std::io::Result<()> {
+//!     let headers = SecurityHeaders::new()
+//!         .content_security_policy("default-src 'self'; script-src 'nonce-{nonce}'")
+//!         .permissions_policy("camera=(), microphone=()");
+//!     let route = Router::new()
+//!         .route("/", get(page))
+//!         .layer(headers);
+//!     vice::listen("0.0.0.0:3000", route)
+//! }
+//! ```
+use super::Layer;
+use crate::{
+    http::{FromRequestParts, IntoResponse, Request, Response},
+    util::{base64, random},
+};
+use http::{header, request, HeaderName, HeaderValue, StatusCode};
+use hyper::service::Service;
+use std::{
+    fmt,
+    future::{ready, Ready},
+    pin::Pin,
+    sync::Arc,
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::convert::Infallible;
+
+    #[test]
+    fn headers() {
+        let service = SecurityHeaders::new()
+            .content_security_policy("script-src 'nonce-{nonce}'")
+            .frame_options("SAMEORIGIN")
+            .remove(header::STRICT_TRANSPORT_SECURITY)
+            .layer(hyper::service::service_fn(|req: Request| async move {
+                let nonce = req.extensions().get::<CspNonce>().unwrap().clone();
+                let res = (("Referrer-Policy", "no-referrer"), nonce.to_string()).into_response();
+                Ok::<Response,Infallible>(res)
+            }));
+
+        let mut cx = Context::from_waker(std::task::Waker::noop());
+        let mut f = std::pin::pin!(service.call(Request::new(Default::default())));
+        let Poll::Ready(Ok(res)) = f.as_mut().poll(&mut cx) else {
+            unreachable!()
+        };
+
+        let nonce = std::str::from_utf8(res.body().as_bytes().unwrap()).unwrap();
+        let headers = res.headers();
+        assert_eq!(headers[header::CONTENT_SECURITY_POLICY], format!("script-src 'nonce-{nonce}'"));
+        assert_eq!(headers[header::X_FRAME_OPTIONS], "SAMEORIGIN");
+        assert_eq!(headers[header::REFERRER_POLICY], "no-referrer");
+        assert_eq!(headers[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+        assert!(!headers.contains_key(header::STRICT_TRANSPORT_SECURITY));
+    }
+}
+
+const NONCE_PLACEHOLDER: &str = "{nonce}";
+
+/// per request `Content-Security-Policy` nonce
+///
+/// when used as extractor, [`SecurityHeaders`] must be applied with
+/// `Content-Security-Policy` containing `{nonce}`
+#[derive(Clone, Debug)]
+pub struct CspNonce(Arc<str>);
+
+impl CspNonce {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for CspNonce {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromRequestParts for CspNonce {
+    type Error = MissingCspNonce;
+    type Future = Ready<Result<Self,Self::Error>>;
+
+    fn from_request_parts(parts: &mut request::Parts) -> Self::Future {
+        ready(parts.extensions.get::<CspNonce>().cloned().ok_or(MissingCspNonce))
+    }
+}
+
+/// error returned when csp nonce is not generated
+#[derive(thiserror::Error, Debug)]
+#[error("csp nonce is not available")]
+pub struct MissingCspNonce;
+
+impl IntoResponse for MissingCspNonce {
+    fn into_response(self) -> Response {
+        (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()).into_response()
+    }
+}
+
+/// security headers layer
+///
+/// see [module level documentation](self) for more details
+///
+/// # Panics
+///
+/// header setters panics if the value is not a valid header value
+#[derive(Clone, Debug)]
+pub struct SecurityHeaders {
+    headers: Vec<(HeaderName, HeaderValue)>,
+    csp: Option<Arc<str>>,
+}
+
+impl SecurityHeaders {
+    /// create new `SecurityHeaders` with default headers
+    pub fn new() -> Self {
+        Self { headers: vec![], csp: None }
+            .hsts(Duration::from_secs(60 * 60 * 24 * 365), true)
+            .header(header::X_CONTENT_TYPE_OPTIONS, "nosniff")
+            .frame_options("DENY")
+            .referrer_policy("strict-origin-when-cross-origin")
+            .cross_origin_opener_policy("same-origin")
+            .cross_origin_resource_policy("same-origin")
+    }
+
+    /// set header, replacing previously configured value
+    pub fn header(mut self, name: HeaderName, value: &str) -> Self {
+        let value = HeaderValue::from_str(value).expect("invalid header value");
+        match self.headers.iter_mut().find(|(e,_)|e == name) {
+            Some((_, old)) => *old = value,
+            None => self.headers.push((name, value)),
+        }
+        self
+    }
+
+    /// do not send given header, including `Content-Security-Policy`
+    pub fn remove(mut self, name: HeaderName) -> Self {
+        if name == header::CONTENT_SECURITY_POLICY {
+            self.csp = None;
+        }
+        self.headers.retain(|(e,_)|e != name);
+        self
+    }
+
+    /// `Strict-Transport-Security`
+    pub fn hsts(self, max_age: Duration, include_subdomains: bool) -> Self {
+        let value = match include_subdomains {
+            true => format!("max-age={}; includeSubDomains", max_age.as_secs()),
+            false => format!("max-age={}", max_age.as_secs()),
+        };
+        self.header(header::STRICT_TRANSPORT_SECURITY, &value)
+    }
+
+    /// `Content-Security-Policy`, every `{nonce}` is replaced with per request nonce
+    pub fn content_security_policy(mut self, policy: impl Into<Arc<str>>) -> Self {
+        let policy = policy.into();
+        HeaderValue::from_str(&policy).expect("invalid header value");
+        self.csp = Some(policy);
+        self
+    }
+
+    /// `X-Frame-Options`
+    pub fn frame_options(self, value: &str) -> Self {
+        self.header(header::X_FRAME_OPTIONS, value)
+    }
+
+    /// `Referrer-Policy`
+    pub fn referrer_policy(self, value: &str) -> Self {
+        self.header(header::REFERRER_POLICY, value)
+    }
+
+    /// `Permissions-Policy`
+    pub fn permissions_policy(self, value: &str) -> Self {
+        self.header(HeaderName::from_static("permissions-policy"), value)
+    }
+
+    /// `Cross-Origin-Opener-Policy`
+    pub fn cross_origin_opener_policy(self, value: &str) -> Self {
+        self.header(HeaderName::from_static("cross-origin-opener-policy"), value)
+    }
+
+    /// `Cross-Origin-Embedder-Policy`
+    pub fn cross_origin_embedder_policy(self, value: &str) -> Self {
+        self.header(HeaderName::from_static("cross-origin-embedder-policy"), value)
+    }
+
+    /// `Cross-Origin-Resource-Policy`
+    pub fn cross_origin_resource_policy(self, value: &str) -> Self {
+        self.header(HeaderName::from_static("cross-origin-resource-policy"), value)
+    }
+}
+
+impl Default for SecurityHeaders {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Layer<S> for SecurityHeaders {
+    type Service = SecurityHeadersService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SecurityHeadersService {
+            inner,
+            headers: self.headers.clone().into(),
+            csp: self.csp.clone(),
+        }
+    }
+}
+
+/// service that set security headers
+#[derive(Clone)]
+pub struct SecurityHeadersService<S> {
+    inner: S,
+    headers: Arc<[(HeaderName, HeaderValue)]>,
+    csp: Option<Arc<str>>,
+}
+
+impl<S,B> Service<Request<B>> for SecurityHeadersService<S>
+where
+    S: Service<Request<B>, Response = Response>,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = SecurityHeadersFuture<S::Future>;
+
+    fn call(&self, mut req: Request<B>) -> Self::Future {
+        let csp = self.csp.as_deref().and_then(|policy| {
+            if !policy.contains(NONCE_PLACEHOLDER) {
+                return HeaderValue::from_str(policy).ok();
+            }
+            let nonce = base64::encode(&random::bytes::<16>());
+            let value = HeaderValue::from_str(&policy.replace(NONCE_PLACEHOLDER, &nonce)).ok();
+            req.extensions_mut().insert(CspNonce(nonce.into()));
+            value
+        });
+        SecurityHeadersFuture { inner: self.inner.call(req), headers: self.headers.clone(), csp }
+    }
+}
+
+pin_project_lite::pin_project! {
+    /// future returned from [`SecurityHeadersService`]
+    pub struct SecurityHeadersFuture<F> {
+        #[pin]
+        inner: F,
+        headers: Arc<[(HeaderName, HeaderValue)]>,
+        csp: Option<HeaderValue>,
+    }
+}
+
+impl<F,E> Future for SecurityHeadersFuture<F>
+where
+    F: Future<Output = Result<Response,E>>,
+{
+    type Output = Result<Response,E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let me = self.project();
+        let mut res = ready!(me.inner.poll(cx))?;
+        let headers = res.headers_mut();
+        for (name, value) in me.headers.iter() {
+            headers.entry(name).or_insert_with(||value.clone());
+        }
+        if let Some(csp) = me.csp.take() {
+            headers.entry(header::CONTENT_SECURITY_POLICY).or_insert(csp);
+        }
+        Poll::Ready(Ok(res))
+    }
+}
@@ -0,0 +1,422 @@
+//! server side session
+//!
+//! [`SessionLayer`] load the session referenced by the session id cookie
+//! from a [`SessionStore`], and make it available to handler as [`Session`]
+//! extractor
+//!
+//! after the handler runs, the session is persisted only if it was modified,
+//! new session id is issued for new session or when [`Session::rotate_id`]
+//! is called, which should be done on login to prevent session fixation
+//!
+//! # Example
+//!
+//! ```no_run
+//! use vice::{
+//!     middleware::session::{MemoryStore, Session, SessionLayer},
+//!     router::{Router, get},
+//! };
+//!
+//! async fn login(session: Session) -> &'static str {
+//!     session.rotate_id();
+//!     session.insert("user", "bob").unwrap();
+//!     "logged in"
+//! }
+//!
+//! async fn whoami(session: Session) -> String {
+//!     session.get::<String>("user").unwrap_or_default()
+//! }
+//!
+//! fn main() -> std::io::Result<()> {
+//!     let route = Router::new()
+//!         .route("/login", get(login))
+//!         .route("/whoami", get(whoami))
+//!         .layer(SessionLayer::new(MemoryStore::new()));
+//!     vice::listen("0.0.0.0:3000", route)
+//! }
+//! ```
+use super::Layer;
+use crate::{
+    http::{
+        cookie::{Cookie, CookieJar, SameSite},
+        FromRequestParts, IntoResponse, IntoResponseParts, Request, Response,
+    },
+    util::{base64, random},
+};
+use http::{request, StatusCode};
+use hyper::service::Service;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{Map, Value};
+use std::{
+    future::{ready, Ready},
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+pub mod store;
+
+#[doc(inline)]
+pub use store::{FileStore, MemoryStore, Record, SessionStore};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use http::header;
+    use std::{convert::Infallible, task::{Context, Poll}};
+
+    #[test]
+    fn session() {
+        let store = MemoryStore::new();
+        let service = SessionLayer::new(store.clone()).layer(hyper::service::service_fn(|req: Request| async move {
+            let session = req.extensions().get::<Session>().unwrap().clone();
+            match req.uri().path() {
+                "/login" => {
+                    session.rotate_id();
+                    session.insert("user", "bob").unwrap();
+                }
+                "/logout" => session.destroy(),
+                _ => {}
+            }
+            Ok::<Response,Infallible>(session.get::<String>("user").unwrap_or_default().into_response())
+        }));
+
+        let mut cx = Context::from_waker(std::task::Waker::noop());
+        let mut call = |uri: &'static str, cookie: Option<&str>| {
+            let mut req = Request::new(Default::default());
+            *req.uri_mut() = uri.parse().unwrap();
+            if let Some(cookie) = cookie {
+                req.headers_mut().insert(header::COOKIE, cookie.parse().unwrap());
+            }
+            match service.call(req).as_mut().poll(&mut cx) {
+                Poll::Ready(Ok(res)) => res,
+                _ => unreachable!(),
+            }
+        };
+        let set_cookie = |res: &Response| res
+            .headers()
+            .get(header::SET_COOKIE)
+            .map(|e|e.to_str().unwrap().split(';').next().unwrap().to_owned());
+
+        let res = call("/", None);
+        assert!(set_cookie(&res).is_none());
+        assert!(store.is_empty());
+
+        let first = set_cookie(&call("/login", None)).unwrap();
+        let res = call("/", Some(&first));
+        assert!(set_cookie(&res).is_none());
+        assert_eq!(res.body().as_bytes(), Some(&b"bob"[..]));
+
+        let second = set_cookie(&call("/login", Some(&first))).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(store.len(), 1);
+        assert_eq!(call("/", Some(&first)).body().as_bytes(), Some(&b""[..]));
+
+        assert_eq!(set_cookie(&call("/logout", Some(&second))).unwrap(), "id=");
+        assert!(store.is_empty());
+    }
+
+    #[test]
+    fn load_error() {
+        let dir = std::env::temp_dir().join(format!("vice-session-error-{}", std::process::id()));
+        let service = SessionLayer::new(FileStore::new(&dir).unwrap()).layer(hyper::service::service_fn(|req: Request| async move {
+            let session = req.extensions().get::<Session>().unwrap().clone();
+            session.insert("visit", 1).unwrap();
+            Ok::<Response,Infallible>(Response::default())
+        }));
+        let id = new_id();
+        std::fs::write(dir.join(format!("{id}.json")), "not json").unwrap();
+
+        let mut req = Request::new(Default::default());
+        req.headers_mut().insert(header::COOKIE, format!("id={id}").parse().unwrap());
+        let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let res = rt.block_on(service.call(req)).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+        let cookie = res.headers()[header::SET_COOKIE].to_str().unwrap();
+        assert!(cookie.starts_with("id=") && !cookie.contains(&id));
+    }
+}
+
+/// length of base64 encoded 32 bytes session id
+const ID_LEN: usize = 43;
+
+fn new_id() -> String {
+    base64::encode_url(&random::bytes::<32>())
+}
+
+fn is_valid_id(id: &str) -> bool {
+    id.len() == ID_LEN && id.bytes().all(|e|e.is_ascii_alphanumeric() || e == b'-' || e == b'_')
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    /// id of loaded session
+    id: Option<String>,
+    data: Map<String, Value>,
+    modified: bool,
+    rotate: bool,
+    destroyed: bool,
+}
+
+/// session data
+///
+/// this is a shared handle, changes is visible to [`SessionLayer`] after the
+/// handler returns
+///
+/// when used as extractor, [`SessionLayer`] must be applied
+#[derive(Clone, Debug, Default)]
+pub struct Session {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl Session {
+    /// get and deserialize value
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let inner = self.inner.lock().unwrap();
+        T::deserialize(inner.data.get(key)?).ok()
+    }
+
+    /// serialize and insert value, replacing existing value
+    pub fn insert<T: Serialize>(&self, key: impl Into<String>, value: T) -> Result<(), serde_json::Error> {
+        let value = serde_json::to_value(value)?;
+        let mut inner = self.inner.lock().unwrap();
+        inner.data.insert(key.into(), value);
+        inner.modified = true;
+        Ok(())
+    }
+
+    /// remove and deserialize value
+    pub fn remove<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let mut inner = self.inner.lock().unwrap();
+        let value = inner.data.remove(key)?;
+        inner.modified = true;
+        T::deserialize(value).ok()
+    }
+
+    /// remove all values
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.data.clear();
+        inner.modified = true;
+    }
+
+    /// issue new session id while keeping the data
+    pub fn rotate_id(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.rotate = true;
+        inner.modified = true;
+    }
+
+    /// delete the session from the store and remove session cookie
+    pub fn destroy(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.data.clear();
+        inner.destroyed = true;
+    }
+
+    /// id of the loaded session, `None` for new session
+    pub fn id(&self) -> Option<String> {
+        self.inner.lock().unwrap().id.clone()
+    }
+}
+
+impl FromRequestParts for Session {
+    type Error = MissingSession;
+    type Future = Ready<Result<Self,Self::Error>>;
+
+    fn from_request_parts(parts: &mut request::Parts) -> Self::Future {
+        ready(parts.extensions.get::<Session>().cloned().ok_or(MissingSession))
+    }
+}
+
+/// error returned when [`SessionLayer`] is not applied
+#[derive(thiserror::Error, Debug)]
+#[error("session is not available")]
+pub struct MissingSession;
+
+impl IntoResponse for MissingSession {
+    fn into_response(self) -> Response {
+        (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()).into_response()
+    }
+}
+
+/// session layer
+///
+/// see [module level documentation](self) for more details
+#[derive(Debug)]
+pub struct SessionLayer<St> {
+    store: Arc<St>,
+    config: Config,
+}
+
+#[derive(Clone, Debug)]
+struct Config {
+    cookie: Cookie,
+    max_age: Duration,
+}
+
+impl<St> Clone for SessionLayer<St> {
+    fn clone(&self) -> Self {
+        Self { store: self.store.clone(), config: self.config.clone() }
+    }
+}
+
+impl<St> SessionLayer<St> {
+    /// create new `SessionLayer`
+    ///
+    /// default cookie is `id` with `Path=/`, `HttpOnly`, `Secure` and
+    /// `SameSite=Lax`, default max age is 1 day
+    pub fn new(store: St) -> Self {
+        let cookie = Cookie::new("id", "")
+            .path("/")
+            .http_only(true)
+            .secure(true)
+            .same_site(SameSite::Lax);
+        let config = Config { cookie, max_age: Duration::from_secs(60 * 60 * 24) };
+        Self { store: Arc::new(store), config }
+    }
+
+    /// session cookie name and attributes, the value is ignored
+    pub fn cookie(mut self, cookie: Cookie) -> Self {
+        self.config.cookie = cookie;
+        self
+    }
+
+    /// session lifetime since it was last modified
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.config.max_age = max_age;
+        self
+    }
+}
+
+impl<St, S> Layer<S> for SessionLayer<St> {
+    type Service = SessionService<St, S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SessionService {
+            inner: Arc::new(inner),
+            store: self.store.clone(),
+            config: Arc::new(self.config.clone()),
+        }
+    }
+}
+
+/// service that load and persist session
+pub struct SessionService<St, S> {
+    inner: Arc<S>,
+    store: Arc<St>,
+    config: Arc<Config>,
+}
+
+impl<St, S> Clone for SessionService<St, S> {
+    fn clone(&self) -> Self {
+        Self { inner: self.inner.clone(), store: self.store.clone(), config: self.config.clone() }
+    }
+}
+
+/// future returned from [`SessionService`]
+pub type SessionFuture<E> = Pin<Box<dyn Future<Output = Result<Response,E>> + Send + 'static>>;
+
+impl<St, S> Service<Request> for SessionService<St, S>
+where
+    St: SessionStore,
+    S: Service<Request, Response = Response> + Send + Sync + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = SessionFuture<S::Error>;
+
+    fn call(&self, mut req: Request) -> Self::Future {
+        let inner = self.inner.clone();
+        let store = self.store.clone();
+        let config = self.config.clone();
+        Box::pin(async move {
+            let id = CookieJar::from_headers(req.headers())
+                .get(config.cookie.name())
+                .map(|e|e.value().to_owned())
+                .filter(|e|is_valid_id(e));
+
+            let session = Session::default();
+            if let Some(id) = id {
+                match store.load(&id).await {
+                    Ok(Some(record)) if !record.is_expired() => {
+                        let mut session = session.inner.lock().unwrap();
+                        session.id = Some(id);
+                        session.data = record.data;
+                    }
+                    Ok(Some(_)) => {
+                        if let Err(err) = store.delete(&id).await {
+                            log::error!("failed to delete expired session: {err}");
+                        }
+                    }
+                    Ok(None) => {}
+                    // continue with a new session, so a broken record does not lock the client out
+                    Err(err) => log::error!("failed to load session: {err}"),
+                }
+            }
+
+            req.extensions_mut().insert(session.clone());
+            let res = inner.call(req).await?;
+
+            let state = std::mem::take(&mut *session.inner.lock().unwrap());
+            let result = persist(&*store, &config, state).await;
+
+            match result {
+                Ok(Some(cookie)) => {
+                    let (parts, body) = res.into_parts();
+                    Ok(Response::from_parts(cookie.into_response_parts(parts), body))
+                }
+                Ok(None) => Ok(res),
+                Err(err) => {
+                    log::error!("failed to save session: {err}");
+                    Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+                }
+            }
+        })
+    }
+}
+
+/// persist the session after handler returns, returns cookie to be sent if any
+async fn persist<St: SessionStore>(store: &St, config: &Config, state: Inner) -> io::Result<Option<Cookie>> {
+    let Inner { id, data, modified, rotate, destroyed } = state;
+    if destroyed {
+        let Some(id) = id else {
+            return Ok(None);
+        };
+        store.delete(&id).await?;
+        return Ok(Some(removal(&config.cookie)));
+    }
+    if !modified {
+        return Ok(None);
+    }
+
+    let id = match id {
+        Some(old) if rotate => {
+            store.delete(&old).await?;
+            new_id()
+        }
+        Some(id) => id,
+        None => new_id(),
+    };
+    let record = Record { data, expiry: Some(SystemTime::now() + config.max_age) };
+    store.save(&id, &record).await?;
+
+    let mut cookie = config.cookie.clone().max_age(config.max_age);
+    cookie.set_value(id);
+    Ok(Some(cookie))
+}
+
+/// removal cookie that keep `Path` and `Domain` of session cookie
+fn removal(cookie: &Cookie) -> Cookie {
+    let mut removal = Cookie::removal(cookie.name());
+    if let Some(path) = cookie.get_path() {
+        removal = removal.path(path);
+    }
+    if let Some(domain) = cookie.get_domain() {
+        removal = removal.domain(domain);
+    }
+    removal
+}
@@ -0,0 +1,306 @@
+//! session storage
+use serde_json::{Map, Value};
+use std::{
+    collections::HashMap,
+    future::Future,
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn file_store() {
+        let dir = std::env::temp_dir().join(format!("vice-session-{}", std::process::id()));
+        let store = FileStore::new(&dir).unwrap();
+        let id = "AbC-_123";
+        let mut record = Record::default();
+        record.data.insert("user".to_owned(), Value::from("bob"));
+        record.expiry = Some(UNIX_EPOCH + Duration::from_secs(4102444800));
+
+        let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        rt.block_on(async {
+            assert_eq!(store.load(id).await.unwrap(), None);
+            store.save(id, &record).await.unwrap();
+            assert_eq!(store.load(id).await.unwrap(), Some(record.clone()));
+            assert!(!record.is_expired());
+
+            // expiry is stored in whole seconds
+            let expired = Record { expiry: Some(UNIX_EPOCH + Duration::from_secs(60)), ..record.clone() };
+            store.save(id, &expired).await.unwrap();
+            assert!(store.load(id).await.unwrap().unwrap().is_expired());
+
+            store.delete(id).await.unwrap();
+            assert_eq!(store.load(id).await.unwrap(), None);
+            store.delete(id).await.unwrap();
+
+            assert!(store.load("../escape").await.is_err());
+        });
+        // no temporary file left behind
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+        std::fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn file_store_purge() {
+        let dir = std::env::temp_dir().join(format!("vice-session-purge-{}", std::process::id()));
+        let store = FileStore::new(&dir).unwrap();
+        let expired = Record { expiry: Some(UNIX_EPOCH + Duration::from_secs(60)), ..Record::default() };
+
+        // abandoned temporary file from a crashed write, and one being written
+        let stale = dir.join("stale.0000.tmp");
+        std::fs::File::create(&stale).unwrap().set_modified(SystemTime::now() - Duration::from_secs(3600)).unwrap();
+        let fresh = dir.join("fresh.0000.tmp");
+        std::fs::write(&fresh, b"").unwrap();
+
+        let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        rt.block_on(async {
+            store.save("old", &expired).await.unwrap();
+            store.save("keep", &Record::default()).await.unwrap();
+            // not purged until the interval elapsed
+            assert!(dir.join("old.json").exists());
+            assert!(!stale.exists());
+            assert!(fresh.exists());
+
+            // purged on the first save after restart
+            let store = FileStore::new(&dir).unwrap();
+            store.save("new", &Record::default()).await.unwrap();
+            assert!(!dir.join("old.json").exists());
+            assert_eq!(store.load("keep").await.unwrap(), Some(Record::default()));
+        });
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
+
+/// persisted session data
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Record {
+    /// session values, as inserted through [`Session`]
+    ///
+    /// [`Session`]: super::Session
+    pub data: Map<String, Value>,
+    /// time after which the record must no longer be used, `None` never expire
+    pub expiry: Option<SystemTime>,
+}
+
+impl Record {
+    /// returns `true` if `expiry` is reached
+    pub fn is_expired(&self) -> bool {
+        self.expiry.is_some_and(|e|e <= SystemTime::now())
+    }
+}
+
+/// session storage backend
+///
+/// session id passed to the store is always url safe base64 generated by
+/// [`SessionLayer`]
+///
+/// [`SessionLayer`]: super::SessionLayer
+pub trait SessionStore: Send + Sync + 'static {
+    /// load session record, returns `None` if not found
+    fn load(&self, id: &str) -> impl Future<Output = io::Result<Option<Record>>> + Send;
+
+    /// create or replace session record
+    fn save(&self, id: &str, record: &Record) -> impl Future<Output = io::Result<()>> + Send;
+
+    /// delete session record, deleting missing record is not an error
+    fn delete(&self, id: &str) -> impl Future<Output = io::Result<()>> + Send;
+}
+
+/// in memory session store
+///
+/// sessions is lost on restart, expired sessions is purged as the store grow
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStore {
+    inner: Arc<Mutex<MemoryInner>>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryInner {
+    records: HashMap<String, Record>,
+    /// purge expired records when the store reach this length
+    purge_at: usize,
+}
+
+impl MemoryStore {
+    /// create empty `MemoryStore`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// number of stored sessions, including expired one
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl SessionStore for MemoryStore {
+    async fn load(&self, id: &str) -> io::Result<Option<Record>> {
+        Ok(self.inner.lock().unwrap().records.get(id).cloned())
+    }
+
+    async fn save(&self, id: &str, record: &Record) -> io::Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.records.len() >= inner.purge_at {
+            inner.records.retain(|_,e|!e.is_expired());
+            inner.purge_at = (inner.records.len() * 2).max(1024);
+        }
+        inner.records.insert(id.to_owned(), record.clone());
+        Ok(())
+    }
+
+    async fn delete(&self, id: &str) -> io::Result<()> {
+        self.inner.lock().unwrap().records.remove(id);
+        Ok(())
+    }
+}
+
+/// file backed session store
+///
+/// each session is stored as json file in the given directory, expired
+/// session file is removed by [`SessionLayer`] when loaded
+///
+/// sessions that are never loaded again are purged by `save`, which sweeps
+/// the directory on the first call and then once every
+/// [`purge_interval`][FileStore::purge_interval]
+///
+/// [`SessionLayer`]: super::SessionLayer
+#[derive(Clone, Debug)]
+pub struct FileStore {
+    dir: Arc<PathBuf>,
+    purge_interval: Duration,
+    /// sweep the directory when this time is reached
+    purge_at: Arc<Mutex<SystemTime>>,
+}
+
+/// temporary file older than this is left by an interrupted `save`
+const STALE_TMP: Duration = Duration::from_secs(60);
+
+impl FileStore {
+    /// create `FileStore` in given directory, the directory is created if not exists
+    pub fn new(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir: Arc::new(dir),
+            purge_interval: Duration::from_secs(3600),
+            purge_at: Arc::new(Mutex::new(UNIX_EPOCH)),
+        })
+    }
+
+    /// interval between sweeps of expired session files, default to 1 hour
+    pub fn purge_interval(mut self, interval: Duration) -> Self {
+        self.purge_interval = interval;
+        self
+    }
+
+    fn path(&self, id: &str) -> io::Result<PathBuf> {
+        if id.is_empty() || !id.bytes().all(|e|e.is_ascii_alphanumeric() || e == b'-' || e == b'_') {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid session id"));
+        }
+        Ok(self.dir.join(format!("{id}.json")))
+    }
+
+    /// remove expired session files and abandoned temporary files
+    async fn purge(&self) -> io::Result<()> {
+        let now = SystemTime::now();
+        let mut dir = tokio::fs::read_dir(&*self.dir).await?;
+        while let Some(entry) = dir.next_entry().await? {
+            let path = entry.path();
+            let expired = match path.extension().and_then(|e|e.to_str()) {
+                Some("json") => match read_record(&path).await {
+                    Ok(record) => record.is_some_and(|e|e.is_expired()),
+                    Err(err) => {
+                        log::error!("failed to read session file {}: {err}", path.display());
+                        false
+                    }
+                },
+                Some("tmp") => entry
+                    .metadata()
+                    .await?
+                    .modified()
+                    .is_ok_and(|e|now.duration_since(e).unwrap_or_default() >= STALE_TMP),
+                _ => false,
+            };
+            if expired {
+                match tokio::fs::remove_file(&path).await {
+                    Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+async fn read_record(path: &Path) -> io::Result<Option<Record>> {
+    let data = match tokio::fs::read(path).await {
+        Ok(data) => data,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    let Value::Object(mut file) = serde_json::from_slice(&data)? else {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid session file"));
+    };
+    let data = match file.remove("data") {
+        Some(Value::Object(data)) => data,
+        _ => Map::new(),
+    };
+    let expiry = file
+        .get("expiry")
+        .and_then(Value::as_u64)
+        .map(|e|UNIX_EPOCH + Duration::from_secs(e));
+    Ok(Some(Record { data, expiry }))
+}
+
+impl SessionStore for FileStore {
+    async fn load(&self, id: &str) -> io::Result<Option<Record>> {
+        read_record(&self.path(id)?).await
+    }
+
+    async fn save(&self, id: &str, record: &Record) -> io::Result<()> {
+        let path = self.path(id)?;
+
+        let purge = {
+            let now = SystemTime::now();
+            let mut purge_at = self.purge_at.lock().unwrap();
+            let purge = *purge_at <= now;
+            if purge {
+                *purge_at = now + self.purge_interval;
+            }
+            purge
+        };
+        if purge && let Err(err) = self.purge().await {
+            log::error!("failed to purge session files: {err}");
+        }
+
+        let expiry = record
+            .expiry
+            .map(|e|e.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs());
+        let file = serde_json::json!({ "expiry": expiry, "data": record.data });
+
+        // write to temporary file first, so concurrent load never see partial file
+        let tmp = path.with_extension(format!("{}.tmp", crate::util::random::hex_token(4)));
+        tokio::fs::write(&tmp, serde_json::to_vec(&file)?).await?;
+        if let Err(err) = tokio::fs::rename(&tmp, &path).await {
+            let _ = tokio::fs::remove_file(&tmp).await;
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    async fn delete(&self, id: &str) -> io::Result<()> {
+        match tokio::fs::remove_file(self.path(id)?).await {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! request timeout
+//!
+//! [`Timeout`] drop the inner service future when it does not complete within
+//! the deadline, and respond with `408 Request Timeout` or the configured status
+//!
+//! apply it with [`Router::layer`] for every route, or [`Router::route_layer`]
+//! for a single route
+//!
+//! layers nest in declaration order, [`Router::layer`] only wraps the routes
+//! assigned before it, so a route that need a longer deadline than the rest
+//! must be assigned after the global timeout, otherwise the outer, shorter
+//! deadline fires first
+//!
+//! connection level timeouts are configured in [`Server`]
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use vice::{http::StatusCode, middleware::timeout::Timeout, router::{Router, get}};
+//!
+//! fn main() -> std::io::Result<()> {
+//!     let route = Router::new()
+//!         .route("/", get(||async { "Vice Dev" }))
+//!         // wraps "/" and the fallback
+//!         .layer(Timeout::new(Duration::from_secs(5)).status(StatusCode::SERVICE_UNAVAILABLE))
+//!         // matched before reaching the 5 seconds layer
+//!         .route("/report", get(||async { "slow report" }))
+//!         .route_layer(Timeout::new(Duration::from_secs(60)));
+//!     vice::listen("0.0.0.0:3000", route)
+//! }
+//! ```
+//!
+//! [`Router::layer`]: crate::router::Router::layer
+//! [`Router::route_layer`]: crate::router::Router::route_layer
+//! [`Server`]: crate::runtime::Server
+use super::Layer;
+use crate::http::{IntoResponse, Request, Response};
+use bytes::Bytes;
+use http::StatusCode;
+use hyper::service::Service;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time::{sleep, Sleep};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::Body;
+    use std::convert::Infallible;
+
+    #[test]
+    fn timeout() {
+        let service = Timeout::new(Duration::from_millis(20))
+            .status(StatusCode::SERVICE_UNAVAILABLE)
+            .body("too slow")
+            .layer(hyper::service::service_fn(|req: Request| async move {
+                if req.uri().path() == "/hang" {
+                    std::future::pending::<()>().await;
+                }
+                Ok::<Response,Infallible>(Response::default())
+            }));
+        let call = |path: &'static str| {
+            let mut req = Request::new(Default::default());
+            *req.uri_mut() = path.parse().unwrap();
+            service.call(req)
+        };
+
+        let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        rt.block_on(async {
+            let res = call("/").await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+
+            let res = call("/hang").await.unwrap();
+            assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+            assert_eq!(res.headers()["content-type"], "text/plain");
+            let body = http_body_util::BodyExt::collect(Body::new(res.into_body())).await.unwrap();
+            assert_eq!(body.to_bytes(), "too slow");
+        });
+    }
+}
+
+/// request timeout layer
+///
+/// see [module level documentation](self) for more details
+#[derive(Clone, Debug)]
+pub struct Timeout {
+    duration: Duration,
+    status: StatusCode,
+    body: Bytes,
+}
+
+impl Timeout {
+    /// create new `Timeout` with given deadline
+    pub fn new(duration: Duration) -> Self {
+        Self {
+            duration,
+            status: StatusCode::REQUEST_TIMEOUT,
+            body: Bytes::from_static(b"request timed out"),
+        }
+    }
+
+    /// status of timed out response, default to `408 Request Timeout`
+    pub fn status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// body of timed out response
+    pub fn body(mut self, body: impl Into<Bytes>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    fn response(&self) -> Response {
+        (self.status, ("Content-Type", "text/plain"), self.body.clone()).into_response()
+    }
+}
+
+impl<S> Layer<S> for Timeout {
+    type Service = TimeoutService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TimeoutService { inner, timeout: self.clone() }
+    }
+}
+
+/// service that limit the inner service duration
+#[derive(Clone)]
+pub struct TimeoutService<S> {
+    inner: S,
+    timeout: Timeout,
+}
+
+impl<S,B> Service<Request<B>> for TimeoutService<S>
+where
+    S: Service<Request<B>, Response = Response>,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = TimeoutFuture<S::Future>;
+
+    fn call(&self, req: Request<B>) -> Self::Future {
+        TimeoutFuture {
+            inner: self.inner.call(req),
+            sleep: sleep(self.timeout.duration),
+            timeout: self.timeout.clone(),
+        }
+    }
+}
+
+pin_project_lite::pin_project! {
+    /// future returned from [`TimeoutService`]
+    pub struct TimeoutFuture<F> {
+        #[pin]
+        inner: F,
+        #[pin]
+        sleep: Sleep,
+        timeout: Timeout,
+    }
+}
+
+impl<F,E> Future for TimeoutFuture<F>
+where
+    F: Future<Output = Result<Response,E>>,
+{
+    type Output = Result<Response,E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let me = self.project();
+        if let Poll::Ready(res) = me.inner.poll(cx) {
+            return Poll::Ready(res);
+        }
+        match me.sleep.poll(cx) {
+            Poll::Ready(()) => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(timeout = ?me.timeout.duration, "request timed out");
+                Poll::Ready(Ok(me.timeout.response()))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
@@ -0,0 +1,214 @@
+//! [`tracing`] integration
+//!
+//! [`Trace`] open a span for every request, the span is entered whenever the
+//! inner service future is polled, so events emitted by handler is recorded
+//! inside the request span
+//!
+//! the runtime also open a `connection` span for every connection, which
+//! become the parent of request spans
+//!
+//! request span have the following fields:
+//!
+//! - `method`, request method
+//! - `path`, request path
+//! - `route`, the [`MatchedPath`] if any
+//! - `request_id`, the [`RequestId`] if any
+//! - `status`, response status code
+//! - `latency_ms`, duration until response is returned
+//!
+//! # Example
+//!
+//! ```no_run
+//! use vice::{middleware::trace::Trace, router::{Router, get}};
+//!
+//! fn main() -> std::io::Result<()> {
+//!     let route = Router::new()
+//!         .route("/", get(||async { "Vice Dev" }))
+//!         .layer(Trace::new());
+//!     vice::listen("0.0.0.0:3000", route)
+//! }
+//! ```
+//!
+//! [`tracing`]: https://docs.rs/tracing
+//! [`RequestId`]: super::request_id::RequestId
+use super::{request_id::RequestId, Layer};
+use crate::{
+    http::{Request, Response},
+    router::MatchedPath,
+};
+use hyper::service::Service;
+use std::{
+    pin::Pin,
+    task::{ready, Context, Poll},
+    time::Instant,
+};
+use tracing::{field::Empty, instrument::Instrumented, Instrument, Span};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use http::StatusCode;
+    use std::{
+        sync::{atomic::{AtomicU64, Ordering}, Arc, Mutex},
+    };
+    use tracing::{
+        field::{Field, Visit},
+        span::{Attributes, Id, Record},
+        Event, Metadata,
+    };
+
+    /// subscriber that collect every span field as string
+    #[derive(Clone, Default)]
+    struct Collect {
+        fields: Arc<Mutex<Vec<(String, String)>>>,
+        next_id: Arc<AtomicU64>,
+    }
+
+    impl Visit for Collect {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.fields.lock().unwrap().push((field.name().to_owned(), format!("{value:?}")));
+        }
+    }
+
+    impl tracing::Subscriber for Collect {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            span.record(&mut self.clone());
+            Id::from_u64(self.next_id.fetch_add(1, Ordering::Relaxed) + 1)
+        }
+
+        fn record(&self, _: &Id, values: &Record<'_>) {
+            values.record(&mut self.clone());
+        }
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+        fn event(&self, _: &Event<'_>) {}
+        fn enter(&self, _: &Id) {}
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[test]
+    fn span_fields() {
+        let collect = Collect::default();
+        let fields = collect.fields.clone();
+
+        tracing::subscriber::with_default(collect, || {
+            let service = crate::router::Router::new()
+                .route("/users", crate::router::get(||async { (StatusCode::CREATED, "created") }))
+                .layer(Trace::new());
+            let mut req = Request::new(Default::default());
+            *req.method_mut() = http::Method::POST;
+            *req.uri_mut() = "/users?a=1".parse().unwrap();
+            req.extensions_mut().insert(RequestId::new("abc"));
+
+            let mut cx = Context::from_waker(std::task::Waker::noop());
+            let mut f = std::pin::pin!(service.call(req));
+            assert!(matches!(f.as_mut().poll(&mut cx), Poll::Ready(Ok(_))));
+        });
+
+        let fields = fields.lock().unwrap();
+        let get = |name: &str| fields.iter().find(|(e,_)|e == name).map(|(_,e)|e.as_str());
+        assert_eq!(get("method"), Some("POST"));
+        assert_eq!(get("path"), Some("\"/users\""));
+        assert_eq!(get("route"), Some("\"/users\""));
+        assert_eq!(get("request_id"), Some("\"abc\""));
+        assert_eq!(get("status"), Some("201"));
+        assert!(get("latency_ms").is_some());
+    }
+}
+
+/// request tracing layer
+///
+/// see [module level documentation](self) for more details
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Trace {
+    _priv: (),
+}
+
+impl Trace {
+    /// create new `Trace`
+    pub fn new() -> Self {
+        Self { _priv: () }
+    }
+}
+
+impl<S> Layer<S> for Trace {
+    type Service = TraceService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TraceService { inner }
+    }
+}
+
+/// service that open span for every request
+#[derive(Clone)]
+pub struct TraceService<S> {
+    inner: S,
+}
+
+impl<S,B> Service<Request<B>> for TraceService<S>
+where
+    S: Service<Request<B>, Response = Response>,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = TraceFuture<S::Future>;
+
+    fn call(&self, req: Request<B>) -> Self::Future {
+        let span = tracing::info_span!(
+            "request",
+            method = %req.method(),
+            path = req.uri().path(),
+            route = Empty,
+            request_id = Empty,
+            status = Empty,
+            latency_ms = Empty,
+        );
+        if let Some(id) = req.extensions().get::<RequestId>() {
+            span.record("request_id", id.as_str());
+        }
+        let inner = span.in_scope(||self.inner.call(req));
+        TraceFuture { inner: inner.instrument(span), start: Instant::now() }
+    }
+}
+
+pin_project_lite::pin_project! {
+    /// future returned from [`TraceService`]
+    pub struct TraceFuture<F> {
+        #[pin]
+        inner: Instrumented<F>,
+        start: Instant,
+    }
+}
+
+impl<F,E> Future for TraceFuture<F>
+where
+    F: Future<Output = Result<Response,E>>,
+{
+    type Output = Result<Response,E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut me = self.project();
+        let res = ready!(me.inner.as_mut().poll(cx));
+        let span: &Span = me.inner.span();
+        let latency = me.start.elapsed().as_secs_f64() * 1000.0;
+        span.record("latency_ms", latency);
+
+        let _enter = span.enter();
+        match &res {
+            Ok(res) => {
+                if let Some(route) = res.extensions().get::<MatchedPath>() {
+                    span.record("route", route.as_str());
+                }
+                span.record("status", res.status().as_u16());
+                tracing::info!(status = res.status().as_u16(), latency_ms = latency, "response");
+            }
+            Err(_) => tracing::error!(latency_ms = latency, "service error"),
+        }
+
+        Poll::Ready(res)
+    }
+}
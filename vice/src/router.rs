@@ -8,19 +8,30 @@
 //! fn main() -> std::io::Result<()> {
 //!     let route = Router::new()
 //!         .route("/", get(||async { String::from("Vice Dev") }));
+//! #   if false {
 //!     vice::listen("0.0.0.0:3000", route)
+//! #   } else { Ok(()) }
 //! }
 //! ```
 //!
 //!
 use crate::{
-    http::{Request, Response},
+    http::{FromRequestParts, IntoResponse, Request, Response},
+    middleware::Layer,
     util::{futures::EitherInto, service::NotFound, Either},
 };
 use handler::{Handler, HandlerService};
+use http::{request, StatusCode};
 use hyper::service::Service;
-use std::{convert::Infallible, sync::Arc};
+use std::{
+    convert::Infallible,
+    future::{ready, Ready},
+    pin::Pin,
+    sync::{Arc, OnceLock},
+    task::{self, Context, Poll},
+};
 
+pub mod fs;
 pub mod handler;
 
 /// route builder
@@ -73,6 +84,22 @@ impl<S> Router<S> {
         }
     }
 
+    /// wrap the whole router, including the fallback, with given layer
+    ///
+    /// see [`middleware`] for more details
+    ///
+    /// [`middleware`]: crate::middleware
+    pub fn layer<L>(self, layer: L) -> Router<L::Service>
+    where
+        L: Layer<S>,
+    {
+        Router {
+            inner: Arc::new(layer.layer(
+                Arc::into_inner(self.inner).expect("`Router` should not be cloned in builder"),
+            )),
+        }
+    }
+
     /// assign new route with early generic constraint check
     #[inline]
     pub fn route_checked<R>(self, path: &'static str, route: R) -> Router<Branch<R, S>>
@@ -83,6 +110,39 @@ impl<S> Router<S> {
     }
 }
 
+impl<R,F> Router<Branch<R,F>> {
+    /// wrap the last assigned route with given layer
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vice::{
+    ///     http::{Request, Response},
+    ///     middleware::{from_fn, Next},
+    ///     router::{Router, get},
+    /// };
+    ///
+    /// async fn auth(req: Request, next: Next) -> Response {
+    ///     next.run(req).await
+    /// }
+    ///
+    /// let route = Router::new()
+    ///     .route("/admin", get(||async { "secret" }))
+    ///     .route_layer(from_fn(auth))
+    ///     .route("/", get(||async { "public" }));
+    /// ```
+    pub fn route_layer<L>(self, layer: L) -> Router<Branch<L::Service, F>>
+    where
+        L: Layer<R>,
+    {
+        let Branch { matcher, inner, fallback } =
+            Arc::into_inner(self.inner).expect("`Router` should not be cloned in builder");
+        Router {
+            inner: Arc::new(Branch { matcher, inner: layer.layer(inner), fallback }),
+        }
+    }
+}
+
 impl<S> Service<Request> for Router<S>
 where
     S: Service<Request, Response = Response, Error = Infallible> + Clone + Send + Sync + 'static,
@@ -130,18 +190,108 @@ where
 {
     type Response = Response;
     type Error = Infallible;
-    type Future = EitherInto<S::Future,F::Future,Result<Response,Infallible>>;
+    type Future = EitherInto<MatchedFuture<S::Future>,F::Future,Result<Response,Infallible>>;
 
-    fn call(&self, req: Request) -> Self::Future {
+    fn call(&self, mut req: Request) -> Self::Future {
         match self.matcher == req {
-            true => Either::Left(self.inner.call(req)).await_into(),
+            true => {
+                let path = self.matcher.path.map(MatchedPath);
+                if let Some(path) = path {
+                    if let Some(slot) = req.extensions().get::<RouteSlot>() {
+                        let _ = slot.0.set(path.as_str());
+                    }
+                    req.extensions_mut().insert(path);
+                }
+                Either::Left(MatchedFuture { inner: self.inner.call(req), path }).await_into()
+            }
             false => Either::Right(self.fallback.call(req)).await_into(),
         }
     }
 }
 
+pin_project_lite::pin_project! {
+    /// future returned from matched [`Branch`]
+    ///
+    /// insert [`MatchedPath`] into response extensions
+    pub struct MatchedFuture<F> {
+        #[pin]
+        inner: F,
+        path: Option<MatchedPath>,
+    }
+}
+
+impl<F,E> Future for MatchedFuture<F>
+where
+    F: Future<Output = Result<Response,E>>,
+{
+    type Output = Result<Response,E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let me = self.project();
+        let mut res = task::ready!(me.inner.poll(cx));
+        if let (Ok(res), Some(path)) = (&mut res, me.path.take()) {
+            res.extensions_mut().insert(path);
+        }
+        Poll::Ready(res)
+    }
+}
+
+/// the path of matched route
+///
+/// router insert this into request extensions before calling the route, and
+/// into response extensions after, so outer middleware can see which route
+/// handled the request
+///
+/// # Example
+///
+/// ```
+/// use vice::router::MatchedPath;
+///
+/// async fn handle(path: MatchedPath) -> String {
+///     path.as_str().to_owned()
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MatchedPath(&'static str);
+
+impl MatchedPath {
+    /// returns the matched path
+    pub fn as_str(&self) -> &'static str {
+        self.0
+    }
+}
+
+impl FromRequestParts for MatchedPath {
+    type Error = MissingMatchedPath;
+    type Future = Ready<Result<Self,Self::Error>>;
+
+    fn from_request_parts(parts: &mut request::Parts) -> Self::Future {
+        ready(parts.extensions.get::<MatchedPath>().copied().ok_or(MissingMatchedPath))
+    }
+}
+
+/// slot that outer middleware put into request extensions to observe the
+/// [`MatchedPath`] as soon as the router match, before the response is returned
+///
+/// the outermost match win
+#[derive(Clone, Default)]
+pub(crate) struct RouteSlot(pub(crate) Arc<OnceLock<&'static str>>);
+
+/// error returned when request is not routed with path matcher
+#[derive(thiserror::Error, Debug)]
+#[error("no route path matched")]
+pub struct MissingMatchedPath;
+
+impl IntoResponse for MissingMatchedPath {
+    fn into_response(self) -> Response {
+        (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()).into_response()
+    }
+}
+
 /// partially match request
 ///
+/// path ending with `/*` match the prefix itself and every path below it
+///
 /// # Example
 ///
 /// ```
@@ -153,6 +303,11 @@ where
 /// assert_eq!(RequestMatcher::from(Method::GET),Request::new(()));
 /// assert_eq!(RequestMatcher::from(("/",Method::GET)),Request::new(()));
 /// assert_ne!(RequestMatcher::from(("/",Method::POST)),Request::new(()));
+///
+/// let req = |uri: &str| Request::get(uri).body(()).unwrap();
+/// assert_eq!(RequestMatcher::from("/assets/*"),req("/assets/app.js"));
+/// assert_eq!(RequestMatcher::from("/assets/*"),req("/assets"));
+/// assert_ne!(RequestMatcher::from("/assets/*"),req("/assetsx"));
 /// ```
 #[derive(Clone,Default,Debug)]
 pub struct RequestMatcher {
@@ -162,17 +317,15 @@ pub struct RequestMatcher {
 
 impl<T> PartialEq<Request<T>> for RequestMatcher {
     fn eq(&self, other: &Request<T>) -> bool {
-        if let Some(path) = self.path {
-            if path != other.uri().path() {
-                return false;
-            }
-        }
-        if let Some(method) = &self.method {
-            if method != other.method() {
-                return false;
-            }
-        }
-        true
+        self.path.is_none_or(|path| path_matches(path, other.uri().path()))
+            && self.method.as_ref().is_none_or(|method| method == other.method())
+    }
+}
+
+fn path_matches(pattern: &str, path: &str) -> bool {
+    match pattern.strip_suffix("/*") {
+        Some(prefix) => path == prefix || path.strip_prefix(prefix).is_some_and(|e|e.starts_with('/')),
+        None => pattern == path,
     }
 }
 
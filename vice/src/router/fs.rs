@@ -0,0 +1,596 @@
+//! static file serving
+//!
+//! [`ServeDir`] serve files from a directory and [`ServeFile`] serve a single
+//! file, both support:
+//!
+//! - `GET` and `HEAD` method, other method is rejected with `405`
+//! - `ETag` and `Last-Modified` with `If-None-Match` and `If-Modified-Since`
+//! - single `Range` request with `If-Range`
+//! - precompressed `.br` and `.gz` sibling when enabled and accepted by client
+//!
+//! request path is percent decoded, and path containing `..` or resolving
+//! outside the directory through symlink is rejected with `404`
+//!
+//! # Example
+//!
+//! ```no_run
+//! use vice::router::{Router, fs::{ServeDir, ServeFile}};
+//!
+//! fn main() -> std::io::Result<()> {
+//!     let route = Router::new()
+//!         .route("/assets/*", ServeDir::new("public").precompressed_br().precompressed_gzip())
+//!         .route("/favicon.ico", ServeFile::new("public/favicon.ico"));
+//!     vice::listen("0.0.0.0:3000", route)
+//! }
+//! ```
+use super::MatchedPath;
+use crate::{
+    http::{Body, IntoResponse, Request, Response, body::BoxError},
+    util::time::UtcTime,
+};
+use bytes::{Bytes, BytesMut};
+use http::{header, request, HeaderMap, HeaderValue, Method, StatusCode};
+use hyper::{
+    body::{Body as HttpBody, Frame, SizeHint},
+    service::Service,
+};
+use std::{
+    convert::Infallible,
+    ffi::OsString,
+    io::{self, SeekFrom},
+    path::{Component, Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+    task::{ready, Context, Poll},
+    time::UNIX_EPOCH,
+};
+use tokio::{
+    fs::File,
+    io::{AsyncRead, AsyncSeekExt, ReadBuf},
+};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use http_body_util::BodyExt;
+
+    fn run(service: &ServeDir, uri: &str, headers: &[(&'static str, &str)]) -> (Response, Bytes) {
+        let mut req = Request::new(Body::default());
+        *req.uri_mut() = uri.parse().unwrap();
+        req.extensions_mut().insert(MatchedPath("/static/*"));
+        for (name, value) in headers {
+            req.headers_mut().insert(*name, value.parse().unwrap());
+        }
+        tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+            let (parts, body) = service.call(req).await.unwrap().into_parts();
+            (Response::from_parts(parts, Body::default()), body.collect().await.unwrap().to_bytes())
+        })
+    }
+
+    #[test]
+    fn serve_dir() {
+        let dir = std::env::temp_dir().join(format!("vice-fs-{}", crate::util::random::hex_token(8)));
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("app.js"), "0123456789").unwrap();
+        std::fs::write(dir.join("app.js.gz"), "gzipped").unwrap();
+        std::fs::write(dir.join("sub/index.html"), "<h1>").unwrap();
+        let service = ServeDir::new(&dir).precompressed_gzip();
+
+        let (res, body) = run(&service, "/static/app.js", &[]);
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "text/javascript; charset=utf-8");
+        assert_eq!(body, "0123456789");
+        let etag = res.headers()[header::ETAG].to_str().unwrap().to_owned();
+        let modified = res.headers()[header::LAST_MODIFIED].to_str().unwrap().to_owned();
+
+        let (res, _) = run(&service, "/static/app.js", &[("if-none-match", &etag)]);
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        let (res, _) = run(&service, "/static/app.js", &[("if-modified-since", &modified)]);
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+
+        let (res, body) = run(&service, "/static/app.js", &[("range", "bytes=2-4"), ("if-range", &etag)]);
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(res.headers()[header::CONTENT_RANGE], "bytes 2-4/10");
+        assert_eq!(body, "234");
+        let (_, body) = run(&service, "/static/app.js", &[("range", "bytes=-3"), ("if-range", "\"stale\"")]);
+        assert_eq!(body, "0123456789");
+        let (res, _) = run(&service, "/static/app.js", &[("range", "bytes=10-")]);
+        assert_eq!(res.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(res.headers()[header::CONTENT_RANGE], "bytes */10");
+
+        let (res, body) = run(&service, "/static/app.js", &[("accept-encoding", "br, gzip")]);
+        assert_eq!(res.headers()[header::CONTENT_ENCODING], "gzip");
+        assert_eq!(res.headers()[header::VARY], "accept-encoding");
+        assert_eq!(body, "gzipped");
+
+        let (res, body) = run(&service, "/static/sub/", &[]);
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "text/html; charset=utf-8");
+        assert_eq!(body, "<h1>");
+        let (res, _) = run(&service, "/static/sub?a=1", &[]);
+        assert_eq!(res.headers()[header::LOCATION], "./sub/?a=1");
+        let uri = http::Uri::builder().path_and_query("//evil.com").build().unwrap();
+        assert_eq!(redirect_slash(&uri).headers()[header::LOCATION], "./evil.com/");
+        assert_eq!(run(&service, "/other/app.js", &[]).0.status(), StatusCode::NOT_FOUND);
+
+        assert_eq!(run(&service, "/static/%2e%2e/etc/passwd", &[]).0.status(), StatusCode::NOT_FOUND);
+        assert_eq!(run(&service, "/static/missing", &[]).0.status(), StatusCode::NOT_FOUND);
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(std::env::temp_dir(), dir.join("escape")).unwrap();
+            assert_eq!(run(&service, "/static/escape/", &[]).0.status(), StatusCode::NOT_FOUND);
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
+
+/// size of each streamed body chunk
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// future returned from [`ServeDir`] and [`ServeFile`]
+pub type ServeFuture = Pin<Box<dyn Future<Output = Result<Response,Infallible>> + Send + 'static>>;
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Precompressed {
+    br: bool,
+    gzip: bool,
+}
+
+/// service that serve files from a directory
+///
+/// when mounted with path ending in `/*`, the matched prefix is stripped
+/// from request path before mapping it to the directory
+///
+/// directory request is served with its index file, request to directory
+/// without trailing slash is redirected
+///
+/// see [module level documentation](self) for more details
+#[derive(Clone, Debug)]
+pub struct ServeDir {
+    root: Arc<PathBuf>,
+    index: Option<Arc<str>>,
+    precompressed: Precompressed,
+}
+
+impl ServeDir {
+    /// create new `ServeDir`, default index file is `index.html`
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: Arc::new(root.into()),
+            index: Some("index.html".into()),
+            precompressed: Precompressed::default(),
+        }
+    }
+
+    /// file served for directory request, `None` to respond `404` instead
+    pub fn index_file(mut self, name: Option<&str>) -> Self {
+        self.index = name.map(Into::into);
+        self
+    }
+
+    /// serve `.br` sibling when client accept brotli
+    pub fn precompressed_br(mut self) -> Self {
+        self.precompressed.br = true;
+        self
+    }
+
+    /// serve `.gz` sibling when client accept gzip
+    pub fn precompressed_gzip(mut self) -> Self {
+        self.precompressed.gzip = true;
+        self
+    }
+
+    async fn serve(self, parts: request::Parts) -> Response {
+        let path = parts.uri.path();
+        let relative = match parts.extensions.get::<MatchedPath>().and_then(|e|e.as_str().strip_suffix("/*")) {
+            Some(prefix) => path.strip_prefix(prefix),
+            None => Some(path),
+        };
+        let Some(relative) = relative.and_then(decode_path) else {
+            return StatusCode::NOT_FOUND.into_response();
+        };
+
+        let root = match tokio::fs::canonicalize(&*self.root).await {
+            Ok(root) => root,
+            Err(err) => return io_error(err),
+        };
+        let mut path = root.join(relative);
+        let meta = match tokio::fs::metadata(&path).await {
+            Ok(meta) => meta,
+            Err(err) => return io_error(err),
+        };
+        if meta.is_dir() {
+            if !parts.uri.path().ends_with('/') {
+                return redirect_slash(&parts.uri);
+            }
+            let Some(index) = &self.index else {
+                return StatusCode::NOT_FOUND.into_response();
+            };
+            path.push(&**index);
+        }
+
+        serve_file(&parts, &path, Some(&root), self.precompressed).await
+    }
+}
+
+impl Service<Request> for ServeDir {
+    type Response = Response;
+    type Error = Infallible;
+    type Future = ServeFuture;
+
+    fn call(&self, req: Request) -> Self::Future {
+        let (parts, _) = req.into_parts();
+        let me = self.clone();
+        Box::pin(async move {
+            if !matches!(parts.method, Method::GET | Method::HEAD) {
+                return Ok(method_not_allowed());
+            }
+            Ok(me.serve(parts).await)
+        })
+    }
+}
+
+/// service that serve a single file
+///
+/// see [module level documentation](self) for more details
+#[derive(Clone, Debug)]
+pub struct ServeFile {
+    path: Arc<PathBuf>,
+    precompressed: Precompressed,
+}
+
+impl ServeFile {
+    /// create new `ServeFile`
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: Arc::new(path.into()), precompressed: Precompressed::default() }
+    }
+
+    /// serve `.br` sibling when client accept brotli
+    pub fn precompressed_br(mut self) -> Self {
+        self.precompressed.br = true;
+        self
+    }
+
+    /// serve `.gz` sibling when client accept gzip
+    pub fn precompressed_gzip(mut self) -> Self {
+        self.precompressed.gzip = true;
+        self
+    }
+}
+
+impl Service<Request> for ServeFile {
+    type Response = Response;
+    type Error = Infallible;
+    type Future = ServeFuture;
+
+    fn call(&self, req: Request) -> Self::Future {
+        let (parts, _) = req.into_parts();
+        let me = self.clone();
+        Box::pin(async move {
+            if !matches!(parts.method, Method::GET | Method::HEAD) {
+                return Ok(method_not_allowed());
+            }
+            Ok(serve_file(&parts, &me.path, None, me.precompressed).await)
+        })
+    }
+}
+
+fn method_not_allowed() -> Response {
+    (StatusCode::METHOD_NOT_ALLOWED, ("Allow", "GET, HEAD")).into_response()
+}
+
+/// serve file at `path`, the resolved path must be inside `root` if given
+async fn serve_file(parts: &request::Parts, path: &Path, root: Option<&Path>, precompressed: Precompressed) -> Response {
+    let mut encoding = None;
+    let mut file = None;
+    for (name, ext, enabled) in [("br", "br", precompressed.br), ("gzip", "gz", precompressed.gzip)] {
+        if !enabled || !accepts(&parts.headers, name) {
+            continue;
+        }
+        let mut sibling = OsString::from(path);
+        sibling.push(".");
+        sibling.push(ext);
+        if let Ok(Some(found)) = open(Path::new(&sibling), root).await {
+            encoding = Some(name);
+            file = Some(found);
+            break;
+        }
+    }
+    let (mut file, meta) = match file {
+        Some(file) => file,
+        None => match open(path, root).await {
+            Ok(Some(file)) => file,
+            Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+            Err(err) => return io_error(err),
+        },
+    };
+
+    let len = meta.len();
+    let modified = meta.modified().ok().and_then(|e|e.duration_since(UNIX_EPOCH).ok());
+    let etag = format!(
+        "\"{len:x}-{:x}{}\"",
+        modified.map_or(0, |e|e.as_nanos()),
+        encoding.map_or(String::new(), |e|format!("-{e}")),
+    );
+    let last_modified = modified.map(|e|UtcTime::from(UNIX_EPOCH + e));
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::ETAG, header_value(etag.clone()));
+    if let Some(time) = &last_modified {
+        headers.insert(header::LAST_MODIFIED, header_value(time.http_date().to_string()));
+    }
+    if precompressed.br || precompressed.gzip {
+        headers.insert(header::VARY, HeaderValue::from_static("accept-encoding"));
+    }
+
+    if is_not_modified(&parts.headers, &etag, last_modified.as_ref()) {
+        return (StatusCode::NOT_MODIFIED, headers).into_response();
+    }
+
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(mime_type(path)));
+    headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    if let Some(encoding) = encoding {
+        headers.insert(header::CONTENT_ENCODING, HeaderValue::from_static(encoding));
+    }
+
+    let (status, start, end) = match byte_range(&parts.headers, len, &etag, last_modified.as_ref()) {
+        ByteRange::Full => (StatusCode::OK, 0, len),
+        ByteRange::Partial(start, end) => {
+            headers.insert(header::CONTENT_RANGE, header_value(format!("bytes {start}-{}/{len}", end - 1)));
+            (StatusCode::PARTIAL_CONTENT, start, end)
+        }
+        ByteRange::Unsatisfiable => {
+            headers.insert(header::CONTENT_RANGE, header_value(format!("bytes */{len}")));
+            return (StatusCode::RANGE_NOT_SATISFIABLE, headers).into_response();
+        }
+    };
+    headers.insert(header::CONTENT_LENGTH, header_value((end - start).to_string()));
+
+    if parts.method == Method::HEAD {
+        return (status, headers).into_response();
+    }
+    if start != 0 && let Err(err) = file.seek(SeekFrom::Start(start)).await {
+        return io_error(err);
+    }
+    let body = Body::new(FileBody { file, remaining: end - start, buf: BytesMut::new() });
+    (status, headers, Response::new(body)).into_response()
+}
+
+/// open regular file, returns `None` if it is not a file or resolved outside `root`
+///
+/// with `root`, the checked canonical path is opened, so a symlink swapped in
+/// after the check is not followed
+async fn open(path: &Path, root: Option<&Path>) -> io::Result<Option<(File, std::fs::Metadata)>> {
+    let path = match root {
+        Some(root) => {
+            let path = tokio::fs::canonicalize(path).await?;
+            if !path.starts_with(root) {
+                return Ok(None);
+            }
+            path
+        }
+        None => path.to_owned(),
+    };
+    let file = File::open(&path).await?;
+    let meta = file.metadata().await?;
+    Ok(meta.is_file().then_some((file, meta)))
+}
+
+fn io_error(err: io::Error) -> Response {
+    match err.kind() {
+        io::ErrorKind::NotFound | io::ErrorKind::NotADirectory | io::ErrorKind::InvalidFilename => {
+            StatusCode::NOT_FOUND.into_response()
+        }
+        io::ErrorKind::PermissionDenied => StatusCode::FORBIDDEN.into_response(),
+        _ => {
+            log::error!("failed to serve file: {err}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+fn header_value(value: String) -> HeaderValue {
+    HeaderValue::try_from(value).expect("formatted header value is valid")
+}
+
+/// redirect to the same path with trailing slash
+///
+/// the location is relative to the last segment, so a path like `//host`
+/// cannot turn into a protocol relative redirect
+fn redirect_slash(uri: &http::Uri) -> Response {
+    let name = uri.path().rsplit('/').next().unwrap_or_default();
+    let location = match uri.query() {
+        Some(query) => format!("./{name}/?{query}"),
+        None => format!("./{name}/"),
+    };
+    (StatusCode::MOVED_PERMANENTLY, ("Location", location)).into_response()
+}
+
+/// percent decode request path into relative path, returns `None` if the
+/// path try to escape the directory
+fn decode_path(path: &str) -> Option<PathBuf> {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = |b: u8| (b as char).to_digit(16);
+        match (bytes[i], bytes.get(i + 1).and_then(|e|hex(*e)), bytes.get(i + 2).and_then(|e|hex(*e))) {
+            (b'%', Some(hi), Some(lo)) => {
+                decoded.push((hi * 16 + lo) as u8);
+                i += 2;
+            }
+            (b, _, _) => decoded.push(b),
+        }
+        i += 1;
+    }
+
+    let decoded = String::from_utf8(decoded).ok()?;
+    let mut path = PathBuf::new();
+    for segment in decoded.split('/').filter(|e|!e.is_empty() && *e != ".") {
+        let mut components = Path::new(segment).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(_)), None) if !segment.contains(['\\', '\0']) => path.push(segment),
+            _ => return None,
+        }
+    }
+    Some(path)
+}
+
+/// returns `true` if `Accept-Encoding` accept given encoding
+fn accepts(headers: &HeaderMap, encoding: &str) -> bool {
+    headers
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|e|e.to_str().ok())
+        .flat_map(|e|e.split(','))
+        .any(|e| {
+            let mut params = e.split(';').map(str::trim);
+            params.next().is_some_and(|e|e.eq_ignore_ascii_case(encoding))
+                && !params.any(|e|matches!(e.split_once('='), Some(("q", q)) if q.trim_end_matches(['0', '.']).is_empty()))
+        })
+}
+
+fn is_not_modified(headers: &HeaderMap, etag: &str, modified: Option<&UtcTime>) -> bool {
+    // `If-Modified-Since` is ignored when `If-None-Match` is present
+    if let Some(value) = headers.get(header::IF_NONE_MATCH) {
+        let Ok(value) = value.to_str() else {
+            return false;
+        };
+        return value.trim() == "*"
+            || value.split(',').any(|e|e.trim().trim_start_matches("W/") == etag);
+    }
+    let since = headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|e|e.to_str().ok())
+        .and_then(UtcTime::from_http_date);
+    matches!((since, modified), (Some(since), Some(modified)) if modified.unix() <= since.unix())
+}
+
+enum ByteRange {
+    Full,
+    /// start inclusive, end exclusive
+    Partial(u64, u64),
+    Unsatisfiable,
+}
+
+/// parse `Range` header, only single range is supported, other is served in full
+fn byte_range(headers: &HeaderMap, len: u64, etag: &str, modified: Option<&UtcTime>) -> ByteRange {
+    let Some(range) = headers.get(header::RANGE).and_then(|e|e.to_str().ok()) else {
+        return ByteRange::Full;
+    };
+    if let Some(if_range) = headers.get(header::IF_RANGE) {
+        // etag in `If-Range` use strong comparison
+        let fresh = match if_range.to_str() {
+            Ok(value) if value.starts_with('"') => value == etag,
+            Ok(value) => matches!(
+                (UtcTime::from_http_date(value), modified),
+                (Some(date), Some(modified)) if date == *modified
+            ),
+            Err(_) => false,
+        };
+        if !fresh {
+            return ByteRange::Full;
+        }
+    }
+
+    let Some((start, end)) = range.trim().strip_prefix("bytes=").and_then(|e|e.split_once('-')) else {
+        return ByteRange::Full;
+    };
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => match suffix.parse::<u64>() {
+            Ok(0) => return ByteRange::Unsatisfiable,
+            Ok(suffix) => (len.saturating_sub(suffix), len),
+            Err(_) => return ByteRange::Full,
+        },
+        (start, "") => match start.parse::<u64>() {
+            Ok(start) => (start, len),
+            Err(_) => return ByteRange::Full,
+        },
+        (start, end) => match (start.parse::<u64>(), end.parse::<u64>()) {
+            (Ok(start), Ok(end)) if start <= end => (start, end.saturating_add(1).min(len)),
+            _ => return ByteRange::Full,
+        },
+    };
+    match start < len {
+        true => ByteRange::Partial(start, end),
+        false => ByteRange::Unsatisfiable,
+    }
+}
+
+/// guess mime type from file extension
+fn mime_type(path: &Path) -> &'static str {
+    let ext = path.extension().and_then(|e|e.to_str()).unwrap_or_default().to_ascii_lowercase();
+    match ext.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" | "map" => "application/json",
+        "txt" => "text/plain; charset=utf-8",
+        "md" => "text/markdown; charset=utf-8",
+        "csv" => "text/csv; charset=utf-8",
+        "xml" => "application/xml",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "mp3" => "audio/mpeg",
+        "ogg" => "audio/ogg",
+        "wav" => "audio/wav",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        _ => "application/octet-stream",
+    }
+}
+
+/// body that stream file content in chunks
+struct FileBody {
+    file: File,
+    remaining: u64,
+    buf: BytesMut,
+}
+
+impl HttpBody for FileBody {
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let me = self.get_mut();
+        if me.remaining == 0 {
+            return Poll::Ready(None);
+        }
+
+        me.buf.resize(me.remaining.min(CHUNK_SIZE as u64) as usize, 0);
+        let mut read = ReadBuf::new(&mut me.buf);
+        ready!(Pin::new(&mut me.file).poll_read(cx, &mut read))?;
+        let n = read.filled().len();
+        if n == 0 {
+            // file is truncated after the headers is sent
+            me.remaining = 0;
+            return Poll::Ready(Some(Err(io::Error::from(io::ErrorKind::UnexpectedEof).into())));
+        }
+
+        me.remaining -= n as u64;
+        Poll::Ready(Some(Ok(Frame::data(me.buf.split_to(n).freeze()))))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.remaining == 0
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::with_exact(self.remaining)
+    }
+}
@@ -175,6 +175,7 @@ where
 
     fn handle(&self, req: Request) -> Self::Future {
         let (parts,body) = req.into_parts();
+        #[allow(clippy::type_complexity)]
         fn mapper<A1,A2,A3,A4,A,F,Fut>(((((a1,a2),a3),a4),a): ((((A1,A2),A3),A4),A), inner: F) -> Fut
         where
             F: FnOnce(A1,A2,A3,A4,A) -> Fut,
@@ -201,6 +202,7 @@ where
 
     fn handle(&self, req: Request) -> Self::Future {
         let (parts,body) = req.into_parts();
+        #[allow(clippy::type_complexity)]
         fn mapper<A1,A2,A3,A4,A5,A,F,Fut>((((((a1,a2),a3),a4),a5),a): (((((A1,A2),A3),A4),A5),A), inner: F) -> Fut
         where
             F: FnOnce(A1,A2,A3,A4,A5,A) -> Fut,
@@ -216,6 +218,14 @@ mod future {
     use http::request;
     use super::*;
 
+    /// convert extractor error into response
+    fn rejection<E: IntoResponse>(err: E) -> Response {
+        let res = err.into_response();
+        #[cfg(feature = "tracing")]
+        tracing::debug!(rejection = std::any::type_name::<E>(), status = %res.status(), "extractor rejected request");
+        res
+    }
+
     pin_project_lite::pin_project! {
         /// future that call handle without any arguments
         pub struct Ft<Fut> {
@@ -277,7 +287,7 @@ mod future {
             let me = self.project();
             match ready!(me.f.poll(cx)) {
                 Ok(frp) => Ready(Ok((me.parts.take().unwrap(),frp))),
-                Err(err) => Ready(Err(err.into_response())),
+                Err(err) => Ready(Err(rejection(err))),
             }
         }
     }
@@ -325,7 +335,7 @@ mod future {
                     },
                     FrpProj::Frp2 { f, parts, frp1, } => return match ready!(f.poll(cx)) {
                         Ok(frp2) => Ready(Ok((parts.take().unwrap(),(frp1.take().unwrap(),frp2)))),
-                        Err(err) => Ready(Err(err.into_response())),
+                        Err(err) => Ready(Err(rejection(err))),
                     }
                 }
             }
@@ -362,7 +372,7 @@ mod future {
         fn poll(self: std::pin::Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Self::Output> {
             match ready!(self.project().f.poll(cx)) {
                 Ok(fr) => Ready(Ok(fr)),
-                Err(err) => Ready(Err(err.into_response())),
+                Err(err) => Ready(Err(rejection(err))),
             }
         }
     }
@@ -413,7 +423,7 @@ mod future {
                     },
                     FrProj::Fr { f, frp } => return match ready!(f.poll(cx)) {
                         Ok(fr) => Ready(Ok((frp.take().unwrap(),fr))),
-                        Err(err) => Ready(Err(err.into_response())),
+                        Err(err) => Ready(Err(rejection(err))),
                     }
                 }
             }
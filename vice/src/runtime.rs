@@ -1,45 +1,333 @@
 //! entrypoint of the server
 use crate::http::{Request, Response};
-use hyper::{server::conn::http1::Builder as Hyper, service::Service};
-use hyper_util::rt::TokioIo;
-use log::error;
-use std::{convert::Infallible, fmt::Display, io, net::ToSocketAddrs};
-use tokio::net::TcpListener;
+use accept::{AcceptAction, AcceptErrorHandler, Backoff};
+use connect_info::ConnectService;
+use idle::{Idle, IdleIo, IdleService};
+use limit::LimitService;
+use hyper::{body::Incoming, server::conn::http1::Builder as Hyper, service::Service};
+use hyper_util::rt::{TokioIo, TokioTimer};
+use log::{debug, error};
+use proxy::ProxyProtocol;
+use std::{convert::Infallible, fmt::Display, io, net::ToSocketAddrs, sync::Arc, time::Duration};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpListener,
+    sync::Semaphore,
+};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::router::{Router, get};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+    fn serve(timeouts: Timeouts) -> (DuplexStream, tokio::task::JoinHandle<()>) {
+        let (client, server) = tokio::io::duplex(4096);
+        let route = Router::new()
+            .route("/", get(||async { "Vice Dev" }))
+            .route("/slow", get(||async {
+                tokio::time::sleep(Duration::from_millis(150)).await;
+                "slow"
+            }));
+        let conn = Connection::new(([127,0,0,1], 4000).into(), ([127,0,0,1], 3000).into());
+        let task = tokio::spawn(serve_connection(server, ConnectService::new(route, conn), conn, timeouts));
+        (client, task)
+    }
+
+    async fn read_response(client: &mut DuplexStream) -> String {
+        let mut buf = vec![0; 4096];
+        let len = client.read(&mut buf).await.unwrap();
+        String::from_utf8_lossy(&buf[..len]).into_owned()
+    }
+
+    #[test]
+    fn connection_timeouts() {
+        let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        rt.block_on(async {
+            let deadline = Duration::from_secs(2);
+
+            // stalled request headers close the connection
+            let (mut client, task) = serve(Timeouts { header_read: Some(Duration::from_millis(50)), idle: None });
+            client.write_all(b"GET / HTTP/1.1\r\nHost: vice\r\n").await.unwrap();
+            tokio::time::timeout(deadline, task).await.expect("header read timeout").unwrap();
+
+            // in flight request is not cut by idle timeout
+            let (mut client, task) = serve(Timeouts { header_read: None, idle: Some(Duration::from_millis(50)) });
+            client.write_all(b"GET /slow HTTP/1.1\r\nHost: vice\r\n\r\n").await.unwrap();
+            let res = read_response(&mut client).await;
+            assert!(res.starts_with("HTTP/1.1 200 OK"), "{res}");
+            assert!(res.ends_with("slow"), "{res}");
+
+            // keep alive connection is closed once idle
+            client.write_all(b"GET / HTTP/1.1\r\nHost: vice\r\n\r\n").await.unwrap();
+            assert!(read_response(&mut client).await.ends_with("Vice Dev"));
+            tokio::time::timeout(deadline, task).await.expect("idle timeout").unwrap();
+            assert_eq!(client.read(&mut [0; 16]).await.unwrap(), 0);
+
+            // without timeouts the idle connection stays open
+            let (mut client, task) = serve(Timeouts::default());
+            client.write_all(b"GET / HTTP/1.1\r\nHost: vice\r\n\r\n").await.unwrap();
+            assert!(read_response(&mut client).await.ends_with("Vice Dev"));
+            assert!(tokio::time::timeout(Duration::from_millis(200), task).await.is_err());
+        });
+    }
+}
+
+pub mod accept;
+pub mod connect_info;
+mod idle;
+pub mod limit;
+pub mod proxy;
+
+#[doc(inline)]
+pub use connect_info::{ConnectInfo, Connection};
+#[doc(inline)]
+pub use limit::ServerStats;
 
 /// entrypoint to run the server
+///
+/// use [`Server`] for more configuration
 pub fn listen<S>(addr: impl ToSocketAddrs + Display + Clone, service: S) -> io::Result<()>
 where
     S: Service<Request, Response = Response, Error = Infallible> + Clone + Send + 'static,
     S::Future: Future<Output = Result<Response,Infallible>> + Send + 'static,
 {
-    let tcp = std::net::TcpListener::bind(addr.clone()).map_err(|e|tcp_error(addr, e))?;
-    tcp.set_nonblocking(true)?;
-
-    tokio::runtime::Builder::new_multi_thread()
-        .enable_all()
-        .build()?
-        .block_on(async move {
-            let tcp = TcpListener::from_std(tcp)?;
-
-            loop {
-                let service = service.clone();
-                match tcp.accept().await {
-                    Ok((stream, _)) => {
-                        tokio::spawn(
-                            Hyper::new()
-                                .serve_connection(TokioIo::new(stream), service)
-                                .with_upgrades(),
-                        );
-                    }
-                    Err(err) => {
-                        error!("{err}");
+    Server::bind(addr)?.serve(service)
+}
+
+/// server builder
+///
+/// # Example
+///
+/// ```no_run
+/// use vice::{router::Router, runtime::{Server, proxy::ProxyProtocol}};
+///
+/// fn main() -> std::io::Result<()> {
+///     Server::bind("0.0.0.0:3000")?
+///         .proxy_protocol(ProxyProtocol::strict())
+///         .serve(Router::new())
+/// }
+/// ```
+pub struct Server {
+    tcp: std::net::TcpListener,
+    proxy: Option<ProxyProtocol>,
+    max_connections: Option<usize>,
+    max_requests: Option<usize>,
+    retry_after: Duration,
+    timeouts: Timeouts,
+    stats: ServerStats,
+    accept_handler: Box<dyn AcceptErrorHandler>,
+}
+
+/// connection level timeouts
+#[derive(Clone, Copy, Default)]
+struct Timeouts {
+    header_read: Option<Duration>,
+    idle: Option<Duration>,
+}
+
+impl Server {
+    /// bind tcp listener to given address
+    pub fn bind(addr: impl ToSocketAddrs + Display + Clone) -> io::Result<Server> {
+        let tcp = std::net::TcpListener::bind(addr.clone()).map_err(|e|tcp_error(addr, e))?;
+        tcp.set_nonblocking(true)?;
+        Ok(Server {
+            tcp,
+            proxy: None,
+            max_connections: None,
+            max_requests: None,
+            retry_after: Duration::from_secs(1),
+            timeouts: Timeouts::default(),
+            stats: ServerStats::default(),
+            accept_handler: Box::new(Backoff::default()),
+        })
+    }
+
+    /// read PROXY protocol header before serving each connection
+    ///
+    /// see [`proxy`] module for more details
+    pub fn proxy_protocol(mut self, proxy: ProxyProtocol) -> Server {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// maximum concurrent connections
+    ///
+    /// when reached, the server stop accepting new connection until one is closed
+    pub fn max_connections(mut self, max: usize) -> Server {
+        self.max_connections = Some(max);
+        self
+    }
+
+    /// maximum concurrent requests across all connections
+    ///
+    /// when reached, new request is rejected with `503 Service Unavailable`
+    pub fn max_requests(mut self, max: usize) -> Server {
+        self.max_requests = Some(max);
+        self
+    }
+
+    /// value of `Retry-After` header for rejected request, default to 1 second
+    pub fn retry_after(mut self, retry_after: Duration) -> Server {
+        self.retry_after = retry_after;
+        self
+    }
+
+    /// maximum duration to receive the complete request headers
+    ///
+    /// when exceeded, the connection is closed, disabled by default
+    pub fn header_read_timeout(mut self, timeout: Duration) -> Server {
+        self.timeouts.header_read = Some(timeout);
+        self
+    }
+
+    /// maximum duration of keep-alive connection without request in flight
+    /// and without any read or write
+    ///
+    /// when exceeded, the connection is gracefully closed, disabled by default
+    pub fn idle_timeout(mut self, timeout: Duration) -> Server {
+        self.timeouts.idle = Some(timeout);
+        self
+    }
+
+    /// policy of handling error when accepting connection
+    ///
+    /// default to [`Backoff`], see [`accept`] module for more details
+    pub fn accept_error_handler(mut self, handler: impl AcceptErrorHandler) -> Server {
+        self.accept_handler = Box::new(handler);
+        self
+    }
+
+    /// returns handle to the server counters
+    ///
+    /// the handle keep updated while the server is running
+    pub fn stats(&self) -> ServerStats {
+        self.stats.clone()
+    }
+
+    /// run the server, blocking the current thread
+    pub fn serve<S>(self, service: S) -> io::Result<()>
+    where
+        S: Service<Request, Response = Response, Error = Infallible> + Clone + Send + 'static,
+        S::Future: Future<Output = Result<Response,Infallible>> + Send + 'static,
+    {
+        let Server { tcp, proxy, max_connections, max_requests, retry_after, timeouts, stats, mut accept_handler } = self;
+        let connections = max_connections.map(|e|Arc::new(Semaphore::new(e)));
+        let requests = max_requests.map(|e|Arc::new(Semaphore::new(e)));
+        let service = LimitService::new(service, requests, retry_after, stats.clone());
+
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()?
+            .block_on(async move {
+                let tcp = TcpListener::from_std(tcp)?;
+                let local_addr = tcp.local_addr()?;
+
+                loop {
+                    let permit = match &connections {
+                        Some(connections) => {
+                            if connections.available_permits() == 0 {
+                                stats.accept_pause();
+                            }
+                            Some(connections.clone().acquire_owned().await.expect("semaphore never closed"))
+                        }
+                        None => None,
+                    };
+
+                    match tcp.accept().await {
+                        Ok((stream, peer_addr)) => {
+                            accept_handler.on_success();
+                            let local_addr = stream.local_addr().unwrap_or(local_addr);
+                            let conn = Connection::new(peer_addr, local_addr);
+                            let service = service.clone();
+                            let guard = stats.connection();
+                            let proxy = proxy.clone();
+
+                            tokio::spawn(async move {
+                                let _permit = permit;
+                                let _guard = guard;
+
+                                let Some(proxy) = proxy else {
+                                    return serve_connection(stream, ConnectService::new(service, conn), conn, timeouts).await;
+                                };
+
+                                let (stream, conn) = match proxy.accept(stream).await {
+                                    Ok((stream, Some(header))) => {
+                                        let conn = match (header.source(), header.destination()) {
+                                            (Some(src), Some(dst)) => conn.with_proxy(src, dst),
+                                            _ => conn,
+                                        };
+                                        (stream, conn.with_tls(header.is_tls()))
+                                    }
+                                    Ok((stream, None)) => (stream, conn),
+                                    Err(err) => {
+                                        debug!("{peer_addr}: {err}");
+                                        return;
+                                    }
+                                };
+                                serve_connection(stream, ConnectService::new(service, conn), conn, timeouts).await
+                            });
+                        }
+                        Err(err) => match accept_handler.on_error(&err) {
+                            AcceptAction::Retry => debug!("failed to accept connection: {err}"),
+                            AcceptAction::Backoff(delay) => {
+                                error!("failed to accept connection: {err}, retrying in {delay:?}");
+                                tokio::time::sleep(delay).await;
+                            }
+                            AcceptAction::Fail => {
+                                error!("failed to accept connection: {err}");
+                                return Err(err);
+                            }
+                        },
                     }
                 }
+            })
+    }
+}
+
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+async fn serve_connection<IO,S>(io: IO, service: S, conn: Connection, timeouts: Timeouts)
+where
+    IO: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    S: Service<Request<Incoming>, Response = Response, Error = Infallible>,
+{
+    let mut hyper = Hyper::new();
+    if let Some(timeout) = timeouts.header_read {
+        hyper.timer(TokioTimer::new()).header_read_timeout(timeout);
+    }
+
+    let idle = Idle::new();
+    let serve = hyper
+        .serve_connection(TokioIo::new(IdleIo::new(io, idle.clone())), IdleService::new(service, idle.clone()))
+        .with_upgrades();
+
+    let serve = async move {
+        let mut serve = std::pin::pin!(serve);
+        let Some(timeout) = timeouts.idle else {
+            return serve.await;
+        };
+        let mut idle = std::pin::pin!(idle.timeout(timeout));
+        let mut shutdown = false;
+        std::future::poll_fn(|cx| {
+            if !shutdown && idle.as_mut().poll(cx).is_ready() {
+                serve.as_mut().graceful_shutdown();
+                shutdown = true;
             }
+            serve.as_mut().poll(cx)
         })
+        .await
+    };
+
+    #[cfg(feature = "tracing")]
+    let serve = tracing::Instrument::instrument(serve, tracing::info_span!(
+        "connection",
+        peer = %conn.peer_addr(),
+        local = %conn.local_addr(),
+    ));
+
+    let _ = serve.await;
 }
 
 fn tcp_error(addr: impl ToSocketAddrs + Display + Clone, err: io::Error) -> io::Error {
     io::Error::new(err.kind(), format!("failed to bind \"{addr}\" :{err}"))
 }
-
@@ -0,0 +1,212 @@
+//! accept loop error handling
+//!
+//! error returned from `accept` is classified into:
+//!
+//! - [`Transient`], error specific to single connection, like the client reset
+//!   the connection before it is accepted, accept is retried immediately
+//! - [`Exhausted`], process or system run out of resource, like file descriptor
+//!   limit (`EMFILE`/`ENFILE`), accept is retried after exponential backoff
+//!   to give time for other connection to close
+//! - [`Fatal`], the listener is unusable, the server stop and return the error
+//!
+//! custom policy can be provided by implementing [`AcceptErrorHandler`]
+//!
+//! [`Transient`]: AcceptErrorKind::Transient
+//! [`Exhausted`]: AcceptErrorKind::Exhausted
+//! [`Fatal`]: AcceptErrorKind::Fatal
+use std::{io, time::Duration};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn backoff() {
+        let mut backoff = Backoff::new(Duration::from_millis(10), Duration::from_millis(35));
+        let emfile = io::Error::from_raw_os_error(errno::EXHAUSTED[1]);
+        let reset = io::Error::from(io::ErrorKind::ConnectionReset);
+        let fatal = io::Error::from(io::ErrorKind::InvalidInput);
+
+        assert_eq!(backoff.on_error(&reset), AcceptAction::Retry);
+        assert_eq!(backoff.on_error(&emfile), AcceptAction::Backoff(Duration::from_millis(10)));
+        assert_eq!(backoff.on_error(&emfile), AcceptAction::Backoff(Duration::from_millis(20)));
+        assert_eq!(backoff.on_error(&emfile), AcceptAction::Backoff(Duration::from_millis(35)));
+        backoff.on_success();
+        assert_eq!(backoff.on_error(&emfile), AcceptAction::Backoff(Duration::from_millis(10)));
+        assert_eq!(backoff.on_error(&fatal), AcceptAction::Fail);
+    }
+}
+
+/// classification of accept error
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AcceptErrorKind {
+    /// error specific to single connection
+    Transient,
+    /// process or system run out of resource
+    Exhausted,
+    /// listener is unusable
+    Fatal,
+}
+
+impl AcceptErrorKind {
+    /// classify accept error
+    pub fn classify(err: &io::Error) -> AcceptErrorKind {
+        use io::ErrorKind::*;
+
+        if let Some(code) = err.raw_os_error() && errno::EXHAUSTED.contains(&code) {
+            return AcceptErrorKind::Exhausted;
+        }
+
+        match err.kind() {
+            OutOfMemory => AcceptErrorKind::Exhausted,
+            ConnectionAborted | ConnectionReset | ConnectionRefused | Interrupted | WouldBlock
+            | TimedOut | PermissionDenied => AcceptErrorKind::Transient,
+            _ => match err.raw_os_error() {
+                Some(code) if errno::TRANSIENT.contains(&code) => AcceptErrorKind::Transient,
+                _ => AcceptErrorKind::Fatal,
+            },
+        }
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod errno {
+    /// ENFILE, EMFILE, ENOMEM, ENOBUFS
+    pub const EXHAUSTED: &[i32] = &[23, 24, 12, 105];
+    /// EPROTO, ENETDOWN, ENOPROTOOPT, ENONET, EHOSTDOWN, EHOSTUNREACH, EOPNOTSUPP, ENETUNREACH
+    pub const TRANSIENT: &[i32] = &[71, 100, 92, 64, 112, 113, 95, 101];
+}
+
+#[cfg(target_vendor = "apple")]
+mod errno {
+    /// ENFILE, EMFILE, ENOMEM, ENOBUFS
+    pub const EXHAUSTED: &[i32] = &[23, 24, 12, 55];
+    /// EPROTO, ENETDOWN, ENOPROTOOPT, EHOSTDOWN, EHOSTUNREACH, EOPNOTSUPP, ENETUNREACH
+    pub const TRANSIENT: &[i32] = &[100, 50, 42, 64, 65, 102, 51];
+}
+
+#[cfg(any(target_os = "freebsd", target_os = "dragonfly", target_os = "netbsd", target_os = "openbsd"))]
+mod errno {
+    /// ENFILE, EMFILE, ENOMEM, ENOBUFS
+    pub const EXHAUSTED: &[i32] = &[23, 24, 12, 55];
+    /// EPROTO, ENETDOWN, ENOPROTOOPT, EHOSTDOWN, EHOSTUNREACH, EOPNOTSUPP, ENETUNREACH
+    pub const TRANSIENT: &[i32] = &[EPROTO, 50, 42, 64, 65, 45, 51];
+
+    #[cfg(any(target_os = "freebsd", target_os = "dragonfly"))]
+    const EPROTO: i32 = 92;
+    #[cfg(target_os = "netbsd")]
+    const EPROTO: i32 = 96;
+    #[cfg(target_os = "openbsd")]
+    const EPROTO: i32 = 95;
+}
+
+/// other unix only classify by the portable values and [`io::ErrorKind`]
+#[cfg(all(
+    unix,
+    not(any(target_os = "linux", target_os = "android", target_vendor = "apple")),
+    not(any(target_os = "freebsd", target_os = "dragonfly", target_os = "netbsd", target_os = "openbsd")),
+))]
+mod errno {
+    /// ENFILE, EMFILE, ENOMEM
+    pub const EXHAUSTED: &[i32] = &[23, 24, 12];
+    pub const TRANSIENT: &[i32] = &[];
+}
+
+#[cfg(not(unix))]
+mod errno {
+    /// WSAEMFILE, WSAENOBUFS
+    pub const EXHAUSTED: &[i32] = &[10024, 10055];
+    /// WSAENETDOWN, WSAEHOSTDOWN, WSAEHOSTUNREACH, WSAENETUNREACH
+    pub const TRANSIENT: &[i32] = &[10050, 10064, 10065, 10051];
+}
+
+/// what the accept loop should do after an error
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AcceptAction {
+    /// accept again immediately
+    Retry,
+    /// wait for given duration before accept again
+    Backoff(Duration),
+    /// stop the server and return the error
+    Fail,
+}
+
+/// policy of handling accept error
+///
+/// # Example
+///
+/// ```no_run
+/// use vice::{router::Router, runtime::{Server, accept::AcceptAction}};
+/// use std::time::Duration;
+///
+/// fn main() -> std::io::Result<()> {
+///     Server::bind("0.0.0.0:3000")?
+///         .accept_error_handler(|_: &std::io::Error| AcceptAction::Backoff(Duration::from_millis(50)))
+///         .serve(Router::new())
+/// }
+/// ```
+pub trait AcceptErrorHandler: Send + 'static {
+    /// called when accept returns an error
+    fn on_error(&mut self, err: &io::Error) -> AcceptAction;
+
+    /// called when accept succeed
+    fn on_success(&mut self) { }
+}
+
+impl<F> AcceptErrorHandler for F
+where
+    F: FnMut(&io::Error) -> AcceptAction + Send + 'static,
+{
+    fn on_error(&mut self, err: &io::Error) -> AcceptAction {
+        self(err)
+    }
+}
+
+/// default accept error policy
+///
+/// [`Transient`] error is retried immediately, [`Exhausted`] error is
+/// backed off exponentially, and [`Fatal`] error stop the server
+///
+/// [`Transient`]: AcceptErrorKind::Transient
+/// [`Exhausted`]: AcceptErrorKind::Exhausted
+/// [`Fatal`]: AcceptErrorKind::Fatal
+#[derive(Clone, Debug)]
+pub struct Backoff {
+    min: Duration,
+    max: Duration,
+    current: Option<Duration>,
+}
+
+impl Backoff {
+    /// create new `Backoff` with given minimum and maximum delay
+    pub fn new(min: Duration, max: Duration) -> Self {
+        Self { min, max, current: None }
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new(Duration::from_millis(5), Duration::from_secs(1))
+    }
+}
+
+impl AcceptErrorHandler for Backoff {
+    fn on_error(&mut self, err: &io::Error) -> AcceptAction {
+        match AcceptErrorKind::classify(err) {
+            AcceptErrorKind::Transient => AcceptAction::Retry,
+            AcceptErrorKind::Exhausted => {
+                let delay = match self.current {
+                    Some(current) => (current * 2).min(self.max),
+                    None => self.min,
+                };
+                self.current = Some(delay);
+                AcceptAction::Backoff(delay)
+            }
+            AcceptErrorKind::Fatal => AcceptAction::Fail,
+        }
+    }
+
+    fn on_success(&mut self) {
+        self.current = None;
+    }
+}
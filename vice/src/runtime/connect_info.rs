@@ -0,0 +1,162 @@
+//! connection information
+use crate::http::{Body, FromRequestParts, IntoResponse, Request, Response};
+use http::{request, StatusCode};
+use hyper::{body::Incoming, service::Service};
+use std::{
+    future::{ready, Ready},
+    net::SocketAddr,
+    ops::Deref,
+};
+
+/// information about the connection a request come from
+///
+/// the runtime insert this into every request extensions,
+/// use [`ConnectInfo`] to extract it in handler
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Connection {
+    peer_addr: SocketAddr,
+    local_addr: SocketAddr,
+    proxy_addr: Option<SocketAddr>,
+    tls: bool,
+}
+
+impl Connection {
+    /// create new `Connection` for plain tcp stream
+    pub fn new(peer_addr: SocketAddr, local_addr: SocketAddr) -> Self {
+        Self { peer_addr, local_addr, proxy_addr: None, tls: false }
+    }
+
+    /// replace addresses with the one reported by PROXY protocol header
+    ///
+    /// the previous peer address is kept as [`proxy_addr`][Connection::proxy_addr]
+    pub fn with_proxy(mut self, peer_addr: SocketAddr, local_addr: SocketAddr) -> Self {
+        self.proxy_addr = Some(self.peer_addr);
+        self.peer_addr = peer_addr;
+        self.local_addr = local_addr;
+        self
+    }
+
+    /// mark whether the connection is secured by tls
+    ///
+    /// for use by tls acceptor, the runtime set this from PROXY protocol v2
+    /// `PP2_TYPE_SSL` tlv
+    pub fn with_tls(mut self, tls: bool) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    /// address of the remote peer
+    ///
+    /// when PROXY protocol is used, this is the real client address
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer_addr
+    }
+
+    /// address of the local socket that accept the connection
+    ///
+    /// when PROXY protocol is used, this is the original destination address
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// address of the proxy, if the connection use PROXY protocol
+    pub fn proxy_addr(&self) -> Option<SocketAddr> {
+        self.proxy_addr
+    }
+
+    /// is the connection secured by tls
+    ///
+    /// always `false` for the plain tcp listener, unless the proxy report a tls
+    /// client connection through PROXY protocol
+    pub fn is_tls(&self) -> bool {
+        self.tls
+    }
+}
+
+/// extract connection information from request extensions
+///
+/// the runtime insert [`Connection`] and the peer [`SocketAddr`] for every request,
+/// so both `ConnectInfo<Connection>` and `ConnectInfo<SocketAddr>` are available
+///
+/// # Example
+///
+/// ```
+/// use vice::runtime::{ConnectInfo, Connection};
+/// use std::net::SocketAddr;
+///
+/// async fn handle(ConnectInfo(peer): ConnectInfo<SocketAddr>) -> String {
+///     format!("hello {peer}")
+/// }
+///
+/// async fn handle_full(ConnectInfo(conn): ConnectInfo<Connection>) -> String {
+///     format!("accepted at {}", conn.local_addr())
+/// }
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct ConnectInfo<T>(pub T);
+
+impl<T> Deref for ConnectInfo<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> FromRequestParts for ConnectInfo<T>
+where
+    T: Clone + Send + Sync + 'static,
+{
+    type Error = MissingConnectInfo;
+    type Future = Ready<Result<Self,Self::Error>>;
+
+    fn from_request_parts(parts: &mut request::Parts) -> Self::Future {
+        ready(parts.extensions.get::<T>().cloned().map(ConnectInfo).ok_or(MissingConnectInfo))
+    }
+}
+
+/// error returned when request does not contains connection information
+///
+/// this typically happen when the service is not run with the vice runtime
+#[derive(thiserror::Error, Debug)]
+#[error("connection info is not available")]
+pub struct MissingConnectInfo;
+
+impl IntoResponse for MissingConnectInfo {
+    fn into_response(self) -> Response {
+        (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()).into_response()
+    }
+}
+
+/// service that insert [`Connection`] into request extensions and convert
+/// the request body into [`Body`]
+///
+/// the runtime wrap service with this for every accepted connection
+#[derive(Clone)]
+pub struct ConnectService<S> {
+    inner: S,
+    conn: Connection,
+}
+
+impl<S> ConnectService<S> {
+    /// create new `ConnectService`
+    pub fn new(inner: S, conn: Connection) -> Self {
+        Self { inner, conn }
+    }
+}
+
+impl<S> Service<Request<Incoming>> for ConnectService<S>
+where
+    S: Service<Request>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn call(&self, req: Request<Incoming>) -> Self::Future {
+        let mut req = req.map(Body::from);
+        req.extensions_mut().insert(self.conn);
+        req.extensions_mut().insert(self.conn.peer_addr);
+        self.inner.call(req)
+    }
+}
@@ -0,0 +1,162 @@
+//! idle keep-alive connection tracking
+use crate::http::{Request, Response};
+use hyper::service::Service;
+use std::{
+    io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{ready, Context, Poll},
+    time::{Duration, Instant},
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// last activity of a connection
+pub(crate) struct Idle {
+    start: Instant,
+    /// milliseconds since `start` of the last activity
+    last: AtomicU64,
+    in_flight: AtomicUsize,
+}
+
+impl Idle {
+    pub(crate) fn new() -> Arc<Self> {
+        Arc::new(Self { start: Instant::now(), last: AtomicU64::new(0), in_flight: AtomicUsize::new(0) })
+    }
+
+    fn touch(&self) {
+        self.last.store(self.start.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+
+    fn elapsed(&self) -> Duration {
+        let now = self.start.elapsed().as_millis() as u64;
+        Duration::from_millis(now.saturating_sub(self.last.load(Ordering::Relaxed)))
+    }
+
+    /// resolve when connection have no request in flight and no io activity for given duration
+    pub(crate) async fn timeout(self: Arc<Self>, timeout: Duration) {
+        loop {
+            let elapsed = self.elapsed();
+            if self.in_flight.load(Ordering::Relaxed) != 0 {
+                tokio::time::sleep(timeout).await;
+            } else if elapsed >= timeout {
+                return;
+            } else {
+                tokio::time::sleep(timeout - elapsed).await;
+            }
+        }
+    }
+}
+
+pin_project_lite::pin_project! {
+    /// io that record read and write activity
+    pub(crate) struct IdleIo<IO> {
+        #[pin]
+        io: IO,
+        idle: Arc<Idle>,
+    }
+}
+
+impl<IO> IdleIo<IO> {
+    pub(crate) fn new(io: IO, idle: Arc<Idle>) -> Self {
+        Self { io, idle }
+    }
+}
+
+impl<IO: AsyncRead> AsyncRead for IdleIo<IO> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let me = self.project();
+        let len = buf.filled().len();
+        ready!(me.io.poll_read(cx, buf))?;
+        if buf.filled().len() != len {
+            me.idle.touch();
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<IO: AsyncWrite> AsyncWrite for IdleIo<IO> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let me = self.project();
+        let len = ready!(me.io.poll_write(cx, buf))?;
+        if len != 0 {
+            me.idle.touch();
+        }
+        Poll::Ready(Ok(len))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().io.poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().io.poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(self: Pin<&mut Self>, cx: &mut Context<'_>, bufs: &[io::IoSlice<'_>]) -> Poll<io::Result<usize>> {
+        let me = self.project();
+        let len = ready!(me.io.poll_write_vectored(cx, bufs))?;
+        if len != 0 {
+            me.idle.touch();
+        }
+        Poll::Ready(Ok(len))
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.io.is_write_vectored()
+    }
+}
+
+/// service that mark the connection busy while request is in flight
+pub(crate) struct IdleService<S> {
+    inner: S,
+    idle: Arc<Idle>,
+}
+
+impl<S> IdleService<S> {
+    pub(crate) fn new(inner: S, idle: Arc<Idle>) -> Self {
+        Self { inner, idle }
+    }
+}
+
+impl<S,B> Service<Request<B>> for IdleService<S>
+where
+    S: Service<Request<B>, Response = Response>,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = IdleFuture<S::Future>;
+
+    fn call(&self, req: Request<B>) -> Self::Future {
+        self.idle.in_flight.fetch_add(1, Ordering::Relaxed);
+        IdleFuture { inner: self.inner.call(req), guard: InFlight(self.idle.clone()) }
+    }
+}
+
+/// decrement in flight request on drop
+pub(crate) struct InFlight(Arc<Idle>);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.touch();
+        self.0.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+pin_project_lite::pin_project! {
+    pub(crate) struct IdleFuture<F> {
+        #[pin]
+        inner: F,
+        guard: InFlight,
+    }
+}
+
+impl<F: Future> Future for IdleFuture<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.project().inner.poll(cx)
+    }
+}
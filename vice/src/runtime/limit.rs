@@ -0,0 +1,228 @@
+//! connection limits and load shedding
+use crate::http::{IntoResponse, Request, Response};
+use http::StatusCode;
+use hyper::service::Service;
+use std::{
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use http::header;
+    use std::convert::Infallible;
+
+    #[test]
+    fn limit() {
+        let stats = ServerStats::default();
+        let service = LimitService::new(
+            hyper::service::service_fn(|req: Request| async move {
+                if req.uri().path() == "/hang" {
+                    std::future::pending::<()>().await;
+                }
+                Ok::<Response,Infallible>(Response::default())
+            }),
+            Some(Arc::new(Semaphore::new(1))),
+            Duration::from_secs(3),
+            stats.clone(),
+        );
+        let mut cx = Context::from_waker(std::task::Waker::noop());
+        let call = |path: &'static str| {
+            let mut req = Request::new(Default::default());
+            *req.uri_mut() = path.parse().unwrap();
+            Box::pin(service.call(req))
+        };
+
+        let mut hang = call("/hang");
+        assert!(hang.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(stats.requests_in_flight(), 1);
+
+        let Poll::Ready(Ok(res)) = call("/").as_mut().poll(&mut cx) else { unreachable!() };
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(res.headers()[header::RETRY_AFTER], "3");
+        assert_eq!(stats.requests_shed(), 1);
+
+        // dropping the pending response future release the permit
+        drop(hang);
+        assert_eq!(stats.requests_in_flight(), 0);
+
+        let Poll::Ready(Ok(res)) = call("/").as_mut().poll(&mut cx) else { unreachable!() };
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(stats.requests_in_flight(), 0);
+        assert_eq!(stats.requests_shed(), 1);
+    }
+
+    #[test]
+    fn connection_stats() {
+        let stats = ServerStats::default();
+        let guard = stats.connection();
+        let _other = stats.clone().connection();
+        stats.accept_pause();
+        assert_eq!((stats.connections_total(), stats.connections_active()), (2, 2));
+        drop(guard);
+        assert_eq!((stats.connections_total(), stats.connections_active()), (2, 1));
+        assert_eq!(stats.accept_paused(), 1);
+    }
+}
+
+/// server counters
+///
+/// cloning `ServerStats` returns a handle to the same counters,
+/// obtained via [`Server::stats`]
+///
+/// [`Server::stats`]: super::Server::stats
+#[derive(Clone, Default, Debug)]
+pub struct ServerStats {
+    inner: Arc<Counters>,
+}
+
+#[derive(Default, Debug)]
+struct Counters {
+    connections_total: AtomicU64,
+    connections_active: AtomicU64,
+    accept_paused: AtomicU64,
+    requests_in_flight: AtomicU64,
+    requests_shed: AtomicU64,
+}
+
+impl ServerStats {
+    /// total accepted connections
+    pub fn connections_total(&self) -> u64 {
+        self.inner.connections_total.load(Ordering::Relaxed)
+    }
+
+    /// currently open connections
+    pub fn connections_active(&self) -> u64 {
+        self.inner.connections_active.load(Ordering::Relaxed)
+    }
+
+    /// how many times accept is paused because connection limit is reached
+    pub fn accept_paused(&self) -> u64 {
+        self.inner.accept_paused.load(Ordering::Relaxed)
+    }
+
+    /// currently processed requests
+    pub fn requests_in_flight(&self) -> u64 {
+        self.inner.requests_in_flight.load(Ordering::Relaxed)
+    }
+
+    /// how many requests rejected because request limit is reached
+    pub fn requests_shed(&self) -> u64 {
+        self.inner.requests_shed.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn accept_pause(&self) {
+        self.inner.accept_paused.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// track a connection until the returned guard is dropped
+    pub(crate) fn connection(&self) -> ConnectionGuard {
+        self.inner.connections_total.fetch_add(1, Ordering::Relaxed);
+        self.inner.connections_active.fetch_add(1, Ordering::Relaxed);
+        ConnectionGuard { stats: self.inner.clone() }
+    }
+}
+
+/// decrement active connections on drop
+pub(crate) struct ConnectionGuard {
+    stats: Arc<Counters>,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.stats.connections_active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// decrement in flight requests on drop
+pub struct RequestGuard {
+    stats: Arc<Counters>,
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Drop for RequestGuard {
+    fn drop(&mut self) {
+        self.stats.requests_in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// service that limit in flight requests
+///
+/// when the limit is reached, request is rejected with `503 Service Unavailable`
+/// and `Retry-After` header
+#[derive(Clone)]
+pub struct LimitService<S> {
+    inner: S,
+    limit: Option<Arc<Semaphore>>,
+    retry_after: Duration,
+    stats: ServerStats,
+}
+
+impl<S> LimitService<S> {
+    /// create new `LimitService`, `None` limit only count in flight requests
+    pub fn new(inner: S, limit: Option<Arc<Semaphore>>, retry_after: Duration, stats: ServerStats) -> Self {
+        Self { inner, limit, retry_after, stats }
+    }
+}
+
+impl<S,B> Service<Request<B>> for LimitService<S>
+where
+    S: Service<Request<B>, Response = Response>,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = LimitFuture<S::Future>;
+
+    fn call(&self, req: Request<B>) -> Self::Future {
+        let permit = match &self.limit {
+            Some(limit) => match limit.clone().try_acquire_owned() {
+                Ok(permit) => Some(permit),
+                Err(_) => {
+                    self.stats.inner.requests_shed.fetch_add(1, Ordering::Relaxed);
+                    let secs = self.retry_after.as_secs().max(1).to_string();
+                    let res = (StatusCode::SERVICE_UNAVAILABLE, ("Retry-After", secs)).into_response();
+                    return LimitFuture::Shed { res: Some(res) };
+                }
+            },
+            None => None,
+        };
+
+        self.stats.inner.requests_in_flight.fetch_add(1, Ordering::Relaxed);
+        let guard = RequestGuard { stats: self.stats.inner.clone(), _permit: permit };
+        LimitFuture::Inner { f: self.inner.call(req), guard: Some(guard) }
+    }
+}
+
+pin_project_lite::pin_project! {
+    /// future returned from [`LimitService`]
+    #[project = LimitProj]
+    pub enum LimitFuture<F> {
+        Inner { #[pin] f: F, guard: Option<RequestGuard> },
+        Shed { res: Option<Response> },
+    }
+}
+
+impl<F,E> Future for LimitFuture<F>
+where
+    F: Future<Output = Result<Response,E>>,
+{
+    type Output = Result<Response,E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project() {
+            LimitProj::Inner { f, guard } => {
+                let res = ready!(f.poll(cx));
+                guard.take();
+                Poll::Ready(res)
+            }
+            LimitProj::Shed { res } => Poll::Ready(Ok(res.take().expect("poll after complete"))),
+        }
+    }
+}
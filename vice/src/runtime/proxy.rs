@@ -0,0 +1,460 @@
+//! HAProxy PROXY protocol
+//!
+//! when the server is behind a tcp load balancer, the peer address is always the
+//! balancer, the balancer can prepend a PROXY protocol header before the actual
+//! stream which contains the real client address
+//!
+//! both [version 1 and version 2][spec] header are supported
+//!
+//! [spec]: https://www.haproxy.org/download/3.0/doc/proxy-protocol.txt
+use bytes::{Buf, Bytes, BytesMut};
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    pin::Pin,
+    str::FromStr,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_v1() {
+        let header = b"PROXY TCP4 192.168.0.1 192.168.0.11 56324 443\r\nGET / HTTP/1.1\r\n";
+        let Parsed::Header(header, len) = parse(header).unwrap() else { panic!() };
+        assert_eq!(len, 47);
+        assert_eq!(header.source(), Some("192.168.0.1:56324".parse().unwrap()));
+        assert_eq!(header.destination(), Some("192.168.0.11:443".parse().unwrap()));
+
+        let Parsed::Header(header, _) = parse(b"PROXY UNKNOWN\r\n").unwrap() else { panic!() };
+        assert_eq!(header.source(), None);
+
+        assert!(matches!(parse(b"PROXY TCP4 192.168").unwrap(), Parsed::Incomplete));
+        assert!(matches!(parse(b"PRO").unwrap(), Parsed::Incomplete));
+        assert!(matches!(parse(b"GET / HTTP/1.1\r\n").unwrap(), Parsed::NotProxy));
+        assert!(parse(b"PROXY TCP4 localhost 192.168.0.11 56324 443\r\n").is_err());
+    }
+
+    #[test]
+    fn parse_v2() {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.extend_from_slice(&[0x21, 0x11, 0, 12, 10, 0, 0, 1, 10, 0, 0, 2, 0x1f, 0x90, 0, 80]);
+        header.extend_from_slice(b"GET");
+        let Parsed::Header(header, len) = parse(&header).unwrap() else { panic!() };
+        assert_eq!(len, 28);
+        assert_eq!(header.source(), Some("10.0.0.1:8080".parse().unwrap()));
+        assert_eq!(header.destination(), Some("10.0.0.2:80".parse().unwrap()));
+        assert!(!header.is_tls());
+
+        // NOOP tlv followed by PP2_TYPE_SSL with client ssl flag
+        let mut ssl = V2_SIGNATURE.to_vec();
+        ssl.extend_from_slice(&[0x21, 0x11, 0, 24, 10, 0, 0, 1, 10, 0, 0, 2, 0x1f, 0x90, 0, 80]);
+        ssl.extend_from_slice(&[0x04, 0, 1, 0, PP2_TYPE_SSL, 0, 5, PP2_CLIENT_SSL, 0, 0, 0, 0]);
+        let Parsed::Header(header, len) = parse(&ssl).unwrap() else { panic!() };
+        assert_eq!(len, 40);
+        assert!(header.is_tls());
+
+        let mut local = V2_SIGNATURE.to_vec();
+        local.extend_from_slice(&[0x20, 0x00, 0, 0]);
+        let Parsed::Header(header, _) = parse(&local).unwrap() else { panic!() };
+        assert_eq!(header.source(), None);
+
+        assert!(matches!(parse(&V2_SIGNATURE[..8]).unwrap(), Parsed::Incomplete));
+    }
+
+    #[test]
+    fn optional_signature_prefix() {
+        async fn read_all(mut io: impl AsyncRead + Unpin) -> Vec<u8> {
+            let mut out = vec![];
+            io.read_to_end(&mut out).await.unwrap();
+            out
+        }
+
+        tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+            let proxy = ProxyProtocol::optional().timeout(Duration::from_millis(50));
+
+            // leading CRLF diverge from the v2 signature as soon as the request start
+            let (io, header) = proxy.accept(&b"\r\nGET / HTTP/1.1\r\n"[..]).await.unwrap();
+            assert!(header.is_none());
+            assert_eq!(read_all(io).await, b"\r\nGET / HTTP/1.1\r\n");
+
+            // client stalling after bytes that still match the signature is not held
+            let (mut client, server) = tokio::io::duplex(64);
+            tokio::io::AsyncWriteExt::write_all(&mut client, b"\r\n").await.unwrap();
+            let (io, header) = proxy.accept(server).await.unwrap();
+            assert!(header.is_none());
+            drop(client);
+            assert_eq!(read_all(io).await, b"\r\n");
+
+            // silent client is served as is once the timeout elapsed
+            let (mut client, server) = tokio::io::duplex(64);
+            let (io, header) = proxy.accept(server).await.unwrap();
+            assert!(header.is_none());
+            tokio::io::AsyncWriteExt::write_all(&mut client, b"GET").await.unwrap();
+            drop(client);
+            assert_eq!(read_all(io).await, b"GET");
+
+            let (_client, server) = tokio::io::duplex(64);
+            let strict = ProxyProtocol::strict().timeout(Duration::from_millis(50));
+            assert!(matches!(strict.accept(server).await, Err(ProxyError::Timeout)));
+        });
+    }
+}
+
+const V1_SIGNATURE: &[u8] = b"PROXY ";
+const V1_MAX_LEN: usize = 107;
+const V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+const V2_HEADER_LEN: usize = 16;
+/// v2 tlv type of ssl information
+const PP2_TYPE_SSL: u8 = 0x20;
+/// `PP2_TYPE_SSL` client flag, client connected over ssl/tls
+const PP2_CLIENT_SSL: u8 = 0x01;
+
+/// PROXY protocol configuration
+///
+/// # Example
+///
+/// ```no_run
+/// use vice::runtime::{Server, proxy::ProxyProtocol};
+/// use vice::router::Router;
+/// use std::time::Duration;
+///
+/// fn main() -> std::io::Result<()> {
+///     Server::bind("0.0.0.0:3000")?
+///         .proxy_protocol(ProxyProtocol::strict().timeout(Duration::from_secs(3)))
+///         .serve(Router::new())
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct ProxyProtocol {
+    mode: ProxyMode,
+    timeout: Duration,
+}
+
+/// how to treat connection without PROXY protocol header
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProxyMode {
+    /// connection without header is rejected
+    Strict,
+    /// connection without header is served as is
+    ///
+    /// any client that can connect directly can send its own header and spoof
+    /// its source address, only use this when every peer that can reach the
+    /// listener is a trusted balancer
+    Optional,
+}
+
+impl ProxyProtocol {
+    /// create new `ProxyProtocol` with given mode
+    pub fn new(mode: ProxyMode) -> Self {
+        Self { mode, timeout: Duration::from_secs(5) }
+    }
+
+    /// require every connection to send PROXY protocol header
+    pub fn strict() -> Self {
+        Self::new(ProxyMode::Strict)
+    }
+
+    /// accept connection with or without PROXY protocol header
+    ///
+    /// see [`ProxyMode::Optional`] for the security implication
+    pub fn optional() -> Self {
+        Self::new(ProxyMode::Optional)
+    }
+
+    /// maximum duration to wait for the header, default to 5 seconds
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// returns the configured mode
+    pub fn mode(&self) -> ProxyMode {
+        self.mode
+    }
+
+    /// read PROXY protocol header from the stream
+    ///
+    /// returns the stream with remaining bytes replayed,
+    /// and the header if any
+    pub async fn accept<IO>(&self, mut io: IO) -> Result<(Rewind<IO>, Option<ProxyHeader>), ProxyError>
+    where
+        IO: AsyncRead + Unpin,
+    {
+        let mut buf = BytesMut::with_capacity(V1_MAX_LEN);
+        let read = async {
+            loop {
+                match parse(&buf)? {
+                    Parsed::Incomplete => {}
+                    Parsed::NotProxy => return Ok(None),
+                    Parsed::Header(header, len) => {
+                        buf.advance(len);
+                        return Ok(Some(header));
+                    }
+                }
+                if io.read_buf(&mut buf).await? == 0 {
+                    return Err(ProxyError::Io(io::ErrorKind::UnexpectedEof.into()));
+                }
+            }
+        };
+
+        let header = match tokio::time::timeout(self.timeout, read).await {
+            Ok(header) => header?,
+            // client that stay silent, e.g. browser preconnect, or stall on bytes
+            // which happen to prefix the signature, e.g. a leading CRLF, is served as is
+            Err(_) if self.mode == ProxyMode::Optional => None,
+            Err(_) => return Err(ProxyError::Timeout),
+        };
+
+        if header.is_none() && self.mode == ProxyMode::Strict {
+            return Err(ProxyError::Missing);
+        }
+
+        Ok((Rewind::new(io, buf.freeze()), header))
+    }
+}
+
+/// parsed PROXY protocol header
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProxyHeader {
+    addrs: Option<(SocketAddr, SocketAddr)>,
+    tls: bool,
+}
+
+impl ProxyHeader {
+    /// the real client address
+    ///
+    /// returns `None` for `LOCAL` command or `UNKNOWN` protocol,
+    /// in which case the peer address should be used
+    pub fn source(&self) -> Option<SocketAddr> {
+        self.addrs.map(|e|e.0)
+    }
+
+    /// the original destination address
+    pub fn destination(&self) -> Option<SocketAddr> {
+        self.addrs.map(|e|e.1)
+    }
+
+    /// the client connected to the proxy over tls
+    ///
+    /// reported by v2 `PP2_TYPE_SSL` tlv, always `false` for v1
+    pub fn is_tls(&self) -> bool {
+        self.tls
+    }
+}
+
+/// error when reading PROXY protocol header
+#[derive(thiserror::Error, Debug)]
+pub enum ProxyError {
+    #[error("failed to read proxy header: {0}")]
+    Io(#[from] io::Error),
+    #[error("timeout reading proxy header")]
+    Timeout,
+    #[error("proxy header is required")]
+    Missing,
+    #[error("invalid proxy header: {0}")]
+    Invalid(&'static str),
+}
+
+/// result of parsing PROXY protocol header
+#[derive(Debug)]
+pub enum Parsed {
+    /// more bytes is required to decide
+    Incomplete,
+    /// stream does not start with PROXY protocol signature
+    NotProxy,
+    /// the header and its length in bytes
+    Header(ProxyHeader, usize),
+}
+
+/// parse PROXY protocol header from the start of buffer
+pub fn parse(buf: &[u8]) -> Result<Parsed, ProxyError> {
+    if starts_with(buf, V1_SIGNATURE) {
+        if buf.len() < V1_SIGNATURE.len() {
+            return Ok(Parsed::Incomplete);
+        }
+        parse_v1(buf)
+    } else if starts_with(buf, V2_SIGNATURE) {
+        if buf.len() < V2_HEADER_LEN {
+            return Ok(Parsed::Incomplete);
+        }
+        parse_v2(buf)
+    } else {
+        Ok(Parsed::NotProxy)
+    }
+}
+
+/// check that either one is the prefix of the other
+fn starts_with(buf: &[u8], signature: &[u8]) -> bool {
+    let len = buf.len().min(signature.len());
+    buf[..len] == signature[..len]
+}
+
+fn parse_v1(buf: &[u8]) -> Result<Parsed, ProxyError> {
+    let scan = &buf[..buf.len().min(V1_MAX_LEN)];
+    let Some(end) = scan.windows(2).position(|e|e == b"\r\n") else {
+        return match buf.len() < V1_MAX_LEN {
+            true => Ok(Parsed::Incomplete),
+            false => Err(ProxyError::Invalid("v1 header too long")),
+        };
+    };
+
+    let line = std::str::from_utf8(&buf[..end]).map_err(|_|ProxyError::Invalid("v1 header is not ascii"))?;
+    let mut parts = line.split(' ').skip(1);
+
+    let addrs = match parts.next() {
+        Some("UNKNOWN") => None,
+        Some("TCP4") => Some(parse_v1_addrs::<Ipv4Addr>(&mut parts)?),
+        Some("TCP6") => Some(parse_v1_addrs::<Ipv6Addr>(&mut parts)?),
+        _ => return Err(ProxyError::Invalid("v1 unknown protocol")),
+    };
+
+    Ok(Parsed::Header(ProxyHeader { addrs, tls: false }, end + 2))
+}
+
+fn parse_v1_addrs<'a, Ip>(parts: &mut impl Iterator<Item = &'a str>) -> Result<(SocketAddr, SocketAddr), ProxyError>
+where
+    Ip: FromStr + Into<IpAddr>,
+{
+    let mut next = ||parts.next().ok_or(ProxyError::Invalid("v1 missing address"));
+    let src = next()?.parse::<Ip>().map_err(|_|ProxyError::Invalid("v1 invalid source address"))?;
+    let dst = next()?.parse::<Ip>().map_err(|_|ProxyError::Invalid("v1 invalid destination address"))?;
+    let sport = next()?.parse::<u16>().map_err(|_|ProxyError::Invalid("v1 invalid source port"))?;
+    let dport = next()?.parse::<u16>().map_err(|_|ProxyError::Invalid("v1 invalid destination port"))?;
+    if parts.next().is_some() {
+        return Err(ProxyError::Invalid("v1 trailing data"));
+    }
+    Ok((SocketAddr::new(src.into(), sport), SocketAddr::new(dst.into(), dport)))
+}
+
+fn parse_v2(buf: &[u8]) -> Result<Parsed, ProxyError> {
+    let ver_cmd = buf[12];
+    let family = buf[13];
+    let len = V2_HEADER_LEN + u16::from_be_bytes([buf[14], buf[15]]) as usize;
+
+    if ver_cmd >> 4 != 2 {
+        return Err(ProxyError::Invalid("v2 unsupported version"));
+    }
+    if buf.len() < len {
+        return Ok(Parsed::Incomplete);
+    }
+
+    let data = &buf[V2_HEADER_LEN..len];
+    let addrs = match (ver_cmd & 0x0f, family >> 4) {
+        // LOCAL command, connection established by the proxy itself
+        (0x0, _) => None,
+        // PROXY command with AF_INET
+        (0x1, 0x1) => {
+            if data.len() < 12 {
+                return Err(ProxyError::Invalid("v2 address too short"));
+            }
+            let src = Ipv4Addr::from(<[u8;4]>::try_from(&data[0..4]).unwrap());
+            let dst = Ipv4Addr::from(<[u8;4]>::try_from(&data[4..8]).unwrap());
+            let sport = u16::from_be_bytes([data[8], data[9]]);
+            let dport = u16::from_be_bytes([data[10], data[11]]);
+            Some((SocketAddr::new(src.into(), sport), SocketAddr::new(dst.into(), dport)))
+        }
+        // PROXY command with AF_INET6
+        (0x1, 0x2) => {
+            if data.len() < 36 {
+                return Err(ProxyError::Invalid("v2 address too short"));
+            }
+            let src = Ipv6Addr::from(<[u8;16]>::try_from(&data[0..16]).unwrap());
+            let dst = Ipv6Addr::from(<[u8;16]>::try_from(&data[16..32]).unwrap());
+            let sport = u16::from_be_bytes([data[32], data[33]]);
+            let dport = u16::from_be_bytes([data[34], data[35]]);
+            Some((SocketAddr::new(src.into(), sport), SocketAddr::new(dst.into(), dport)))
+        }
+        // PROXY command with AF_UNSPEC or AF_UNIX
+        (0x1, _) => None,
+        _ => return Err(ProxyError::Invalid("v2 unknown command")),
+    };
+
+    let tls = match (ver_cmd & 0x0f, family >> 4) {
+        (0x1, 0x1) => v2_tls(&data[12..]),
+        (0x1, 0x2) => v2_tls(&data[36..]),
+        (0x1, 0x3) => v2_tls(data.get(216..).unwrap_or_default()),
+        (0x1, _) => v2_tls(data),
+        _ => false,
+    };
+    Ok(Parsed::Header(ProxyHeader { addrs, tls }, len))
+}
+
+/// returns `true` if `PP2_TYPE_SSL` tlv report ssl/tls client connection
+fn v2_tls(mut tlvs: &[u8]) -> bool {
+    while let [kind, hi, lo, rest @ ..] = tlvs {
+        let Some(value) = rest.get(..u16::from_be_bytes([*hi, *lo]) as usize) else {
+            return false;
+        };
+        if *kind == PP2_TYPE_SSL {
+            return value.first().is_some_and(|e|e & PP2_CLIENT_SSL != 0);
+        }
+        tlvs = &rest[value.len()..];
+    }
+    false
+}
+
+pin_project_lite::pin_project! {
+    /// io that replay buffered bytes before reading from inner io
+    pub struct Rewind<IO> {
+        prefix: Bytes,
+        #[pin]
+        inner: IO,
+    }
+}
+
+impl<IO> Rewind<IO> {
+    /// create new `Rewind`
+    pub fn new(inner: IO, prefix: Bytes) -> Self {
+        Self { prefix, inner }
+    }
+
+    /// returns the inner io
+    pub fn get_ref(&self) -> &IO {
+        &self.inner
+    }
+}
+
+impl<IO> AsyncRead for Rewind<IO>
+where
+    IO: AsyncRead,
+{
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let me = self.project();
+        if !me.prefix.is_empty() {
+            let len = me.prefix.len().min(buf.remaining());
+            buf.put_slice(&me.prefix.split_to(len));
+            return Poll::Ready(Ok(()));
+        }
+        me.inner.poll_read(cx, buf)
+    }
+}
+
+impl<IO> AsyncWrite for Rewind<IO>
+where
+    IO: AsyncWrite,
+{
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        self.project().inner.poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().inner.poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(self: Pin<&mut Self>, cx: &mut Context<'_>, bufs: &[io::IoSlice<'_>]) -> Poll<io::Result<usize>> {
+        self.project().inner.poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+}
@@ -0,0 +1,298 @@
+//! [`tower`] interoperability
+//!
+//! vice service use [`hyper::service::Service`] which take `&self` and have no
+//! `poll_ready`, while most of the ecosystem middleware is built on [`tower::Service`]
+//!
+//! - [`IntoTower`] turn vice service into tower service, so vice router can be
+//!   mounted inside tower based stack
+//! - [`FromTower`] turn tower service into vice service
+//! - [`TowerLayer`] turn tower layer into vice [`Layer`], so it can be used in
+//!   [`Router::layer`]
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use tower::timeout::TimeoutLayer;
+//! use vice::{router::{Router, get}, tower::TowerLayer};
+//!
+//! let route = Router::new()
+//!     .route("/", get(||async { "Vice Dev" }))
+//!     .layer(TowerLayer::new(TimeoutLayer::new(Duration::from_secs(10))));
+//! ```
+//!
+//! [`tower`]: https://docs.rs/tower
+//! [`tower::Service`]: tower_service::Service
+//! [`Router::layer`]: crate::router::Router::layer
+use crate::{
+    http::{IntoResponse, Response},
+    middleware::Layer,
+};
+use http::StatusCode;
+use std::{
+    convert::Infallible,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+use tower_service::Service as TowerService;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        http::Request,
+        middleware::security_headers::SecurityHeaders,
+        router::get,
+    };
+    use http::header;
+    use hyper::service::Service;
+    use std::time::Duration;
+    use tower::{buffer::BufferLayer, limit::RateLimitLayer, timeout::TimeoutLayer, ServiceBuilder};
+
+    fn block_on<F: Future>(f: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(f)
+    }
+
+    #[test]
+    fn tower_layer() {
+        let layer = TowerLayer::new(TimeoutLayer::new(Duration::from_millis(20)));
+        let ok = layer.layer(get(||async { "Vice Dev" }));
+        let hang = layer.layer(get(std::future::pending::<&'static str>));
+
+        let res = block_on(ok.call(Request::new(Default::default()))).unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.body().as_bytes(), Some(&b"Vice Dev"[..]));
+
+        // tower timeout error is responded with 500
+        let res = block_on(hang.call(Request::new(Default::default()))).unwrap();
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[test]
+    fn service_builder() {
+        // vice handler into tower stack with vice layer, then back into vice service
+        let tower = ServiceBuilder::new()
+            .layer(TimeoutLayer::new(Duration::from_secs(1)))
+            .layer(ViceLayer::new(SecurityHeaders::new()))
+            .service(IntoTower::new(get(||async { "Vice Dev" })));
+        let service = FromTower::new(tower);
+
+        let res = block_on(service.call(Request::new(Default::default()))).unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+        assert_eq!(res.body().as_bytes(), Some(&b"Vice Dev"[..]));
+    }
+
+    #[test]
+    fn buffered_rate_limit() {
+        block_on(async {
+            let limit = ServiceBuilder::new()
+                .layer(BufferLayer::new(8))
+                .layer(RateLimitLayer::new(1, Duration::from_secs(60)));
+            let route = crate::router::Router::new().route("/", get(||async { "Vice Dev" }));
+            let service = TowerLayer::new(limit).layer(route);
+
+            let res = service.call(Request::new(Default::default())).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+
+            // rate limit state is shared, so the second request wait for the next period
+            let second = tokio::time::timeout(Duration::from_millis(100), service.call(Request::new(Default::default())));
+            assert!(second.await.is_err());
+        });
+    }
+}
+
+/// boxed error that tower middleware typically returns
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// turn vice service into tower service
+///
+/// the service is always ready
+#[derive(Clone, Debug)]
+pub struct IntoTower<S> {
+    inner: S,
+}
+
+impl<S> IntoTower<S> {
+    /// create new `IntoTower`
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+
+    /// returns the inner service
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S,R> TowerService<R> for IntoTower<S>
+where
+    S: hyper::service::Service<R>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: R) -> Self::Future {
+        self.inner.call(req)
+    }
+}
+
+/// turn tower service into vice service
+///
+/// the service is cloned for every request, then wait until it is ready before
+/// calling it, error returned by the service is logged and responded with
+/// `500 Internal Server Error`
+///
+/// because of the clone, middleware that keep state per service instance,
+/// like tower `RateLimit`, would not share it between requests, and some of
+/// them is not `Clone` at all, put `tower::buffer::BufferLayer` in front of
+/// such middleware so every request goes through a single instance
+///
+/// ```
+/// use std::time::Duration;
+/// use tower::{ServiceBuilder, buffer::BufferLayer, limit::RateLimitLayer};
+/// use vice::{router::{Router, get}, tower::TowerLayer};
+///
+/// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+/// // `Buffer` spawn its worker, so the layer must be applied within tokio runtime
+/// let limit = ServiceBuilder::new()
+///     .layer(BufferLayer::new(1024))
+///     .layer(RateLimitLayer::new(100, Duration::from_secs(1)));
+/// let route = Router::new()
+///     .route("/", get(||async { "Vice Dev" }))
+///     .layer(TowerLayer::new(limit));
+/// # });
+/// ```
+#[derive(Clone, Debug)]
+pub struct FromTower<S> {
+    inner: S,
+}
+
+impl<S> FromTower<S> {
+    /// create new `FromTower`
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+
+    /// returns the inner service
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S,R> hyper::service::Service<R> for FromTower<S>
+where
+    S: TowerService<R, Response = Response> + Clone,
+    S::Error: Into<BoxError>,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = FromTowerFuture<S,R>;
+
+    fn call(&self, req: R) -> Self::Future {
+        FromTowerFuture::Ready { inner: self.inner.clone(), req: Some(req) }
+    }
+}
+
+pin_project_lite::pin_project! {
+    /// future returned from [`FromTower`]
+    #[project = FromTowerProj]
+    pub enum FromTowerFuture<S,R>
+    where
+        S: TowerService<R>,
+    {
+        Ready { inner: S, req: Option<R> },
+        Call { #[pin] f: S::Future },
+    }
+}
+
+impl<S,R> Future for FromTowerFuture<S,R>
+where
+    S: TowerService<R, Response = Response>,
+    S::Error: Into<BoxError>,
+{
+    type Output = Result<Response,Infallible>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        loop {
+            match self.as_mut().project() {
+                FromTowerProj::Ready { inner, req } => match ready!(inner.poll_ready(cx)) {
+                    Ok(()) => {
+                        let f = inner.call(req.take().expect("poll after complete"));
+                        self.set(FromTowerFuture::Call { f });
+                    }
+                    Err(err) => return Poll::Ready(Ok(error_response(err))),
+                },
+                FromTowerProj::Call { f } => return match ready!(f.poll(cx)) {
+                    Ok(res) => Poll::Ready(Ok(res)),
+                    Err(err) => Poll::Ready(Ok(error_response(err))),
+                },
+            }
+        }
+    }
+}
+
+fn error_response(err: impl Into<BoxError>) -> Response {
+    log::error!("{}", err.into());
+    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+}
+
+/// turn tower layer into vice [`Layer`]
+///
+/// the inner service is wrapped with [`IntoTower`] before given to the tower layer,
+/// and the resulting tower service is wrapped with [`FromTower`], see its
+/// documentation for layers that need `tower::buffer::BufferLayer`
+#[derive(Clone, Debug)]
+pub struct TowerLayer<L> {
+    inner: L,
+}
+
+impl<L> TowerLayer<L> {
+    /// create new `TowerLayer`
+    pub fn new(inner: L) -> Self {
+        Self { inner }
+    }
+}
+
+impl<L,S> Layer<S> for TowerLayer<L>
+where
+    L: tower_layer::Layer<IntoTower<S>>,
+{
+    type Service = FromTower<L::Service>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        FromTower::new(self.inner.layer(IntoTower::new(inner)))
+    }
+}
+
+/// turn vice [`Layer`] into tower layer
+///
+/// the inner tower service is wrapped with [`FromTower`] before given to the vice layer,
+/// and the resulting vice service is wrapped with [`IntoTower`]
+#[derive(Clone, Debug)]
+pub struct ViceLayer<L> {
+    inner: L,
+}
+
+impl<L> ViceLayer<L> {
+    /// create new `ViceLayer`
+    pub fn new(inner: L) -> Self {
+        Self { inner }
+    }
+}
+
+impl<L,S> tower_layer::Layer<S> for ViceLayer<L>
+where
+    L: Layer<FromTower<S>>,
+{
+    type Service = IntoTower<L::Service>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        IntoTower::new(self.inner.layer(FromTower::new(inner)))
+    }
+}
@@ -1,7 +1,10 @@
 //! utility types
+pub mod base64;
 pub mod futures;
+pub mod random;
 pub mod response;
 pub mod service;
+pub mod time;
 
 use std::marker::PhantomData;
 use futures::EitherInto;
@@ -0,0 +1,82 @@
+//! base64 encoding
+//!
+//! standard alphabet with padding, and url safe alphabet without padding
+#[cfg(test)]
+mod test {
+    #[test]
+    fn roundtrip() {
+        assert_eq!(super::encode(b"Aladdin:open sesame"), "QWxhZGRpbjpvcGVuIHNlc2FtZQ==");
+        assert_eq!(super::decode("QWxhZGRpbjpvcGVuIHNlc2FtZQ==").unwrap(), b"Aladdin:open sesame");
+        assert_eq!(super::encode_url(&[0xfb, 0xff]), "-_8");
+        assert_eq!(super::decode_url("-_8").unwrap(), [0xfb, 0xff]);
+        for len in 0..8 {
+            let data = (0..len).collect::<Vec<u8>>();
+            assert_eq!(super::decode(&super::encode(&data)).unwrap(), data);
+        }
+        assert!(super::decode("a").is_none());
+        assert!(super::decode("a*==").is_none());
+    }
+}
+
+const STANDARD: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const URL_SAFE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// encode using standard alphabet with padding
+pub fn encode(data: &[u8]) -> String {
+    encode_with(data, STANDARD, true)
+}
+
+/// decode standard alphabet, padding is optional
+pub fn decode(data: &str) -> Option<Vec<u8>> {
+    decode_with(data, STANDARD)
+}
+
+/// encode using url safe alphabet without padding
+pub fn encode_url(data: &[u8]) -> String {
+    encode_with(data, URL_SAFE, false)
+}
+
+/// decode url safe alphabet, padding is optional
+pub fn decode_url(data: &str) -> Option<Vec<u8>> {
+    decode_with(data, URL_SAFE)
+}
+
+fn encode_with(data: &[u8], alphabet: &[u8; 64], pad: bool) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let n = match chunk {
+            [a, b, c] => u32::from_be_bytes([0, *a, *b, *c]),
+            [a, b] => u32::from_be_bytes([0, *a, *b, 0]),
+            [a] => u32::from_be_bytes([0, *a, 0, 0]),
+            _ => unreachable!(),
+        };
+        let len = chunk.len() + 1;
+        for i in 0..4 {
+            if i < len {
+                out.push(alphabet[(n >> (18 - i * 6)) as usize & 0x3f] as char);
+            } else if pad {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn decode_with(data: &str, alphabet: &[u8; 64]) -> Option<Vec<u8>> {
+    let data = data.trim_end_matches('=').as_bytes();
+    if data.len() % 4 == 1 {
+        return None;
+    }
+
+    let mut out = Vec::with_capacity(data.len() * 3 / 4);
+    for chunk in data.chunks(4) {
+        let mut n = 0u32;
+        for (i,byte) in chunk.iter().enumerate() {
+            let value = alphabet.iter().position(|e|e == byte)? as u32;
+            n |= value << (18 - i * 6);
+        }
+        let bytes = n.to_be_bytes();
+        out.extend_from_slice(&bytes[1..chunk.len()]);
+    }
+    Some(out)
+}
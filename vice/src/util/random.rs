@@ -0,0 +1,80 @@
+//! random identifier utility
+//!
+//! every function use the operating system cryptographically secure random source
+use std::{
+    fmt::Write,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn format() {
+        let uuid = uuid();
+        assert_eq!(uuid.len(), 36);
+        assert_eq!(&uuid[14..15], "4");
+        assert!(uuid.split('-').map(str::len).eq([8, 4, 4, 4, 12]));
+
+        let (a, b) = (ulid(), ulid());
+        assert_eq!(a.len(), 26);
+        assert_ne!(a, b);
+        assert!(a.bytes().all(|e|CROCKFORD.contains(&e)));
+    }
+}
+
+const CROCKFORD: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// returns random bytes
+///
+/// # Panics
+///
+/// panics if the operating system random source is unavailable
+pub fn bytes<const N: usize>() -> [u8; N] {
+    let mut buf = [0u8; N];
+    getrandom::fill(&mut buf).expect("operating system random source is unavailable");
+    buf
+}
+
+/// returns random version 4 uuid in hyphenated lowercase format
+pub fn uuid() -> String {
+    let mut buf = bytes::<16>();
+    buf[6] = (buf[6] & 0x0f) | 0x40;
+    buf[8] = (buf[8] & 0x3f) | 0x80;
+
+    let mut out = String::with_capacity(36);
+    for (i,byte) in buf.iter().enumerate() {
+        if matches!(i, 4 | 6 | 8 | 10) {
+            out.push('-');
+        }
+        let _ = write!(out, "{byte:02x}");
+    }
+    out
+}
+
+/// returns random [ulid](https://github.com/ulid/spec)
+pub fn ulid() -> String {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |e|e.as_millis());
+    let random = u128::from_be_bytes(bytes::<16>()) >> 48;
+    let value = (millis & ((1 << 48) - 1)) << 80 | random;
+
+    (0..26)
+        .rev()
+        .map(|i|CROCKFORD[((value >> (i * 5)) & 0x1f) as usize] as char)
+        .collect()
+}
+
+/// returns random token of given bytes length in lowercase hex
+pub fn hex_token(len: usize) -> String {
+    let mut buf = vec![0u8; len];
+    getrandom::fill(&mut buf).expect("operating system random source is unavailable");
+
+    let mut out = String::with_capacity(len * 2);
+    for byte in buf {
+        let _ = write!(out, "{byte:02x}");
+    }
+    out
+}
@@ -23,6 +23,8 @@ where
     E: std::fmt::Display
 {
     fn into_response(self) -> crate::http::Response {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(error = %self.0, "bad request");
         (http::StatusCode::BAD_REQUEST, self.0.to_string()).into_response()
     }
 }
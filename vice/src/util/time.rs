@@ -0,0 +1,164 @@
+//! time formatting and parsing utility
+use std::{
+    fmt,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn http_date() {
+        let time = UtcTime::from_http_date("Tue, 10 Oct 2000 13:55:36 GMT").unwrap();
+        assert_eq!(time.unix(), 971186136);
+        assert_eq!(time.http_date().to_string(), "Tue, 10 Oct 2000 13:55:36 GMT");
+        assert!(UtcTime::from_http_date("Thu, 31 Feb 2000 00:00:00 GMT").is_none());
+        assert!(UtcTime::from_http_date("Thu, 01 Jan 99999999999999 00:00:00 GMT").is_none());
+        assert!(UtcTime::from_http_date("Thu, 01 Jan -99999999999999 00:00:00 GMT").is_none());
+        assert!(UtcTime::from_http_date("Fri, 31 Dec 9999 23:59:59 GMT").is_some());
+    }
+}
+
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+
+/// broken down utc time
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UtcTime {
+    pub year: i64,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    /// days since thursday, 1 January 1970
+    days: i64,
+}
+
+impl UtcTime {
+    /// current time
+    pub fn now() -> Self {
+        Self::from(SystemTime::now())
+    }
+
+    /// create `UtcTime` from seconds since unix epoch
+    pub fn from_unix(secs: i64) -> Self {
+        let days = secs.div_euclid(86400);
+        let rem = secs.rem_euclid(86400);
+
+        // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z.rem_euclid(146097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+        let year = yoe + era * 400 + (month <= 2) as i64;
+
+        Self {
+            year,
+            month,
+            day,
+            hour: (rem / 3600) as u8,
+            minute: (rem % 3600 / 60) as u8,
+            second: (rem % 60) as u8,
+            days,
+        }
+    }
+
+    /// parse http date, `Tue, 10 Oct 2000 13:55:36 GMT`
+    ///
+    /// only the preferred IMF-fixdate format with year 1 to 9999 is supported
+    pub fn from_http_date(value: &str) -> Option<Self> {
+        let mut parts = value.split_ascii_whitespace();
+        let (_, day, month, year, time) = (parts.next()?, parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+        if parts.next()? != "GMT" || parts.next().is_some() {
+            return None;
+        }
+
+        let day = day.parse::<i64>().ok()?;
+        let month = MONTHS.iter().position(|e|*e == month)? as i64 + 1;
+        let year = year.parse::<i64>().ok()?;
+        let mut time = time.splitn(3, ':').map(|e|e.parse::<i64>().ok());
+        let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+        // bound the year so the day arithmetic below cannot overflow
+        if !(1..=9999).contains(&year) || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 59 {
+            return None;
+        }
+
+        // http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+        let y = if month <= 2 { year - 1 } else { year };
+        let era = y.div_euclid(400);
+        let yoe = y - era * 400;
+        let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = era * 146097 + doe - 719468;
+
+        let time = Self::from_unix(days * 86400 + hour * 3600 + minute * 60 + second);
+        // reject day that overflow the month, e.g. 31 Feb
+        (time.day as i64 == day).then_some(time)
+    }
+
+    /// seconds since unix epoch
+    pub fn unix(&self) -> i64 {
+        self.days * 86400 + self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64
+    }
+
+    /// format as common log format, `10/Oct/2000:13:55:36 +0000`
+    pub fn clf(&self) -> impl fmt::Display + '_ {
+        Fmt(move |f: &mut fmt::Formatter| write!(
+            f, "{:02}/{}/{}:{:02}:{:02}:{:02} +0000",
+            self.day, MONTHS[self.month as usize - 1], self.year, self.hour, self.minute, self.second,
+        ))
+    }
+
+    /// format as rfc 3339, `2000-10-10T13:55:36Z`
+    pub fn rfc3339(&self) -> impl fmt::Display + '_ {
+        Fmt(move |f: &mut fmt::Formatter| write!(
+            f, "{}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second,
+        ))
+    }
+
+    /// format as http date, `Tue, 10 Oct 2000 13:55:36 GMT`
+    pub fn http_date(&self) -> impl fmt::Display + '_ {
+        Fmt(move |f: &mut fmt::Formatter| write!(
+            f, "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+            WEEKDAYS[self.days.rem_euclid(7) as usize], self.day, MONTHS[self.month as usize - 1],
+            self.year, self.hour, self.minute, self.second,
+        ))
+    }
+}
+
+impl From<SystemTime> for UtcTime {
+    fn from(value: SystemTime) -> Self {
+        let secs = match value.duration_since(UNIX_EPOCH) {
+            Ok(dur) => dur.as_secs() as i64,
+            Err(err) => -(err.duration().as_secs() as i64),
+        };
+        Self::from_unix(secs)
+    }
+}
+
+impl From<UtcTime> for SystemTime {
+    fn from(value: UtcTime) -> Self {
+        match u64::try_from(value.unix()) {
+            Ok(secs) => UNIX_EPOCH + Duration::from_secs(secs),
+            Err(_) => UNIX_EPOCH - Duration::from_secs(value.unix().unsigned_abs()),
+        }
+    }
+}
+
+struct Fmt<F>(F);
+
+impl<F> fmt::Display for Fmt<F>
+where
+    F: Fn(&mut fmt::Formatter) -> fmt::Result,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (self.0)(f)
+    }
+}